@@ -1,3 +1,7 @@
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use std::convert::Infallible;
+use std::net::SocketAddr;
 use tower_sessions::Session;
 
 use crate::error::{MonitorError, Result};
@@ -5,6 +9,22 @@ use crate::error::{MonitorError, Result};
 /// Session key for storing authentication status
 pub const SESSION_USER_KEY: &str = "authenticated";
 
+/// Who triggered an operation, as recorded in the history log
+#[derive(Debug, Clone)]
+pub struct Actor(pub String);
+
+#[axum::async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> std::result::Result<Self, Self::Rejection> {
+        let actor = match parts.extensions.get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("dashboard ({})", addr.ip()),
+            None => "dashboard".to_string(),
+        };
+        Ok(Actor(actor))
+    }
+}
 
 
 /// Hash a plaintext password using bcrypt
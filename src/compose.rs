@@ -0,0 +1,292 @@
+use crate::error::{MonitorError, Result};
+use bollard::models::ContainerSummary;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+/// Label compose puts on every container with the project name
+pub const PROJECT_LABEL: &str = "com.docker.compose.project";
+/// Label with the service a container was created for
+pub const SERVICE_LABEL: &str = "com.docker.compose.service";
+/// Label with the replica number of a container within its service
+const NUMBER_LABEL: &str = "com.docker.compose.container-number";
+/// Label with the comma-separated compose files a container was created from
+const CONFIG_FILES_LABEL: &str = "com.docker.compose.project.config_files";
+
+/// Services of a compose project
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ComposeProject {
+    /// Project name, `None` until it is known
+    pub name: Option<String>,
+    /// Services in name order
+    pub services: Vec<ComposeService>,
+}
+
+/// A service as declared in the compose file
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComposeService {
+    pub name: String,
+    /// Fixed `container_name`, if the service sets one
+    pub container_name: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ConfigOutput {
+    name: Option<String>,
+    #[serde(default)]
+    services: BTreeMap<String, ServiceOutput>,
+}
+
+#[derive(Deserialize)]
+struct ServiceOutput {
+    container_name: Option<String>,
+}
+
+impl ComposeProject {
+    /// Parses the output of `docker compose config --format json`
+    pub fn from_config(json: &str) -> Result<Self> {
+        let config: ConfigOutput = serde_json::from_str(json)
+            .map_err(|e| MonitorError::Docker(format!("Failed to parse docker compose config: {}", e)))?;
+        Ok(Self {
+            name: config.name,
+            services: config
+                .services
+                .into_iter()
+                .map(|(name, service)| ComposeService {
+                    name,
+                    container_name: service.container_name,
+                })
+                .collect(),
+        })
+    }
+
+    /// Recovers the project from the labels of containers created from `compose_file`
+    pub fn from_labels(containers: &[ContainerSummary], compose_file: &Path) -> Option<Self> {
+        let compose_file = compose_file.canonicalize().unwrap_or_else(|_| compose_file.to_path_buf());
+
+        let mut name = None;
+        let mut services = BTreeMap::new();
+        for container in containers {
+            let Some(labels) = &container.labels else { continue };
+            let from_file = labels
+                .get(CONFIG_FILES_LABEL)
+                .is_some_and(|files| files.split(',').any(|file| Path::new(file) == compose_file));
+            if !from_file {
+                continue;
+            }
+            if let (Some(project), Some(service)) = (labels.get(PROJECT_LABEL), labels.get(SERVICE_LABEL)) {
+                name = Some(project.clone());
+                services.insert(service.clone(), None);
+            }
+        }
+
+        name.map(|name| Self {
+            name: Some(name),
+            services: services
+                .into_iter()
+                .map(|(name, container_name)| ComposeService { name, container_name })
+                .collect(),
+        })
+    }
+
+    /// Looks up a service by its name or its fixed container name
+    pub fn find(&self, name: &str) -> Option<&ComposeService> {
+        self.services
+            .iter()
+            .find(|service| service.name == name)
+            .or_else(|| {
+                self.services
+                    .iter()
+                    .find(|service| service.container_name.as_deref() == Some(name))
+            })
+    }
+}
+
+/// Groups containers of a compose project by service, replicas in order
+pub fn containers_by_service(containers: &[ContainerSummary]) -> HashMap<String, Vec<String>> {
+    let mut replicas: HashMap<String, Vec<(u32, String)>> = HashMap::new();
+    for container in containers {
+        let Some(service) = container.labels.as_ref().and_then(|labels| labels.get(SERVICE_LABEL)) else {
+            continue;
+        };
+        let Some(name) = container.names.as_ref().and_then(|names| names.first()) else {
+            continue;
+        };
+        let number = container
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(NUMBER_LABEL))
+            .and_then(|number| number.parse().ok())
+            .unwrap_or(0);
+        replicas
+            .entry(service.clone())
+            .or_default()
+            .push((number, name.trim_start_matches('/').to_string()));
+    }
+
+    replicas
+        .into_iter()
+        .map(|(service, mut containers)| {
+            containers.sort();
+            (service, containers.into_iter().map(|(_, name)| name).collect())
+        })
+        .collect()
+}
+
+/// Which of the discovered services are managed
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServiceFilter {
+    All,
+    /// Only these services
+    Only(Vec<String>),
+    /// Every service except these
+    Except(Vec<String>),
+}
+
+impl ServiceFilter {
+    /// Builds the filter from an allow list and a deny list, at most one of which may be set
+    pub fn new(only: Vec<String>, except: Vec<String>) -> Self {
+        if !only.is_empty() {
+            ServiceFilter::Only(only)
+        } else if !except.is_empty() {
+            ServiceFilter::Except(except)
+        } else {
+            ServiceFilter::All
+        }
+    }
+
+    /// Whether `service` passes the filter; entries may also name its fixed container name
+    pub fn matches(&self, service: &ComposeService) -> bool {
+        let listed = |names: &[String]| {
+            names
+                .iter()
+                .any(|name| *name == service.name || Some(name.as_str()) == service.container_name.as_deref())
+        };
+        match self {
+            ServiceFilter::All => true,
+            ServiceFilter::Only(names) => listed(names),
+            ServiceFilter::Except(names) => !listed(names),
+        }
+    }
+
+    /// Entries that name neither a service nor a container name of `project`
+    pub fn unknown<'a>(&'a self, project: &ComposeProject) -> Vec<&'a str> {
+        match self {
+            ServiceFilter::All => Vec::new(),
+            ServiceFilter::Only(names) | ServiceFilter::Except(names) => names
+                .iter()
+                .filter(|name| project.find(name).is_none())
+                .map(String::as_str)
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn container(name: &str, labels: &[(&str, &str)]) -> ContainerSummary {
+        ContainerSummary {
+            names: Some(vec![format!("/{}", name)]),
+            labels: Some(
+                labels
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_compose_project_from_config() {
+        let project = ComposeProject::from_config(
+            r#"{"name": "shop", "services": {
+                "web": {"image": "nginx", "container_name": "shop-frontend"},
+                "db": {"image": "postgres"}
+            }, "networks": {}}"#,
+        )
+        .unwrap();
+
+        assert_eq!(project.name.as_deref(), Some("shop"));
+        let names: Vec<&str> = project.services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["db", "web"]);
+        assert_eq!(project.find("shop-frontend").unwrap().name, "web");
+        assert_eq!(project.find("db").unwrap().container_name, None);
+        assert!(project.find("cache").is_none());
+
+        assert!(ComposeProject::from_config("not json").is_err());
+    }
+
+    #[test]
+    fn test_compose_project_from_labels() {
+        let file = "/srv/shop/docker-compose.yml";
+        let other = "/srv/blog/docker-compose.yml";
+        let containers = vec![
+            container("shop-web-1", &[(PROJECT_LABEL, "shop"), (SERVICE_LABEL, "web"), (CONFIG_FILES_LABEL, file)]),
+            container(
+                "shop-worker-1",
+                &[(PROJECT_LABEL, "shop"), (SERVICE_LABEL, "worker"), (CONFIG_FILES_LABEL, &format!("{},/srv/shop/override.yml", file))],
+            ),
+            container("blog-web-1", &[(PROJECT_LABEL, "blog"), (SERVICE_LABEL, "web"), (CONFIG_FILES_LABEL, other)]),
+            container("standalone", &[]),
+        ];
+
+        let project = ComposeProject::from_labels(&containers, Path::new(file)).unwrap();
+        assert_eq!(project.name.as_deref(), Some("shop"));
+        let names: Vec<&str> = project.services.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["web", "worker"]);
+
+        assert_eq!(ComposeProject::from_labels(&containers, Path::new("/srv/none.yml")), None);
+    }
+
+    #[test]
+    fn test_containers_by_service() {
+        let containers = vec![
+            container("shop-web-2", &[(SERVICE_LABEL, "web"), (NUMBER_LABEL, "2")]),
+            container("shop-web-10", &[(SERVICE_LABEL, "web"), (NUMBER_LABEL, "10")]),
+            container("shop-web-1", &[(SERVICE_LABEL, "web"), (NUMBER_LABEL, "1")]),
+            container("shop-frontend", &[(SERVICE_LABEL, "frontend")]),
+            container("standalone", &[]),
+        ];
+
+        let services = containers_by_service(&containers);
+        assert_eq!(services.len(), 2);
+        assert_eq!(services["web"], vec!["shop-web-1", "shop-web-2", "shop-web-10"]);
+        assert_eq!(services["frontend"], vec!["shop-frontend"]);
+    }
+
+    #[test]
+    fn test_service_filter() {
+        let project = ComposeProject {
+            name: Some("shop".to_string()),
+            services: vec![
+                ComposeService {
+                    name: "db".to_string(),
+                    container_name: None,
+                },
+                ComposeService {
+                    name: "web".to_string(),
+                    container_name: Some("shop-frontend".to_string()),
+                },
+            ],
+        };
+        let [db, web] = [&project.services[0], &project.services[1]];
+
+        let all = ServiceFilter::new(Vec::new(), Vec::new());
+        assert_eq!(all, ServiceFilter::All);
+        assert!(all.matches(db) && all.matches(web));
+
+        // Container names select their service
+        let only = ServiceFilter::new(vec!["shop-frontend".to_string(), "cache".to_string()], Vec::new());
+        assert!(!only.matches(db));
+        assert!(only.matches(web));
+        assert_eq!(only.unknown(&project), vec!["cache"]);
+
+        let except = ServiceFilter::new(Vec::new(), vec!["db".to_string()]);
+        assert!(!except.matches(db));
+        assert!(except.matches(web));
+        assert!(except.unknown(&project).is_empty());
+    }
+}
@@ -1,13 +1,31 @@
 use serde::{Deserialize, Serialize};
+use crate::docker_host::DockerHost;
 use crate::error::{MonitorError, Result};
+use crate::git::{GitBackendKind, PullStrategy, VerifyMode};
+use std::collections::HashMap;
 use std::env;
+use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
     pub server: ServerConfig,
     pub auth: AuthenticationConfig,
+    /// Managed projects, each a repository with its own compose project
+    pub projects: Vec<ProjectConfig>,
+    pub storage: StorageConfig,
+}
+
+/// A repository and the compose project deployed from it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectConfig {
+    /// Name used in URLs and on the dashboard
+    pub name: String,
     pub git: GitConfig,
     pub docker: DockerConfig,
+    pub hooks: HookConfig,
+    /// Directory for this project's deploy log
+    pub data_dir: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,12 +51,60 @@ fn default_session_timeout() -> i64 {
 pub struct GitConfig {
     /// Local repository path to manage
     pub repo_path: String,
+    /// Repository cloned into `repo_path` on startup when it is not a repository yet
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Number of commits fetched by that clone (0 clones the full history)
+    #[serde(default)]
+    pub clone_depth: u32,
     /// Git remote name (typically "origin")
     #[serde(default = "default_git_remote")]
     pub remote: String,
     /// Branch to track
     #[serde(default = "default_git_branch")]
     pub branch: String,
+    /// Git implementation: the `git` CLI or the native libgit2 backend
+    #[serde(default)]
+    pub backend: GitBackendKind,
+    /// How pulls reconcile the local branch with the remote branch
+    #[serde(default)]
+    pub pull_strategy: PullStrategy,
+    /// Sync and update submodules after a pull, checkout or reset
+    #[serde(default)]
+    pub submodules: bool,
+    /// Fetch and check out Git LFS objects after a pull, checkout or reset
+    #[serde(default)]
+    pub lfs: bool,
+    /// Which commits must be signed by a trusted key before they are checked out
+    #[serde(default)]
+    pub verify_signatures: VerifyMode,
+    /// SSH allowed signers file with the trusted keys
+    #[serde(default)]
+    pub allowed_signers: Option<String>,
+    /// GnuPG home directory with the trusted public keys
+    #[serde(default)]
+    pub gpg_home: Option<String>,
+    /// Private SSH key (deploy key) for SSH remotes
+    #[serde(default)]
+    pub ssh_key: Option<String>,
+    /// known_hosts file the remote's SSH host key must be listed in
+    #[serde(default)]
+    pub known_hosts: Option<String>,
+    /// File holding an access token for HTTPS remotes
+    #[serde(default)]
+    pub token_file: Option<String>,
+    /// Seconds a single git operation may run before it is aborted
+    #[serde(default = "default_git_timeout")]
+    pub timeout: u64,
+    /// Secret for validating GitHub push webhooks (webhook endpoint disabled when unset)
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+    /// Seconds between background fetches (0 disables the poller)
+    #[serde(default)]
+    pub poll_interval: u64,
+    /// Deploy automatically when the poller finds new commits
+    #[serde(default)]
+    pub auto_deploy: bool,
 }
 
 fn default_git_remote() -> String {
@@ -49,59 +115,205 @@ fn default_git_branch() -> String {
     "main".to_string()
 }
 
+fn default_git_timeout() -> u64 {
+    120
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DockerConfig {
     /// Path to docker-compose.yml file
     pub compose_file: String,
-    /// List of container names to manage
+    /// Services to manage; all services in the compose file when empty
+    #[serde(default)]
     pub containers: Vec<String>,
-    /// Docker socket path (default: unix:///var/run/docker.sock)
+    /// Services not to manage
+    #[serde(default)]
+    pub exclude_containers: Vec<String>,
+    /// Containers or services that may not be stopped, restarted or updated from the dashboard or API
+    #[serde(default)]
+    pub protected_containers: Vec<String>,
+    /// Let updates, deploys and rollbacks recreate the protected services anyway
+    #[serde(default)]
+    pub update_protected: bool,
+    /// Docker daemon address: unix://, tcp:// or ssh:// (default: unix:///var/run/docker.sock)
     #[serde(default = "default_docker_socket")]
     pub socket: String,
+    /// Connect to a tcp:// daemon over TLS, with the client certificates in `cert_path`
+    #[serde(default)]
+    pub tls_verify: bool,
+    /// Directory holding `ca.pem`, `cert.pem` and `key.pem`
+    #[serde(default)]
+    pub cert_path: Option<String>,
+    /// `docker context` to use instead of `socket`
+    #[serde(default)]
+    pub context: Option<String>,
+    /// Roll back to the previous image when a service becomes unhealthy after an update
+    #[serde(default)]
+    pub auto_rollback: bool,
+    /// Seconds to watch updated services before the update counts as successful
+    #[serde(default = "default_rollback_grace_period")]
+    pub rollback_grace_period: u64,
+    /// Seconds a single docker compose invocation may run before it is killed
+    #[serde(default = "default_compose_timeout")]
+    pub compose_timeout: u64,
+    /// Restart containers whose healthcheck keeps failing
+    #[serde(default)]
+    pub restart_unhealthy: bool,
+    /// Seconds a container must stay unhealthy before it is restarted
+    #[serde(default = "default_unhealthy_threshold")]
+    pub unhealthy_threshold: u64,
+    /// Restarts attempted before giving up on a container until it is healthy again
+    #[serde(default = "default_max_restarts")]
+    pub max_restarts: u32,
+    /// Seconds between health checks of the restart supervisor
+    #[serde(default = "default_health_check_interval")]
+    pub health_check_interval: u64,
+    /// Seconds between resource usage samples for the dashboard (0 disables sampling)
+    #[serde(default = "default_stats_interval")]
+    pub stats_interval: u64,
+}
+
+impl DockerConfig {
+    /// Client certificate directory for TLS, defaulting to `~/.docker` like the docker CLI
+    pub fn tls_cert_path(&self) -> Option<String> {
+        if !self.tls_verify {
+            return None;
+        }
+        self.cert_path.clone().or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|home| Path::new(&home).join(".docker").to_string_lossy().into_owned())
+        })
+    }
 }
 
 fn default_docker_socket() -> String {
     "unix:///var/run/docker.sock".to_string()
 }
 
+fn default_rollback_grace_period() -> u64 {
+    30
+}
+
+fn default_compose_timeout() -> u64 {
+    600
+}
+
+fn default_unhealthy_threshold() -> u64 {
+    60
+}
+
+fn default_max_restarts() -> u32 {
+    3
+}
+
+fn default_health_check_interval() -> u64 {
+    10
+}
+
+fn default_stats_interval() -> u64 {
+    10
+}
+
+/// Shell commands run during a deploy, from the repository directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookConfig {
+    /// Runs before the pull; failing aborts the deploy
+    #[serde(default)]
+    pub pre_pull: Option<String>,
+    /// Runs after the pull, before containers are updated; failing aborts the deploy
+    #[serde(default)]
+    pub post_pull: Option<String>,
+    /// Runs before `docker compose up`; failing aborts the deploy
+    #[serde(default)]
+    pub pre_up: Option<String>,
+    /// Runs after the containers were updated; failing marks the deploy as failed
+    #[serde(default)]
+    pub post_up: Option<String>,
+    /// Seconds a single hook may run before it is killed
+    #[serde(default = "default_hook_timeout")]
+    pub timeout: u64,
+}
+
+fn default_hook_timeout() -> u64 {
+    300
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StorageConfig {
+    /// Directory for persistent state such as the operation history
+    #[serde(default = "default_data_dir")]
+    pub data_dir: String,
+}
+
+fn default_data_dir() -> String {
+    "./data".to_string()
+}
+
+/// Name of the project when `PROJECTS` is not set
+const DEFAULT_PROJECT_NAME: &str = "default";
+
 impl Config {
     /// Load configuration from environment variables
+    ///
+    /// Without `PROJECTS` a single project is read from the unprefixed `GIT_*`
+    /// and `DOCKER_*` variables. With `PROJECTS=web,blog` each project reads
+    /// `PROJECT_WEB_GIT_REPO_PATH` etc., falling back to the unprefixed variable
+    /// for everything but the repository path and the compose file.
     pub fn load() -> Result<Self> {
         dotenvy::dotenv().ok(); // Load .env file if present (ignore errors if not found)
 
+        // Variables that are not valid UTF-8 count as unset, as with env::var
+        let vars = env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        Self::from_vars(&vars)
+    }
+
+    /// Builds and validates the configuration from a set of environment variables
+    fn from_vars(vars: &HashMap<String, String>) -> Result<Self> {
+        let var = |name: &str| vars.get(name).cloned();
+
+        let storage = StorageConfig {
+            data_dir: var("DATA_DIR").unwrap_or_else(default_data_dir),
+        };
+
+        let projects = match var("PROJECTS") {
+            Some(names) => names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| {
+                    let data_dir = Path::new(&storage.data_dir).join(name);
+                    ProjectConfig::load(name, &ProjectEnv::prefixed(name, vars), data_dir.to_string_lossy().to_string())
+                })
+                .collect::<Result<Vec<_>>>()?,
+            // A single project keeps its deploy log directly in the data directory
+            None => vec![ProjectConfig::load(
+                &var("PROJECT_NAME").unwrap_or_else(|| DEFAULT_PROJECT_NAME.to_string()),
+                &ProjectEnv::unprefixed(vars),
+                storage.data_dir.clone(),
+            )?],
+        };
+
         let config = Config {
             server: ServerConfig {
-                host: env::var("SERVER_HOST").unwrap_or_else(|_| "127.0.0.1".to_string()),
-                port: env::var("SERVER_PORT")
-                    .unwrap_or_else(|_| "3000".to_string())
+                host: var("SERVER_HOST").unwrap_or_else(|| "127.0.0.1".to_string()),
+                port: var("SERVER_PORT")
+                    .unwrap_or_else(|| "3000".to_string())
                     .parse()
                     .map_err(|e| MonitorError::Config(format!("Invalid SERVER_PORT: {}", e)))?,
             },
             auth: AuthenticationConfig {
-                password: env::var("DASHBOARD_PASSWORD")
-                    .map_err(|_| MonitorError::Config("DASHBOARD_PASSWORD must be set in environment".to_string()))?,
-                session_timeout: env::var("SESSION_TIMEOUT")
-                    .unwrap_or_else(|_| "3600".to_string())
+                password: var("DASHBOARD_PASSWORD")
+                    .ok_or_else(|| MonitorError::Config("DASHBOARD_PASSWORD must be set in environment".to_string()))?,
+                session_timeout: var("SESSION_TIMEOUT")
+                    .unwrap_or_else(|| "3600".to_string())
                     .parse()
                     .map_err(|e| MonitorError::Config(format!("Invalid SESSION_TIMEOUT: {}", e)))?,
             },
-            git: GitConfig {
-                repo_path: env::var("GIT_REPO_PATH")
-                    .map_err(|_| MonitorError::Config("GIT_REPO_PATH must be set in environment".to_string()))?,
-                remote: env::var("GIT_REMOTE").unwrap_or_else(|_| "origin".to_string()),
-                branch: env::var("GIT_BRANCH").unwrap_or_else(|_| "main".to_string()),
-            },
-            docker: DockerConfig {
-                compose_file: env::var("DOCKER_COMPOSE_FILE")
-                    .map_err(|_| MonitorError::Config("DOCKER_COMPOSE_FILE must be set in environment".to_string()))?,
-                containers: env::var("DOCKER_CONTAINERS")
-                    .map_err(|_| MonitorError::Config("DOCKER_CONTAINERS must be set in environment".to_string()))?
-                    .split(',')
-                    .map(|s| s.trim().to_string())
-                    .filter(|s| !s.is_empty())
-                    .collect(),
-                socket: env::var("DOCKER_SOCKET").unwrap_or_else(|_| "unix:///var/run/docker.sock".to_string()),
-            },
+            projects,
+            storage,
         };
 
         // Validate configuration
@@ -127,27 +339,458 @@ impl Config {
             return Err(MonitorError::Config("Session timeout must be greater than 0".to_string()));
         }
 
-        // Validate git repo path exists
-        if !std::path::Path::new(&self.git.repo_path).exists() {
-            return Err(MonitorError::Config(format!(
-                "Git repository path does not exist: {}",
-                self.git.repo_path
-            )));
+        // Validate data directory is set
+        if self.storage.data_dir.trim().is_empty() {
+            return Err(MonitorError::Config("DATA_DIR cannot be empty".to_string()));
+        }
+
+        // Validate at least one project is configured, each with a unique name
+        if self.projects.is_empty() {
+            return Err(MonitorError::Config("PROJECTS must name at least one project".to_string()));
+        }
+        for (index, project) in self.projects.iter().enumerate() {
+            for other in &self.projects[..index] {
+                if other.name == project.name {
+                    return Err(MonitorError::Config(format!("Duplicate project name: {}", project.name)));
+                }
+                let prefix = env_prefix(&project.name);
+                if env_prefix(&other.name) == prefix {
+                    return Err(MonitorError::Config(format!(
+                        "Projects '{}' and '{}' both read {}* variables",
+                        other.name, project.name, prefix
+                    )));
+                }
+                if same_path(&other.git.repo_path, &project.git.repo_path) {
+                    return Err(MonitorError::Config(format!(
+                        "Projects '{}' and '{}' use the same repository: {}",
+                        other.name, project.name, project.git.repo_path
+                    )));
+                }
+                if same_path(&other.docker.compose_file, &project.docker.compose_file) {
+                    return Err(MonitorError::Config(format!(
+                        "Projects '{}' and '{}' use the same compose file: {}",
+                        other.name, project.name, project.docker.compose_file
+                    )));
+                }
+            }
+            project.validate()?;
         }
 
-        // Validate docker compose file exists
-        if !std::path::Path::new(&self.docker.compose_file).exists() {
+        Ok(())
+    }
+}
+
+/// Whether two paths name the same file, resolving symlinks and `..` when they exist
+fn same_path(a: &str, b: &str) -> bool {
+    match (Path::new(a).canonicalize(), Path::new(b).canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => Path::new(a) == Path::new(b),
+    }
+}
+
+impl ProjectConfig {
+    fn load(name: &str, env: &ProjectEnv<'_>, data_dir: String) -> Result<Self> {
+        Ok(ProjectConfig {
+            name: name.to_string(),
+            git: GitConfig {
+                repo_path: env.required("GIT_REPO_PATH")?,
+                url: env.own("GIT_REPO_URL"),
+                clone_depth: env.parse("GIT_CLONE_DEPTH", 0)?,
+                remote: env.var("GIT_REMOTE").unwrap_or_else(default_git_remote),
+                branch: env.var("GIT_BRANCH").unwrap_or_else(default_git_branch),
+                backend: env.parse("GIT_BACKEND", GitBackendKind::default())?,
+                pull_strategy: env.parse("GIT_PULL_STRATEGY", PullStrategy::default())?,
+                submodules: env.bool("GIT_SUBMODULES", false)?,
+                lfs: env.bool("GIT_LFS", false)?,
+                verify_signatures: env.parse("GIT_VERIFY_SIGNATURES", VerifyMode::default())?,
+                allowed_signers: env.var("GIT_ALLOWED_SIGNERS").filter(|s| !s.trim().is_empty()),
+                gpg_home: env.var("GIT_GPG_HOME").filter(|s| !s.trim().is_empty()),
+                ssh_key: env.var("GIT_SSH_KEY").filter(|s| !s.trim().is_empty()),
+                known_hosts: env.var("GIT_KNOWN_HOSTS").filter(|s| !s.trim().is_empty()),
+                token_file: env.var("GIT_TOKEN_FILE").filter(|s| !s.trim().is_empty()),
+                timeout: env.parse("GIT_TIMEOUT", default_git_timeout())?,
+                webhook_secret: env.var("GITHUB_WEBHOOK_SECRET").filter(|s| !s.trim().is_empty()),
+                poll_interval: env.parse("GIT_POLL_INTERVAL", 0)?,
+                auto_deploy: env.bool("GIT_AUTO_DEPLOY", false)?,
+            },
+            docker: DockerConfig {
+                compose_file: env.required("DOCKER_COMPOSE_FILE")?,
+                containers: env.list("DOCKER_CONTAINERS"),
+                exclude_containers: env.list("DOCKER_EXCLUDE_CONTAINERS"),
+                protected_containers: env.list("DOCKER_PROTECTED_CONTAINERS"),
+                update_protected: env.bool("DOCKER_UPDATE_PROTECTED", false)?,
+                socket: env.var("DOCKER_SOCKET").unwrap_or_else(default_docker_socket),
+                tls_verify: env.bool("DOCKER_TLS_VERIFY", false)?,
+                cert_path: env.var("DOCKER_CERT_PATH").filter(|s| !s.trim().is_empty()),
+                context: env.var("DOCKER_CONTEXT").filter(|s| !s.trim().is_empty()),
+                auto_rollback: env.bool("AUTO_ROLLBACK", false)?,
+                rollback_grace_period: env.parse("ROLLBACK_GRACE_PERIOD", default_rollback_grace_period())?,
+                compose_timeout: env.parse("COMPOSE_TIMEOUT", default_compose_timeout())?,
+                restart_unhealthy: env.bool("RESTART_UNHEALTHY", false)?,
+                unhealthy_threshold: env.parse("UNHEALTHY_THRESHOLD", default_unhealthy_threshold())?,
+                max_restarts: env.parse("MAX_RESTARTS", default_max_restarts())?,
+                health_check_interval: env.parse("HEALTH_CHECK_INTERVAL", default_health_check_interval())?,
+                stats_interval: env.parse("STATS_INTERVAL", default_stats_interval())?,
+            },
+            hooks: HookConfig {
+                pre_pull: env.var("HOOK_PRE_PULL").filter(|s| !s.trim().is_empty()),
+                post_pull: env.var("HOOK_POST_PULL").filter(|s| !s.trim().is_empty()),
+                pre_up: env.var("HOOK_PRE_UP").filter(|s| !s.trim().is_empty()),
+                post_up: env.var("HOOK_POST_UP").filter(|s| !s.trim().is_empty()),
+                timeout: env.parse("HOOK_TIMEOUT", default_hook_timeout())?,
+            },
+            data_dir,
+        })
+    }
+
+    /// Validate the settings of a single project
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(MonitorError::Config(format!("Project '{}': {}", self.name, message)));
+
+        // Validate the name can be used in URLs
+        if self.name.is_empty()
+            || !self.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
             return Err(MonitorError::Config(format!(
-                "Docker compose file does not exist: {}",
-                self.docker.compose_file
+                "Invalid project name '{}': use letters, digits, '-' and '_'",
+                self.name
             )));
         }
 
-        // Validate at least one container is specified
-        if self.docker.containers.is_empty() {
-            return Err(MonitorError::Config("At least one Docker container must be specified".to_string()));
+        // Validate git repo path exists, or can be cloned into
+        let repo_path = Path::new(&self.git.repo_path);
+        let will_clone = self.git.url.is_some() && !repo_path.join(".git").exists();
+        if will_clone {
+            let is_empty = repo_path
+                .read_dir()
+                .map(|mut entries| entries.next().is_none())
+                .unwrap_or(!repo_path.exists());
+            if !is_empty {
+                return invalid(format!(
+                    "Cannot clone GIT_REPO_URL into {}: it exists and is not an empty directory",
+                    self.git.repo_path
+                ));
+            }
+        } else if !repo_path.exists() {
+            return invalid(format!(
+                "Git repository path does not exist: {} (set GIT_REPO_URL to clone it)",
+                self.git.repo_path
+            ));
+        }
+
+        // Validate docker compose file exists; one inside a repository that is yet to be cloned is checked later
+        let compose_in_clone = will_clone && Path::new(&self.docker.compose_file).starts_with(repo_path);
+        if !compose_in_clone && !Path::new(&self.docker.compose_file).exists() {
+            return invalid(format!("Docker compose file does not exist: {}", self.docker.compose_file));
+        }
+
+        // Validate signature verification has keys to check against
+        if self.git.verify_signatures != VerifyMode::Off {
+            if self.git.allowed_signers.is_none() && self.git.gpg_home.is_none() {
+                return invalid(
+                    "GIT_VERIFY_SIGNATURES requires GIT_ALLOWED_SIGNERS or GIT_GPG_HOME".to_string(),
+                );
+            }
+            for path in self.git.allowed_signers.iter().chain(&self.git.gpg_home) {
+                if !Path::new(path).exists() {
+                    return invalid(format!("Signing key file does not exist: {}", path));
+                }
+            }
+        }
+
+        // Validate credential files
+        for (var, path) in [
+            ("GIT_SSH_KEY", &self.git.ssh_key),
+            ("GIT_KNOWN_HOSTS", &self.git.known_hosts),
+            ("GIT_TOKEN_FILE", &self.git.token_file),
+        ] {
+            if let Some(path) = path {
+                if !Path::new(path).is_file() {
+                    return invalid(format!("{} does not point to a file: {}", var, path));
+                }
+            }
+        }
+
+        // Validate subprocess timeouts
+        if self.git.timeout == 0 {
+            return invalid("GIT_TIMEOUT must be greater than 0".to_string());
+        }
+        if self.docker.compose_timeout == 0 {
+            return invalid("COMPOSE_TIMEOUT must be greater than 0".to_string());
+        }
+        if self.hooks.timeout == 0 {
+            return invalid("HOOK_TIMEOUT must be greater than 0".to_string());
+        }
+
+        // Validate auto-deploy has a poller to drive it
+        if self.git.auto_deploy && self.git.poll_interval == 0 {
+            return invalid("GIT_AUTO_DEPLOY requires GIT_POLL_INTERVAL to be greater than 0".to_string());
+        }
+
+        // Validate rollback grace period
+        if self.docker.auto_rollback && self.docker.rollback_grace_period == 0 {
+            return invalid("ROLLBACK_GRACE_PERIOD must be greater than 0 when AUTO_ROLLBACK is enabled".to_string());
+        }
+
+        // Validate the restart supervisor settings
+        if self.docker.restart_unhealthy {
+            if self.docker.health_check_interval == 0 {
+                return invalid("HEALTH_CHECK_INTERVAL must be greater than 0 when RESTART_UNHEALTHY is enabled".to_string());
+            }
+            if self.docker.max_restarts == 0 {
+                return invalid("MAX_RESTARTS must be greater than 0 when RESTART_UNHEALTHY is enabled".to_string());
+            }
+        }
+
+        // Validate the Docker host; a context is resolved when the project is opened
+        if self.docker.context.is_none() {
+            let cert_path = self.docker.tls_cert_path();
+            match DockerHost::parse(&self.docker.socket, cert_path.as_deref()) {
+                Ok(DockerHost::Tcp { cert_path: Some(dir), .. }) => {
+                    for file in ["ca.pem", "cert.pem", "key.pem"] {
+                        let path = Path::new(&dir).join(file);
+                        if !path.exists() {
+                            return invalid(format!("TLS certificate file does not exist: {}", path.display()));
+                        }
+                    }
+                }
+                Ok(_) if self.docker.tls_verify => {
+                    return invalid("DOCKER_TLS_VERIFY requires a tcp:// DOCKER_SOCKET".to_string());
+                }
+                Ok(_) => {}
+                Err(e) => return invalid(format!("Invalid DOCKER_SOCKET: {}", e)),
+            }
+        }
+
+        // Validate the service filter is either an allow or a deny list
+        if !self.docker.containers.is_empty() && !self.docker.exclude_containers.is_empty() {
+            return invalid("Set either DOCKER_CONTAINERS or DOCKER_EXCLUDE_CONTAINERS, not both".to_string());
         }
 
         Ok(())
     }
 }
+
+/// Prefix of the variables of `project`, e.g. "PROJECT_WEB_"
+fn env_prefix(project: &str) -> String {
+    format!("PROJECT_{}_", project.to_uppercase().replace('-', "_"))
+}
+
+/// Reads the environment variables of one project
+struct ProjectEnv<'a> {
+    /// Prepended to every variable name, e.g. "PROJECT_WEB_" (empty for a single project)
+    prefix: String,
+    vars: &'a HashMap<String, String>,
+}
+
+impl<'a> ProjectEnv<'a> {
+    fn unprefixed(vars: &'a HashMap<String, String>) -> Self {
+        Self { prefix: String::new(), vars }
+    }
+
+    fn prefixed(project: &str, vars: &'a HashMap<String, String>) -> Self {
+        Self { prefix: env_prefix(project), vars }
+    }
+
+    /// Name of the variable as the user should set it
+    fn name(&self, name: &str) -> String {
+        format!("{}{}", self.prefix, name)
+    }
+
+    /// Reads the prefixed variable, falling back to the unprefixed one
+    fn var(&self, name: &str) -> Option<String> {
+        self.vars.get(&self.name(name)).or_else(|| self.vars.get(name)).cloned()
+    }
+
+    /// Reads a variable that belongs to this project alone; no fallback, so two
+    /// projects never end up sharing a repository or compose file by accident
+    fn own(&self, name: &str) -> Option<String> {
+        self.vars.get(&self.name(name)).filter(|value| !value.trim().is_empty()).cloned()
+    }
+
+    /// Like [`Self::own`], but the variable must be set
+    fn required(&self, name: &str) -> Result<String> {
+        self.own(name)
+            .ok_or_else(|| MonitorError::Config(format!("{} must be set in environment", self.name(name))))
+    }
+
+    fn parse<T>(&self, name: &str, default: T) -> Result<T>
+    where
+        T: FromStr,
+        T::Err: std::fmt::Display,
+    {
+        match self.var(name) {
+            Some(value) => value
+                .trim()
+                .parse()
+                .map_err(|e| MonitorError::Config(format!("Invalid {}: {}", self.name(name), e))),
+            None => Ok(default),
+        }
+    }
+
+    /// Reads a comma-separated list, empty when the variable is unset
+    fn list(&self, name: &str) -> Vec<String> {
+        self.var(name)
+            .map(|value| {
+                value
+                    .split(',')
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Reads a boolean variable ("true"/"false", "1"/"0", "yes"/"no")
+    fn bool(&self, name: &str, default: bool) -> Result<bool> {
+        match self.var(name) {
+            Some(value) => match value.trim().to_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Ok(true),
+                "false" | "0" | "no" | "off" | "" => Ok(false),
+                other => Err(MonitorError::Config(format!(
+                    "Invalid {}: '{}' is not a boolean",
+                    self.name(name),
+                    other
+                ))),
+            },
+            None => Ok(default),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A directory holding a repository directory and a compose file for each of `projects`
+    fn fixture(name: &str, projects: &[&str]) -> PathBuf {
+        let root = std::env::temp_dir().join(format!("ghd-config-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        for project in projects {
+            std::fs::create_dir_all(root.join(project).join("repo")).unwrap();
+            std::fs::write(root.join(project).join("docker-compose.yml"), "services: {}\n").unwrap();
+        }
+        root
+    }
+
+    /// Variables for a valid configuration of `projects`, each pointing at its fixture files
+    fn project_vars(root: &Path, projects: &[&str]) -> HashMap<String, String> {
+        let mut vars = HashMap::from([
+            ("DASHBOARD_PASSWORD".to_string(), "secret".to_string()),
+            ("DATA_DIR".to_string(), root.join("data").to_string_lossy().into_owned()),
+            ("PROJECTS".to_string(), projects.join(", ")),
+        ]);
+        for project in projects {
+            let prefix = env_prefix(project);
+            let dir = root.join(project);
+            vars.insert(format!("{}GIT_REPO_PATH", prefix), dir.join("repo").to_string_lossy().into_owned());
+            vars.insert(
+                format!("{}DOCKER_COMPOSE_FILE", prefix),
+                dir.join("docker-compose.yml").to_string_lossy().into_owned(),
+            );
+        }
+        vars
+    }
+
+    #[test]
+    fn test_projects() {
+        let root = fixture("projects", &["web", "blog-api"]);
+        let mut vars = project_vars(&root, &["web", "blog-api"]);
+        vars.insert("PROJECTS".to_string(), " web, ,blog-api,".to_string());
+
+        let config = Config::from_vars(&vars).unwrap();
+        let names: Vec<_> = config.projects.iter().map(|project| project.name.as_str()).collect();
+        assert_eq!(names, vec!["web", "blog-api"]);
+        // Dashes in the name become underscores in the prefix
+        assert_eq!(config.projects[1].git.repo_path, root.join("blog-api/repo").to_string_lossy());
+        assert_eq!(config.projects[1].data_dir, root.join("data/blog-api").to_string_lossy());
+
+        // Without PROJECTS a single project reads the unprefixed variables
+        let mut single = HashMap::from([
+            ("DASHBOARD_PASSWORD".to_string(), "secret".to_string()),
+            ("DATA_DIR".to_string(), root.join("data").to_string_lossy().into_owned()),
+        ]);
+        single.insert("GIT_REPO_PATH".to_string(), vars["PROJECT_WEB_GIT_REPO_PATH"].clone());
+        single.insert("DOCKER_COMPOSE_FILE".to_string(), vars["PROJECT_WEB_DOCKER_COMPOSE_FILE"].clone());
+        let config = Config::from_vars(&single).unwrap();
+        assert_eq!(config.projects.len(), 1);
+        assert_eq!(config.projects[0].name, DEFAULT_PROJECT_NAME);
+        assert_eq!(config.projects[0].data_dir, root.join("data").to_string_lossy());
+
+        vars.insert("PROJECTS".to_string(), " , ".to_string());
+        assert!(Config::from_vars(&vars).is_err());
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_project_variable_fallback() {
+        let root = fixture("fallback", &["web", "blog"]);
+        let mut vars = project_vars(&root, &["web", "blog"]);
+        vars.insert("GIT_BRANCH".to_string(), "production".to_string());
+        vars.insert("PROJECT_BLOG_GIT_BRANCH".to_string(), "release".to_string());
+        // Never shared, so one project cannot clone into another's repository
+        vars.insert("GIT_REPO_URL".to_string(), "https://example.com/shared.git".to_string());
+        vars.insert("PROJECT_BLOG_GIT_REPO_URL".to_string(), "https://example.com/blog.git".to_string());
+
+        let config = Config::from_vars(&vars).unwrap();
+        let (web, blog) = (&config.projects[0], &config.projects[1]);
+        assert_eq!(web.git.branch, "production");
+        assert_eq!(blog.git.branch, "release");
+        assert_eq!(web.git.remote, "origin");
+        assert_eq!(web.git.url, None);
+        assert_eq!(blog.git.url.as_deref(), Some("https://example.com/blog.git"));
+
+        // Neither are the repository path and the compose file
+        vars.remove("PROJECT_WEB_DOCKER_COMPOSE_FILE");
+        vars.insert("DOCKER_COMPOSE_FILE".to_string(), vars["PROJECT_BLOG_DOCKER_COMPOSE_FILE"].clone());
+        let err = Config::from_vars(&vars).unwrap_err();
+        assert!(err.to_string().contains("PROJECT_WEB_DOCKER_COMPOSE_FILE must be set"), "{}", err);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_missing_repo_path() {
+        let root = fixture("missing", &["web", "blog"]);
+        let mut vars = project_vars(&root, &["web", "blog"]);
+        vars.insert("GIT_REPO_PATH".to_string(), vars["PROJECT_WEB_GIT_REPO_PATH"].clone());
+        vars.remove("PROJECT_BLOG_GIT_REPO_PATH");
+
+        let err = Config::from_vars(&vars).unwrap_err();
+        assert!(err.to_string().contains("PROJECT_BLOG_GIT_REPO_PATH must be set"), "{}", err);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn test_duplicate_projects() {
+        let root = fixture("duplicates", &["web", "blog", "web_app"]);
+        let projects_error = |vars: &HashMap<String, String>| Config::from_vars(vars).unwrap_err().to_string();
+
+        let mut vars = project_vars(&root, &["web", "blog"]);
+        vars.insert("PROJECTS".to_string(), "web,blog,web".to_string());
+        assert!(projects_error(&vars).contains("Duplicate project name: web"));
+
+        // Both read PROJECT_WEB_APP_*
+        let vars = project_vars(&root, &["web-app", "web_app"]);
+        let err = projects_error(&vars);
+        assert!(err.contains("both read PROJECT_WEB_APP_* variables"), "{}", err);
+
+        // The same repository or compose file, even through a different path
+        let mut vars = project_vars(&root, &["web", "blog"]);
+        let web_repo = root.join("blog/../web/repo").to_string_lossy().into_owned();
+        vars.insert("PROJECT_BLOG_GIT_REPO_PATH".to_string(), web_repo);
+        let err = projects_error(&vars);
+        assert!(err.contains("'web' and 'blog' use the same repository"), "{}", err);
+
+        let mut vars = project_vars(&root, &["web", "blog"]);
+        let web_compose = vars["PROJECT_WEB_DOCKER_COMPOSE_FILE"].clone();
+        vars.insert("PROJECT_BLOG_DOCKER_COMPOSE_FILE".to_string(), web_compose);
+        let err = projects_error(&vars);
+        assert!(err.contains("'web' and 'blog' use the same compose file"), "{}", err);
+
+        let _ = std::fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,844 @@
+use crate::docker::{DockerManager, ImageSnapshot, Rollback, UpdateResult};
+use crate::error::{MonitorError, Result};
+use crate::git::GitManager;
+use crate::hooks::{HookPoint, Hooks};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::{Mutex, OwnedMutexGuard, RwLock};
+use tracing::{error, info, warn};
+
+/// Number of finished deploys kept in memory
+const MAX_DEPLOY_HISTORY: usize = 50;
+
+/// Name of the deploy log inside the data directory
+const DEPLOYS_FILE: &str = "deploys.jsonl";
+
+/// Name of the rollback hold inside the data directory
+const ROLLBACK_HOLD_FILE: &str = "rollback_hold.json";
+
+const PULL_STEP: &str = "git pull";
+const UPDATE_STEP: &str = "compose update";
+const RESET_STEP: &str = "git reset";
+const RESTORE_STEP: &str = "restore images";
+
+/// Steps of a rollback, in the order they run
+const ROLLBACK_STEPS: [&str; 2] = [RESET_STEP, RESTORE_STEP];
+
+/// Status of a deploy or of one of its steps
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeployStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    Skipped,
+}
+
+/// A single step of a deploy (git pull, compose update, hooks)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployStep {
+    pub name: String,
+    pub status: DeployStatus,
+    pub started_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    pub output: String,
+    pub error: Option<String>,
+}
+
+impl DeployStep {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            status: DeployStatus::Pending,
+            started_at: None,
+            duration_ms: None,
+            output: String::new(),
+            error: None,
+        }
+    }
+}
+
+/// A tracked git pull + compose update operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Deploy {
+    pub id: String,
+    pub status: DeployStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub duration_ms: Option<u64>,
+    /// Commit checked out before the pull
+    pub previous_commit: Option<String>,
+    /// Commit checked out after the pull
+    pub commit: Option<String>,
+    /// Services that were updated (empty when the update step was skipped)
+    pub services: Vec<String>,
+    pub steps: Vec<DeployStep>,
+    /// Services rolled back automatically because they failed after the update
+    #[serde(default)]
+    pub rollbacks: Vec<Rollback>,
+    /// Image each managed service was running when the deploy finished
+    #[serde(default)]
+    pub images: HashMap<String, ImageSnapshot>,
+    /// ID of the deploy whose state this one restored, for manual rollbacks
+    #[serde(default)]
+    pub rollback_of: Option<String>,
+}
+
+impl Deploy {
+    /// Combined output of all steps, in order
+    pub fn output(&self) -> String {
+        self.steps
+            .iter()
+            .filter(|step| !step.output.is_empty() || step.error.is_some())
+            .map(|step| {
+                let mut section = format!("== {} ({:?}) ==\n{}", step.name, step.status, step.output);
+                if let Some(err) = &step.error {
+                    section.push_str(&format!("\n{}", err));
+                }
+                section
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Index of the step called `name`, which the deploy was started with
+    fn step_index(&self, name: &str) -> usize {
+        self.steps
+            .iter()
+            .position(|step| step.name == name)
+            .unwrap_or_else(|| panic!("deploy has no '{}' step", name))
+    }
+
+    /// Marks the steps that have not run as skipped
+    fn skip_pending(&mut self) {
+        for step in &mut self.steps {
+            if step.status == DeployStatus::Pending {
+                step.status = DeployStatus::Skipped;
+            }
+        }
+    }
+}
+
+/// Keeps automatic deploys from bringing back the commit a rollback moved away from
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollbackHold {
+    /// Remote commit at the time of the rollback
+    pub commit: String,
+    /// ID of the rollback that set the hold
+    pub rollback_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Options for a deploy
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct DeployOptions {
+    /// Only update the services touched by the pulled commits
+    #[serde(default)]
+    pub only_changed: bool,
+}
+
+/// Runs deploys and keeps a record of recent ones
+pub struct DeployManager {
+    git: Arc<GitManager>,
+    docker: Arc<DockerManager>,
+    hooks: Hooks,
+    deploys: RwLock<VecDeque<Deploy>>,
+    path: PathBuf,
+    hold: RwLock<Option<RollbackHold>>,
+    hold_path: PathBuf,
+    next_id: AtomicU64,
+    // Held for the duration of a deploy so that two deploys never overlap
+    running: Arc<Mutex<()>>,
+}
+
+impl DeployManager {
+    /// Creates a deploy manager, loading earlier deploys from `data_dir`
+    pub fn new(
+        git: Arc<GitManager>,
+        docker: Arc<DockerManager>,
+        hooks: Hooks,
+        data_dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir).map_err(|e| {
+            MonitorError::Config(format!(
+                "Failed to create data directory {}: {}",
+                data_dir.display(),
+                e
+            ))
+        })?;
+
+        let path = data_dir.join(DEPLOYS_FILE);
+        let mut deploys = VecDeque::new();
+
+        if path.exists() {
+            let file = std::fs::File::open(&path)?;
+            for (index, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<Deploy>(&line) {
+                    Ok(deploy) => {
+                        deploys.push_front(deploy);
+                        deploys.truncate(MAX_DEPLOY_HISTORY);
+                    }
+                    Err(e) => warn!("Skipping malformed deploy line {}: {}", index + 1, e),
+                }
+            }
+        }
+
+        info!("Loaded {} deploy(s) from {}", deploys.len(), path.display());
+
+        let hold_path = data_dir.join(ROLLBACK_HOLD_FILE);
+        let hold = match std::fs::read_to_string(&hold_path) {
+            Ok(contents) => match serde_json::from_str::<RollbackHold>(&contents) {
+                Ok(hold) => {
+                    info!("Auto-deploy of {} is held since rollback {}", hold.commit, hold.rollback_id);
+                    Some(hold)
+                }
+                Err(e) => {
+                    warn!("Ignoring malformed {}: {}", hold_path.display(), e);
+                    None
+                }
+            },
+            Err(_) => None,
+        };
+
+        Ok(Self {
+            git,
+            docker,
+            hooks,
+            deploys: RwLock::new(deploys),
+            path,
+            hold: RwLock::new(hold),
+            hold_path,
+            next_id: AtomicU64::new(1),
+            running: Arc::new(Mutex::new(())),
+        })
+    }
+
+    /// Whether a deploy or rollback is in progress
+    pub fn is_running(&self) -> bool {
+        self.running.try_lock().is_err()
+    }
+
+    /// Claims the repository and containers; no deploy or rollback starts while the
+    /// guard is held. Fails while one is running. The guard can be moved into a
+    /// background task.
+    pub fn try_lock(&self) -> Result<OwnedMutexGuard<()>> {
+        self.running
+            .clone()
+            .try_lock_owned()
+            .map_err(|_| MonitorError::Busy("A deploy, rollback or update is already in progress".to_string()))
+    }
+
+    /// Runs `operation`, such as a manual pull or checkout, under [`Self::try_lock`]
+    pub async fn exclusive<T>(&self, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        let _guard = self.try_lock()?;
+        operation.await
+    }
+
+    /// Runs git pull followed by a compose update as one tracked deploy,
+    /// with the configured hooks around both
+    pub async fn deploy(&self, options: DeployOptions) -> Result<Deploy> {
+        let _guard = self.try_lock()?;
+
+        let started = Instant::now();
+        let mut deploy = self.start_deploy(&self.deploy_steps()).await;
+        info!("Starting deploy {} (only_changed: {})", deploy.id, options.only_changed);
+        let mut env = vec![
+            ("GHD_DEPLOY_ID", deploy.id.clone()),
+            ("GHD_PREVIOUS_COMMIT", deploy.previous_commit.clone().unwrap_or_default()),
+        ];
+
+        if !self.run_hook(&mut deploy, HookPoint::PrePull, &env).await {
+            deploy.skip_pending();
+            return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+        }
+
+        // Step 1: git pull
+        let pull_step = deploy.step_index(PULL_STEP);
+        let pull_step_start = Instant::now();
+        deploy.steps[pull_step].status = DeployStatus::Running;
+        deploy.steps[pull_step].started_at = Some(Utc::now());
+        let pull = self.git.pull().await;
+        deploy.steps[pull_step].duration_ms = Some(pull_step_start.elapsed().as_millis() as u64);
+
+        let pull = match pull {
+            Ok(pull) => {
+                deploy.steps[pull_step].status = DeployStatus::Succeeded;
+                deploy.steps[pull_step].output = pull.output.clone();
+                pull
+            }
+            Err(e) => {
+                error!("Deploy {} failed during git pull: {}", deploy.id, e);
+                deploy.steps[pull_step].status = DeployStatus::Failed;
+                deploy.steps[pull_step].error = Some(e.to_string());
+                deploy.skip_pending();
+                return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+            }
+        };
+        deploy.previous_commit = Some(pull.previous_commit.clone());
+        deploy.commit = Some(pull.commit.clone());
+        if let Some(err) = pull.extras_error {
+            error!("Deploy {} failed updating submodules or LFS objects: {}", deploy.id, err);
+            deploy.steps[pull_step].status = DeployStatus::Failed;
+            deploy.steps[pull_step].error = Some(err);
+            deploy.skip_pending();
+            return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+        }
+        env.push(("GHD_COMMIT", pull.commit.clone()));
+
+        // Migrations and the like run before any container is touched
+        if !self.run_hook(&mut deploy, HookPoint::PostPull, &env).await {
+            deploy.skip_pending();
+            return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+        }
+
+        // Step 2: work out which services to update, from the compose file as pulled
+        self.docker.refresh_services().await;
+        let all_services = self.docker.service_names();
+        let all_services_count = all_services.len();
+        // Changes are counted from what was last deployed successfully, not from the
+        // pre-pull HEAD, so that a retry after a failed deploy still updates the services
+        let services = if !options.only_changed {
+            all_services
+        } else {
+            match self.last_deployed_commit(&deploy.id).await {
+                None => {
+                    info!("No earlier successful deploy, updating all services");
+                    all_services
+                }
+                Some(base) if base == pull.commit => Vec::new(),
+                Some(base) => match self.git.changed_files(&base, &pull.commit).await {
+                    Ok(changed) => {
+                        let compose_changed = changed
+                            .iter()
+                            .any(|path| self.is_compose_file(path));
+                        services_for_changes(&changed, &all_services, compose_changed)
+                    }
+                    Err(e) => {
+                        warn!("Could not determine changed files, updating all services: {}", e);
+                        all_services
+                    }
+                },
+            }
+        };
+
+        // Protected services are left as they are
+        let protected = self.docker.protected_services();
+        let (skipped, services): (Vec<String>, Vec<String>) =
+            services.into_iter().partition(|service| protected.contains(service));
+        let notes = if skipped.is_empty() {
+            String::new()
+        } else {
+            info!("Deploy {} skips protected service(s): {}", deploy.id, skipped.join(", "));
+            format!("Skipped protected service(s): {}\n", skipped.join(", "))
+        };
+
+        let update_step = deploy.step_index(UPDATE_STEP);
+        if services.is_empty() {
+            deploy.steps[update_step].output = format!("{}No services affected by the pulled changes", notes);
+            deploy.skip_pending();
+            return Ok(self.finish(deploy, started, DeployStatus::Succeeded).await);
+        }
+        env.push(("GHD_SERVICES", services.join(",")));
+
+        if !self.run_hook(&mut deploy, HookPoint::PreUp, &env).await {
+            deploy.skip_pending();
+            return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+        }
+
+        // Step 3: compose pull + up
+        let update_step_start = Instant::now();
+        deploy.steps[update_step].status = DeployStatus::Running;
+        deploy.steps[update_step].started_at = Some(Utc::now());
+        deploy.services = services.clone();
+
+        let update = if services.len() == all_services_count {
+            self.docker.update_all_containers().await
+        } else {
+            self.update_services(&services).await
+        };
+        deploy.steps[update_step].duration_ms = Some(update_step_start.elapsed().as_millis() as u64);
+
+        let updated = match update {
+            Ok(result) => {
+                deploy.steps[update_step].output = format!("{}{}", notes, result.output);
+                deploy.steps[update_step].error = result.error;
+                deploy.rollbacks = result.rollbacks;
+                result.success
+            }
+            Err(e) => {
+                deploy.steps[update_step].error = Some(e.to_string());
+                false
+            }
+        };
+        if !updated {
+            deploy.steps[update_step].status = DeployStatus::Failed;
+            deploy.skip_pending();
+            return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+        }
+        deploy.steps[update_step].status = DeployStatus::Succeeded;
+
+        let status = if self.run_hook(&mut deploy, HookPoint::PostUp, &env).await {
+            // Whoever deployed chose the commit that is now running
+            if let Some(hold) = self.release_hold().await {
+                info!("Deploy {} released the rollback hold on {}", deploy.id, hold.commit);
+            }
+            DeployStatus::Succeeded
+        } else {
+            DeployStatus::Failed
+        };
+        Ok(self.finish(deploy, started, status).await)
+    }
+
+    /// Steps of a deploy: the pull and the compose update, each between its configured hooks
+    fn deploy_steps(&self) -> Vec<&'static str> {
+        let hook = |point: HookPoint| self.hooks.command(point).map(|_| point.step_name());
+        [
+            hook(HookPoint::PrePull),
+            Some(PULL_STEP),
+            hook(HookPoint::PostPull),
+            hook(HookPoint::PreUp),
+            Some(UPDATE_STEP),
+            hook(HookPoint::PostUp),
+        ]
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Runs the hook for `point` as a step of `deploy`, if one is configured.
+    /// Returns false when the hook failed.
+    async fn run_hook(&self, deploy: &mut Deploy, point: HookPoint, env: &[(&str, String)]) -> bool {
+        if self.hooks.command(point).is_none() {
+            return true;
+        }
+
+        let step = deploy.step_index(point.step_name());
+        let step_start = Instant::now();
+        deploy.steps[step].status = DeployStatus::Running;
+        deploy.steps[step].started_at = Some(Utc::now());
+        let result = self.hooks.run(point, env).await;
+        deploy.steps[step].duration_ms = Some(step_start.elapsed().as_millis() as u64);
+
+        let error = match result {
+            Ok(output) => {
+                deploy.steps[step].output = output.output;
+                output.error
+            }
+            Err(e) => Some(e.to_string()),
+        };
+        match error {
+            None => {
+                deploy.steps[step].status = DeployStatus::Succeeded;
+                true
+            }
+            Some(err) => {
+                error!("Deploy {} failed during {} hook: {}", deploy.id, point, err);
+                deploy.steps[step].status = DeployStatus::Failed;
+                deploy.steps[step].error = Some(err);
+                false
+            }
+        }
+    }
+
+    /// Restores the commit and images recorded by an earlier deploy
+    pub async fn rollback(&self, target_id: &str) -> Result<Deploy> {
+        let target = self
+            .get(target_id)
+            .await
+            .ok_or_else(|| MonitorError::Deploy(format!("Deploy '{}' not found", target_id)))?;
+
+        if target.status != DeployStatus::Succeeded {
+            return Err(MonitorError::Deploy(format!(
+                "Deploy '{}' did not succeed and cannot be rolled back to",
+                target_id
+            )));
+        }
+        let commit = target.commit.clone().ok_or_else(|| {
+            MonitorError::Deploy(format!("Deploy '{}' has no recorded commit", target_id))
+        })?;
+        if target.images.is_empty() {
+            return Err(MonitorError::Deploy(format!(
+                "Deploy '{}' has no recorded images",
+                target_id
+            )));
+        }
+
+        let _guard = self.try_lock()?;
+
+        let started = Instant::now();
+        let mut deploy = self.start_deploy(&ROLLBACK_STEPS).await;
+        deploy.rollback_of = Some(target.id.clone());
+        info!("Starting rollback {} to deploy {} ({})", deploy.id, target.id, commit);
+        // What an automatic deploy would bring back after the reset
+        let remote_commit = self.git.get_remote_commit().await.ok().or_else(|| deploy.previous_commit.clone());
+
+        // Step 1: reset the repository to the deployed commit
+        let reset_step = deploy.step_index(RESET_STEP);
+        let restore_step = deploy.step_index(RESTORE_STEP);
+        let reset_step_start = Instant::now();
+        deploy.steps[reset_step].status = DeployStatus::Running;
+        deploy.steps[reset_step].started_at = Some(Utc::now());
+        let reset = self.git.reset_hard(&commit).await;
+        deploy.steps[reset_step].duration_ms = Some(reset_step_start.elapsed().as_millis() as u64);
+
+        let extras_error = match reset {
+            Ok(result) => {
+                deploy.steps[reset_step].status = DeployStatus::Succeeded;
+                deploy.steps[reset_step].output = result.output;
+                result.extras_error
+            }
+            Err(e) => {
+                error!("Rollback {} failed during git reset: {}", deploy.id, e);
+                deploy.steps[reset_step].status = DeployStatus::Failed;
+                deploy.steps[reset_step].error = Some(e.to_string());
+                deploy.steps[restore_step].status = DeployStatus::Skipped;
+                return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+            }
+        };
+        deploy.commit = self.git.get_local_commit().await.ok();
+        if let Some(remote_commit) = remote_commit.filter(|remote| Some(remote) != deploy.commit.as_ref()) {
+            info!("Holding auto-deploy of {} after rollback {}", remote_commit, deploy.id);
+            self.set_hold(Some(RollbackHold {
+                commit: remote_commit,
+                rollback_id: deploy.id.clone(),
+                created_at: Utc::now(),
+            }))
+            .await;
+        }
+        if let Some(err) = extras_error {
+            error!("Rollback {} failed updating submodules or LFS objects: {}", deploy.id, err);
+            deploy.steps[reset_step].status = DeployStatus::Failed;
+            deploy.steps[reset_step].error = Some(err);
+            deploy.steps[restore_step].status = DeployStatus::Skipped;
+            return Ok(self.finish(deploy, started, DeployStatus::Failed).await);
+        }
+
+        // Step 2: put every service back on the image it was running
+        let restore_step_start = Instant::now();
+        deploy.steps[restore_step].status = DeployStatus::Running;
+        deploy.steps[restore_step].started_at = Some(Utc::now());
+        deploy.services = target.images.keys().cloned().collect();
+        deploy.services.sort();
+
+        let restore = self.docker.restore_images(&target.images, |_| {}).await;
+        deploy.steps[restore_step].duration_ms = Some(restore_step_start.elapsed().as_millis() as u64);
+        deploy.steps[restore_step].output = restore.output;
+        deploy.steps[restore_step].error = restore.error;
+
+        let status = if restore.success {
+            deploy.steps[restore_step].status = DeployStatus::Succeeded;
+            DeployStatus::Succeeded
+        } else {
+            deploy.steps[restore_step].status = DeployStatus::Failed;
+            DeployStatus::Failed
+        };
+
+        Ok(self.finish(deploy, started, status).await)
+    }
+
+    /// The current rollback hold, if any
+    pub async fn rollback_hold(&self) -> Option<RollbackHold> {
+        self.hold.read().await.clone()
+    }
+
+    /// Whether automatic deploys of `remote_commit` are held back by a rollback.
+    /// The hold is released once the remote has moved past the held commit.
+    pub async fn is_held(&self, remote_commit: &str) -> bool {
+        let Some(hold) = self.rollback_hold().await else {
+            return false;
+        };
+        if hold.commit == remote_commit {
+            return true;
+        }
+        info!("Remote moved past {}, releasing the rollback hold", hold.commit);
+        self.set_hold(None).await;
+        false
+    }
+
+    /// Lifts the rollback hold, returning it
+    pub async fn release_hold(&self) -> Option<RollbackHold> {
+        let hold = self.rollback_hold().await;
+        if hold.is_some() {
+            self.set_hold(None).await;
+        }
+        hold
+    }
+
+    async fn set_hold(&self, hold: Option<RollbackHold>) {
+        if let Err(e) = self.write_hold(hold.as_ref()) {
+            error!("Failed to update {}: {}", self.hold_path.display(), e);
+        }
+        *self.hold.write().await = hold;
+    }
+
+    fn write_hold(&self, hold: Option<&RollbackHold>) -> Result<()> {
+        match hold {
+            Some(hold) => std::fs::write(&self.hold_path, serde_json::to_string(hold)?)?,
+            None if self.hold_path.exists() => std::fs::remove_file(&self.hold_path)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// Commit of the most recent successful deploy or rollback other than `current`
+    async fn last_deployed_commit(&self, current: &str) -> Option<String> {
+        self.deploys
+            .read()
+            .await
+            .iter()
+            .filter(|deploy| deploy.id != current)
+            .find(|deploy| deploy.status == DeployStatus::Succeeded)
+            .and_then(|deploy| deploy.commit.clone())
+    }
+
+    /// Returns a deploy by ID
+    pub async fn get(&self, id: &str) -> Option<Deploy> {
+        self.deploys.read().await.iter().find(|d| d.id == id).cloned()
+    }
+
+    /// Returns recent deploys, newest first
+    pub async fn list(&self) -> Vec<Deploy> {
+        self.deploys.read().await.iter().cloned().collect()
+    }
+
+    async fn update_services(&self, services: &[String]) -> Result<UpdateResult> {
+        let mut output = String::new();
+        for service in services {
+            let result = self.docker.update_container(service).await?;
+            output.push_str(&result.output);
+            output.push('\n');
+            if !result.success {
+                return Ok(UpdateResult {
+                    success: false,
+                    output,
+                    error: result.error,
+                    rollbacks: result.rollbacks,
+                });
+            }
+        }
+
+        Ok(UpdateResult {
+            success: true,
+            output,
+            error: None,
+            rollbacks: Vec::new(),
+        })
+    }
+
+    fn is_compose_file(&self, changed_path: &str) -> bool {
+        let changed = Path::new(self.git.repo_path()).join(changed_path);
+        let compose = Path::new(self.docker.compose_file_path());
+        match (changed.canonicalize(), compose.canonicalize()) {
+            (Ok(a), Ok(b)) => a == b,
+            _ => changed == compose,
+        }
+    }
+
+    async fn start_deploy(&self, steps: &[&str]) -> Deploy {
+        let id = format!(
+            "{}-{}",
+            Utc::now().format("%Y%m%d%H%M%S"),
+            self.next_id.fetch_add(1, Ordering::SeqCst)
+        );
+
+        Deploy {
+            id,
+            status: DeployStatus::Running,
+            started_at: Utc::now(),
+            finished_at: None,
+            duration_ms: None,
+            previous_commit: self.git.get_local_commit().await.ok(),
+            commit: None,
+            services: Vec::new(),
+            steps: steps.iter().map(|name| DeployStep::new(name)).collect(),
+            rollbacks: Vec::new(),
+            images: HashMap::new(),
+            rollback_of: None,
+        }
+    }
+
+    async fn finish(&self, mut deploy: Deploy, started: Instant, status: DeployStatus) -> Deploy {
+        deploy.status = status;
+        deploy.finished_at = Some(Utc::now());
+        deploy.duration_ms = Some(started.elapsed().as_millis() as u64);
+        deploy.images = self.docker.current_images(&self.docker.service_names()).await;
+        info!("Deploy {} finished: {:?}", deploy.id, deploy.status);
+
+        if let Err(e) = self.append(&deploy) {
+            error!("Failed to write deploy {} to {}: {}", deploy.id, self.path.display(), e);
+        }
+
+        let mut deploys = self.deploys.write().await;
+        deploys.push_front(deploy.clone());
+        deploys.truncate(MAX_DEPLOY_HISTORY);
+
+        deploy
+    }
+
+    fn append(&self, deploy: &Deploy) -> Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let mut line = serde_json::to_string(deploy)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Picks the services affected by a set of changed paths.
+///
+/// A service counts as touched when one of the changed paths has a directory
+/// named after it. A change to the compose file itself touches every service.
+pub fn services_for_changes(changed: &[String], services: &[String], compose_changed: bool) -> Vec<String> {
+    if compose_changed {
+        return services.to_vec();
+    }
+
+    services
+        .iter()
+        .filter(|service| {
+            changed.iter().any(|path| {
+                let mut components = Path::new(path).components();
+                // The last component is the file itself
+                components.next_back();
+                components.any(|c| c.as_os_str() == service.as_str())
+            })
+        })
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compose::ServiceFilter;
+    use crate::docker_host::{DockerEndpoint, DockerHost};
+    use crate::git::{GitBackendKind, PullStrategy, UpdateOptions};
+    use std::time::Duration;
+
+    /// A deploy manager keeping its files in a fresh `data_dir`; nothing is sent to git or Docker
+    async fn deploy_manager(data_dir: &Path, hooks: Hooks) -> DeployManager {
+        let git = GitManager::new(
+            data_dir.to_string_lossy(),
+            "origin",
+            "main",
+            GitBackendKind::Cli,
+            PullStrategy::FfOnly,
+            UpdateOptions::default(),
+            Duration::from_secs(30),
+        );
+        // The client only needs the socket to exist
+        let socket = data_dir.join("docker.sock");
+        std::fs::write(&socket, "").unwrap();
+        let endpoint = DockerEndpoint {
+            host: DockerHost::Unix(socket.to_string_lossy().into_owned()),
+            context: None,
+        };
+        let compose_file = data_dir.join("docker-compose.yml");
+        let docker = DockerManager::new(compose_file.to_string_lossy(), ServiceFilter::All, None, Duration::from_secs(60), endpoint)
+            .await
+            .unwrap();
+        DeployManager::new(Arc::new(git), Arc::new(docker), hooks, data_dir).unwrap()
+    }
+
+    fn data_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("ghd-deploy-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_rollback_hold() {
+        let dir = data_dir("hold");
+        let manager = deploy_manager(&dir, Hooks::new(".", Duration::from_secs(1))).await;
+        let hold = |commit: &str| RollbackHold {
+            commit: commit.to_string(),
+            rollback_id: "rollback-1".to_string(),
+            created_at: Utc::now(),
+        };
+        assert!(!manager.is_held("abc").await);
+
+        // Held while the remote stays on the commit the rollback moved away from
+        manager.set_hold(Some(hold("abc"))).await;
+        assert!(manager.is_held("abc").await);
+        assert!(manager.is_held("abc").await);
+
+        // ... and survives a restart
+        let manager = deploy_manager(&dir, Hooks::new(".", Duration::from_secs(1))).await;
+        assert_eq!(manager.rollback_hold().await.map(|hold| hold.commit).as_deref(), Some("abc"));
+
+        // A newer remote commit releases it for good
+        assert!(!manager.is_held("def").await);
+        assert!(manager.rollback_hold().await.is_none());
+        assert!(!manager.is_held("abc").await);
+        assert!(!dir.join(ROLLBACK_HOLD_FILE).exists());
+
+        // Releasing by hand returns the hold once
+        manager.set_hold(Some(hold("abc"))).await;
+        assert_eq!(manager.release_hold().await.map(|hold| hold.commit).as_deref(), Some("abc"));
+        assert!(manager.release_hold().await.is_none());
+        assert!(!manager.is_held("abc").await);
+        assert!(!dir.join(ROLLBACK_HOLD_FILE).exists());
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[tokio::test]
+    async fn test_step_order() {
+        let dir = data_dir("steps");
+        let hooks = Hooks::new(".", Duration::from_secs(1))
+            .with(HookPoint::PostPull, Some("true".to_string()))
+            .with(HookPoint::PostUp, Some("true".to_string()));
+        let manager = deploy_manager(&dir, hooks).await;
+
+        // Hooks sit around the step they belong to; unconfigured ones are left out
+        let deploy = manager.start_deploy(&manager.deploy_steps()).await;
+        let names: Vec<_> = deploy.steps.iter().map(|step| step.name.as_str()).collect();
+        assert_eq!(names, vec![PULL_STEP, "post-pull hook", UPDATE_STEP, "post-up hook"]);
+        assert_eq!(deploy.step_index(PULL_STEP), 0);
+        assert_eq!(deploy.step_index(UPDATE_STEP), 2);
+        assert_eq!(deploy.step_index(HookPoint::PostUp.step_name()), 3);
+
+        // A rollback resets the repository before it touches any container
+        let mut rollback = manager.start_deploy(&ROLLBACK_STEPS).await;
+        assert!(rollback.step_index(RESET_STEP) < rollback.step_index(RESTORE_STEP));
+        assert!(rollback.steps.iter().all(|step| step.status == DeployStatus::Pending));
+        rollback.steps[0].status = DeployStatus::Failed;
+        rollback.skip_pending();
+        let statuses: Vec<_> = rollback.steps.iter().map(|step| step.status).collect();
+        assert_eq!(statuses, vec![DeployStatus::Failed, DeployStatus::Skipped]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_services_for_changes() {
+        let services = vec!["web".to_string(), "worker".to_string(), "db".to_string()];
+
+        let changed = vec!["web/src/main.rs".to_string(), "README.md".to_string()];
+        assert_eq!(services_for_changes(&changed, &services, false), vec!["web".to_string()]);
+
+        let changed = vec!["services/worker/Dockerfile".to_string()];
+        assert_eq!(services_for_changes(&changed, &services, false), vec!["worker".to_string()]);
+
+        // A file named like a service is not a service directory
+        let changed = vec!["docs/db".to_string()];
+        assert!(services_for_changes(&changed, &services, false).is_empty());
+
+        assert_eq!(services_for_changes(&[], &services, true), services);
+    }
+}
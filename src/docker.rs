@@ -1,10 +1,23 @@
+use crate::compose::{containers_by_service, ComposeProject, ComposeService, ServiceFilter, PROJECT_LABEL, SERVICE_LABEL};
+use crate::docker_host::{DockerEndpoint, DockerHost, SshTunnel};
 use crate::error::{MonitorError, Result};
-use bollard::container::{InspectContainerOptions, RestartContainerOptions, StartContainerOptions, StopContainerOptions};
+use bollard::container::{
+    InspectContainerOptions, ListContainersOptions, LogOutput, LogsOptions, MemoryStatsStats,
+    RestartContainerOptions, StartContainerOptions, Stats, StatsOptions, StopContainerOptions,
+};
+use bollard::image::TagImageOptions;
+use bollard::models::{ContainerInspectResponse, ContainerState, ContainerStateStatusEnum, ContainerSummary, HealthStatusEnum};
 use bollard::Docker;
-use bollard::models::ContainerStateStatusEnum;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::Path;
-use std::process::Command;
+use std::process::Stdio;
+use std::time::{Duration, Instant};
+use std::sync::{Arc, RwLock};
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+use futures_util::stream::{self, Stream, StreamExt};
 use tracing::{debug, error, info, warn};
 
 /// Represents the status of a Docker container
@@ -50,29 +63,272 @@ impl std::fmt::Display for ContainerStatus {
     }
 }
 
+/// State of a container's Docker healthcheck
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HealthStatus {
+    Starting,
+    Healthy,
+    Unhealthy,
+}
+
+impl std::fmt::Display for HealthStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HealthStatus::Starting => write!(f, "starting"),
+            HealthStatus::Healthy => write!(f, "healthy"),
+            HealthStatus::Unhealthy => write!(f, "unhealthy"),
+        }
+    }
+}
+
+/// Healthcheck result of a container
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContainerHealth {
+    pub status: HealthStatus,
+    /// Number of consecutive failed probes
+    pub failing_streak: i64,
+    /// Output of the most recent probe
+    pub last_output: Option<String>,
+}
+
+impl ContainerHealth {
+    /// Reads the healthcheck state; `None` when the container has no healthcheck
+    fn from_state(state: &ContainerState) -> Option<Self> {
+        let health = state.health.as_ref()?;
+        let status = match health.status? {
+            HealthStatusEnum::STARTING => HealthStatus::Starting,
+            HealthStatusEnum::HEALTHY => HealthStatus::Healthy,
+            HealthStatusEnum::UNHEALTHY => HealthStatus::Unhealthy,
+            HealthStatusEnum::NONE | HealthStatusEnum::EMPTY => return None,
+        };
+        let last_output = health
+            .log
+            .as_ref()
+            .and_then(|log| log.last())
+            .and_then(|result| result.output.as_deref())
+            .map(|output| output.trim().to_string())
+            .filter(|output| !output.is_empty());
+        Some(Self {
+            status,
+            failing_streak: health.failing_streak.unwrap_or(0),
+            last_output,
+        })
+    }
+}
+
 /// Information about a container
 #[derive(Debug, Clone)]
 pub struct ContainerInfo {
     pub name: String,
+    /// Compose service the container belongs to
+    pub service: String,
     pub status: ContainerStatus,
     pub image: String,
+    /// Healthcheck state, `None` when the image defines no healthcheck
+    pub health: Option<ContainerHealth>,
+}
+
+/// Resource usage of a container at one point in time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ContainerStats {
+    pub name: String,
+    pub timestamp: DateTime<Utc>,
+    /// CPU usage since the previous sample, 100% per fully used core
+    pub cpu_percent: f64,
+    /// Memory in use, excluding the inactive page cache (as `docker stats` reports it)
+    pub memory_usage: u64,
+    pub memory_limit: u64,
+    pub memory_percent: f64,
+    /// Bytes received and sent over all networks since the container started
+    pub network_rx: u64,
+    pub network_tx: u64,
+    /// Bytes read from and written to block devices since the container started
+    pub block_read: u64,
+    pub block_write: u64,
+}
+
+impl ContainerStats {
+    fn from_stats(name: &str, stats: &Stats) -> Self {
+        let cpu_delta = stats
+            .cpu_stats
+            .cpu_usage
+            .total_usage
+            .saturating_sub(stats.precpu_stats.cpu_usage.total_usage);
+        let system_delta = stats
+            .cpu_stats
+            .system_cpu_usage
+            .unwrap_or(0)
+            .saturating_sub(stats.precpu_stats.system_cpu_usage.unwrap_or(0));
+        let cpus = stats.cpu_stats.online_cpus.filter(|cpus| *cpus > 0).unwrap_or_else(|| {
+            stats
+                .cpu_stats
+                .cpu_usage
+                .percpu_usage
+                .as_ref()
+                .map_or(1, |percpu| percpu.len().max(1) as u64)
+        });
+        let cpu_percent = if system_delta > 0 {
+            cpu_delta as f64 / system_delta as f64 * cpus as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        let cache = match stats.memory_stats.stats {
+            Some(MemoryStatsStats::V1(v1)) => v1.total_inactive_file,
+            Some(MemoryStatsStats::V2(v2)) => v2.inactive_file,
+            None => 0,
+        };
+        let memory_usage = stats.memory_stats.usage.unwrap_or(0).saturating_sub(cache);
+        let memory_limit = stats.memory_stats.limit.unwrap_or(0);
+        let memory_percent = if memory_limit > 0 {
+            memory_usage as f64 / memory_limit as f64 * 100.0
+        } else {
+            0.0
+        };
+
+        let (network_rx, network_tx) = stats
+            .networks
+            .iter()
+            .flat_map(|networks| networks.values())
+            .fold((0, 0), |(rx, tx), network| (rx + network.rx_bytes, tx + network.tx_bytes));
+
+        let (block_read, block_write) = stats
+            .blkio_stats
+            .io_service_bytes_recursive
+            .iter()
+            .flatten()
+            .fold((0, 0), |(read, write), entry| match entry.op.to_ascii_lowercase().as_str() {
+                "read" => (read + entry.value, write),
+                "write" => (read, write + entry.value),
+                _ => (read, write),
+            });
+
+        Self {
+            name: name.to_string(),
+            timestamp: Utc::now(),
+            cpu_percent,
+            memory_usage,
+            memory_limit,
+            memory_percent,
+            network_rx,
+            network_tx,
+            block_read,
+            block_write,
+        }
+    }
+}
+
+/// A managed compose service and its containers, replicas in order
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManagedService {
+    pub name: String,
+    /// Empty while the service has no container yet
+    pub containers: Vec<String>,
 }
 
 /// Result of an update operation
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct UpdateResult {
     pub success: bool,
     pub output: String,
     pub error: Option<String>,
+    /// Services that were rolled back because they became unhealthy after the update
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rollbacks: Vec<Rollback>,
+}
+
+/// A service rolled back to its previous image after a failed update
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Rollback {
+    pub service: String,
+    /// Why the updated service was considered failed
+    pub reason: String,
+    /// Image ID the service was updated to
+    pub failed_image: String,
+    /// Image ID the service was rolled back to
+    pub restored_image: String,
+    pub success: bool,
+    pub error: Option<String>,
+}
+
+/// Image a service was running at a point in time
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ImageSnapshot {
+    /// Image ID (sha256:...)
+    pub image_id: String,
+    /// Image reference from the container config (e.g. nginx:latest)
+    pub image_ref: String,
+}
+
+/// How often services are inspected while watching them after an update
+const ROLLBACK_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Which output stream a log line came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogStream {
+    Stdout,
+    Stderr,
+}
+
+/// A single line of container log output
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LogLine {
+    pub stream: LogStream,
+    pub message: String,
+}
+
+/// Filters for reading container logs
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogOptions {
+    /// Number of lines to return from the end of the logs
+    #[serde(default = "default_log_tail")]
+    pub tail: u64,
+    /// Only return logs since this UNIX timestamp
+    #[serde(default)]
+    pub since: i64,
+    /// Prefix every line with its timestamp
+    #[serde(default)]
+    pub timestamps: bool,
+    /// Include stdout
+    #[serde(default = "default_true")]
+    pub stdout: bool,
+    /// Include stderr
+    #[serde(default = "default_true")]
+    pub stderr: bool,
+    /// Keep the stream open and follow new output
+    #[serde(default)]
+    pub follow: bool,
+}
+
+fn default_log_tail() -> u64 {
+    100
+}
+
+fn default_true() -> bool {
+    true
 }
 
 /// Manages Docker containers and compose operations
 #[derive(Clone)]
 pub struct DockerManager {
     docker: Docker,
+    endpoint: DockerEndpoint,
+    /// Connection to an SSH host, reopened when it drops
+    tunnel: Option<Arc<SshTunnel>>,
     compose_file_path: String,
     compose_dir: String,
-    container_names: Vec<String>,
+    /// Which of the compose services are managed
+    filter: ServiceFilter,
+    /// Services as last discovered from the compose file
+    project: Arc<RwLock<ComposeProject>>,
+    /// Containers or services that may not be stopped or restarted
+    protected: Vec<String>,
+    /// Whether updates, deploys and rollbacks may still recreate the protected services
+    update_protected: bool,
+    rollback_grace_period: Option<Duration>,
+    compose_timeout: Duration,
 }
 
 impl DockerManager {
@@ -80,8 +336,18 @@ impl DockerManager {
     ///
     /// # Arguments
     /// * `compose_file_path` - Path to the docker-compose.yml file
-    /// * `container_names` - List of container names to manage
-    pub fn new(compose_file_path: impl Into<String>, container_names: Vec<String>) -> Result<Self> {
+    /// * `filter` - Which of the services discovered in the compose file to manage
+    /// * `rollback_grace_period` - How long to watch updated services before rolling back
+    ///   unhealthy ones to their previous image (`None` disables automatic rollback)
+    /// * `compose_timeout` - How long a single `docker compose` invocation may run before it is killed
+    /// * `endpoint` - Docker daemon used by both the API client and `docker compose`
+    pub async fn new(
+        compose_file_path: impl Into<String>,
+        filter: ServiceFilter,
+        rollback_grace_period: Option<Duration>,
+        compose_timeout: Duration,
+        endpoint: DockerEndpoint,
+    ) -> Result<Self> {
         let compose_file_path = compose_file_path.into();
 
         // Extract directory from compose file path
@@ -93,18 +359,190 @@ impl DockerManager {
             .to_string();
 
         // Connect to Docker daemon
-        let docker = Docker::connect_with_local_defaults()
-            .map_err(|e| MonitorError::Docker(format!("Failed to connect to Docker daemon: {}", e)))?;
+        let connection = endpoint.host.connect().await?;
+        // Compose talks through the same tunnel as the API client
+        let endpoint = match &connection.tunnel {
+            Some(tunnel) => DockerEndpoint {
+                host: DockerHost::Unix(tunnel.socket().to_string_lossy().into_owned()),
+                context: None,
+            },
+            None => endpoint,
+        };
 
         Ok(Self {
-            docker,
+            docker: connection.docker,
+            endpoint,
+            tunnel: connection.tunnel.map(Arc::new),
             compose_file_path,
             compose_dir,
-            container_names,
+            filter,
+            project: Arc::default(),
+            protected: Vec::new(),
+            update_protected: false,
+            rollback_grace_period,
+            compose_timeout,
         })
     }
 
-    /// Validates that the compose file exists and containers are known to Docker
+    /// Refuses to stop, restart or update these containers, or the containers of these services
+    pub fn with_protected(mut self, names: Vec<String>) -> Self {
+        self.protected = names;
+        self
+    }
+
+    /// Lets updates, deploys and rollbacks recreate the protected services anyway
+    pub fn with_protected_updates(mut self, allow: bool) -> Self {
+        self.update_protected = allow;
+        self
+    }
+
+    /// Managed services that updates, deploys and rollbacks leave alone
+    pub fn protected_services(&self) -> Vec<String> {
+        if self.update_protected || self.protected.is_empty() {
+            return Vec::new();
+        }
+        let services = self.service_names();
+        let project = self.project.read().expect("compose project lock poisoned");
+        services
+            .into_iter()
+            .filter(|service| {
+                self.protected
+                    .iter()
+                    .any(|name| project.find(name).is_some_and(|found| found.name == *service))
+            })
+            .collect()
+    }
+
+    /// Names of the managed compose services, as last discovered
+    pub fn service_names(&self) -> Vec<String> {
+        self.project
+            .read()
+            .expect("compose project lock poisoned")
+            .services
+            .iter()
+            .filter(|service| self.filter.matches(service))
+            .map(|service| service.name.clone())
+            .collect()
+    }
+
+    /// The managed services with the containers currently created for them
+    pub async fn services(&self) -> Result<Vec<ManagedService>> {
+        let project = self.project.read().expect("compose project lock poisoned").clone();
+        let mut containers = match &project.name {
+            Some(name) => containers_by_service(&self.list_labelled(format!("{}={}", PROJECT_LABEL, name)).await?),
+            None => HashMap::new(),
+        };
+
+        Ok(project
+            .services
+            .iter()
+            .filter(|service| self.filter.matches(service))
+            .map(|service| {
+                // Without compose labels, fall back to the fixed container name, or to
+                // the service name itself when the project could not be discovered
+                let containers = containers.remove(&service.name).unwrap_or_else(|| {
+                    service
+                        .container_name
+                        .clone()
+                        .or_else(|| project.name.is_none().then(|| service.name.clone()))
+                        .into_iter()
+                        .collect()
+                });
+                ManagedService {
+                    name: service.name.clone(),
+                    containers,
+                }
+            })
+            .collect())
+    }
+
+    /// Names of the containers of all managed services
+    pub async fn container_names(&self) -> Result<Vec<String>> {
+        Ok(self
+            .services()
+            .await?
+            .into_iter()
+            .flat_map(|service| service.containers)
+            .collect())
+    }
+
+    /// Finds the managed service `name` refers to, by its own name or one of its containers
+    pub async fn resolve_service(&self, name: &str) -> Result<String> {
+        self.services()
+            .await?
+            .into_iter()
+            .find(|service| service.name == name || service.containers.iter().any(|container| container == name))
+            .map(|service| service.name)
+            .ok_or_else(|| MonitorError::UnknownContainer(name.to_string()))
+    }
+
+    /// Keeps `docker compose up` from recreating protected services that others depend on
+    fn no_deps(&self) -> &'static [&'static str] {
+        if self.protected_services().is_empty() {
+            &[]
+        } else {
+            &["--no-deps"]
+        }
+    }
+
+    /// Like `resolve_service`, but refuses protected services
+    pub async fn resolve_updatable_service(&self, name: &str) -> Result<String> {
+        let service = self.resolve_service(name).await?;
+        if self.protected_services().contains(&service) {
+            warn!("Refusing to update protected service: {}", service);
+            return Err(MonitorError::ProtectedContainer(name.to_string()));
+        }
+        Ok(service)
+    }
+
+    /// Re-reads the services from the compose file, e.g. after a pull changed it.
+    /// The previously discovered services are kept if that fails.
+    pub async fn refresh_services(&self) {
+        match self.compose_config().await {
+            Ok(project) => *self.project.write().expect("compose project lock poisoned") = project,
+            Err(e) => warn!("Keeping the previously discovered services: {}", e),
+        }
+    }
+
+    /// Resolves the compose file with `docker compose config`
+    async fn compose_config(&self) -> Result<ComposeProject> {
+        let output = self.run_compose(&["config", "--format", "json"], &|_| {}).await?;
+        if !output.success {
+            return Err(MonitorError::Docker(format!(
+                "docker compose config failed: {}",
+                output.stderr.trim()
+            )));
+        }
+        ComposeProject::from_config(&output.stdout)
+    }
+
+    /// Lists all containers, running or not, that carry `label`
+    async fn list_labelled(&self, label: String) -> Result<Vec<ContainerSummary>> {
+        let options = ListContainersOptions::<String> {
+            all: true,
+            filters: HashMap::from([("label".to_string(), vec![label])]),
+            ..Default::default()
+        };
+        self.client().await?
+            .list_containers(Some(options))
+            .await
+            .map_err(|e| MonitorError::Docker(format!("Failed to list containers: {}", e)))
+    }
+
+    /// The API client, reopening the SSH tunnel first if it has dropped
+    async fn client(&self) -> Result<&Docker> {
+        if let Some(tunnel) = &self.tunnel {
+            tunnel.ensure_open().await?;
+        }
+        Ok(&self.docker)
+    }
+
+    /// Path to the docker-compose.yml file
+    pub fn compose_file_path(&self) -> &str {
+        &self.compose_file_path
+    }
+
+    /// Validates that the compose file exists and discovers its services
     pub async fn validate(&self) -> Result<()> {
         info!("Validating Docker configuration");
 
@@ -117,66 +555,126 @@ impl DockerManager {
             )));
         }
 
-        // Validate Docker connection by listing containers
-        let containers = self.docker
-            .list_containers::<String>(None)
+        // Validate Docker connection
+        self.client().await?
+            .ping()
             .await
             .map_err(|e| MonitorError::Docker(format!("Failed to connect to Docker daemon: {}", e)))?;
 
-        debug!("Found {} total containers", containers.len());
-
-        // Check if configured container names exist
-        let mut container_map: HashMap<String, bool> = HashMap::new();
-        for container in &containers {
-            if let Some(names) = &container.names {
-                for name in names {
-                    let clean_name = name.trim_start_matches('/').to_string();
-                    container_map.insert(clean_name, true);
+        let project = match self.compose_config().await {
+            Ok(project) => {
+                let unknown = self.filter.unknown(&project);
+                if !unknown.is_empty() {
+                    return Err(MonitorError::Docker(format!(
+                        "Not a service or container_name in {}: {}",
+                        self.compose_file_path,
+                        unknown.join(", ")
+                    )));
+                }
+                let unknown: Vec<&str> = self
+                    .protected
+                    .iter()
+                    .filter(|name| project.find(name).is_none())
+                    .map(String::as_str)
+                    .collect();
+                if !unknown.is_empty() {
+                    return Err(MonitorError::Docker(format!(
+                        "Protected name is not a service or container_name in {}: {}",
+                        self.compose_file_path,
+                        unknown.join(", ")
+                    )));
                 }
+                project
             }
-        }
-
-        let mut missing_containers = Vec::new();
-        for name in &self.container_names {
-            if !container_map.contains_key(name) {
-                missing_containers.push(name.clone());
+            Err(e) => {
+                warn!("{}; discovering services from container labels", e);
+                let containers = self.list_labelled(PROJECT_LABEL.to_string()).await?;
+                match (ComposeProject::from_labels(&containers, compose_path), &self.filter) {
+                    (Some(project), _) => project,
+                    // Manage the listed names as before, without a compose project
+                    (None, ServiceFilter::Only(names)) => {
+                        warn!("No containers of {} found, managing {:?} as listed", self.compose_file_path, names);
+                        ComposeProject {
+                            name: None,
+                            services: names
+                                .iter()
+                                .map(|name| ComposeService {
+                                    name: name.clone(),
+                                    container_name: None,
+                                })
+                                .collect(),
+                        }
+                    }
+                    (None, _) => {
+                        return Err(MonitorError::Docker(format!(
+                            "Could not discover the services of {}: {}",
+                            self.compose_file_path, e
+                        )))
+                    }
+                }
             }
-        }
+        };
+        *self.project.write().expect("compose project lock poisoned") = project;
 
-        if !missing_containers.is_empty() {
-            warn!(
-                "Some configured containers not found in Docker: {:?}",
-                missing_containers
-            );
-            warn!("These containers may not be created yet. They will be available after first compose up.");
+        let services = self.services().await?;
+        if services.is_empty() {
+            return Err(MonitorError::Docker(format!(
+                "No services to manage in {}",
+                self.compose_file_path
+            )));
+        }
+        for service in &services {
+            if service.containers.is_empty() {
+                warn!(
+                    "Service '{}' has no container yet; it will be available after the first compose up",
+                    service.name
+                );
+            } else {
+                info!("Service '{}': {}", service.name, service.containers.join(", "));
+            }
         }
 
         info!("Docker validation completed successfully");
         Ok(())
     }
 
-    /// Gets the status of a specific container
-    pub async fn get_container_status(&self, container_name: &str) -> Result<ContainerInfo> {
-        debug!("Getting status for container: {}", container_name);
-
-        let inspect = self.docker
+    async fn inspect(&self, container_name: &str) -> Result<ContainerInspectResponse> {
+        self.client()
+            .await?
             .inspect_container(container_name, None::<InspectContainerOptions>)
             .await
             .map_err(|e| MonitorError::Docker(format!(
                 "Failed to inspect container '{}': {}",
                 container_name, e
-            )))?;
+            )))
+    }
+
+    /// Gets the status of a specific container
+    pub async fn get_container_status(&self, container_name: &str) -> Result<ContainerInfo> {
+        debug!("Getting status for container: {}", container_name);
+
+        let inspect = self.inspect(container_name).await?;
 
         let state = inspect.state.ok_or_else(|| {
             MonitorError::Docker(format!("Container '{}' has no state", container_name))
         })?;
 
         let status = state.status.unwrap_or(ContainerStateStatusEnum::EMPTY).into();
+        let name = inspect.name.unwrap_or_default().trim_start_matches('/').to_string();
+        let service = inspect
+            .config
+            .as_ref()
+            .and_then(|config| config.labels.as_ref())
+            .and_then(|labels| labels.get(SERVICE_LABEL))
+            .cloned()
+            .unwrap_or_else(|| name.clone());
 
         let info = ContainerInfo {
-            name: inspect.name.unwrap_or_default().trim_start_matches('/').to_string(),
+            name,
+            service,
             status,
             image: inspect.image.unwrap_or_default(),
+            health: ContainerHealth::from_state(&state),
         };
 
         debug!("Container {} status: {}", container_name, info.status);
@@ -188,8 +686,8 @@ impl DockerManager {
         debug!("Getting status for all managed containers");
 
         let mut infos = Vec::new();
-        for name in &self.container_names {
-            match self.get_container_status(name).await {
+        for name in self.container_names().await? {
+            match self.get_container_status(&name).await {
                 Ok(info) => infos.push(info),
                 Err(e) => {
                     warn!("Failed to get status for container '{}': {}", name, e);
@@ -201,11 +699,162 @@ impl DockerManager {
         Ok(infos)
     }
 
-    /// Starts a specific container
+    /// Samples the resource usage of a container
+    pub async fn container_stats(&self, container_name: &str) -> Result<ContainerStats> {
+        debug!("Getting stats for container: {}", container_name);
+
+        // Not one-shot, so that the daemon takes two samples and CPU usage can be computed
+        let options = StatsOptions {
+            stream: false,
+            one_shot: false,
+        };
+        let stats = self
+            .client()
+            .await?
+            .stats(container_name, Some(options))
+            .next()
+            .await
+            .ok_or_else(|| MonitorError::Docker(format!("No stats returned for container '{}'", container_name)))?
+            .map_err(|e| MonitorError::Docker(format!(
+                "Failed to get stats for container '{}': {}",
+                container_name, e
+            )))?;
+
+        Ok(ContainerStats::from_stats(container_name, &stats))
+    }
+
+    /// Samples the resource usage of all managed containers at once
+    pub async fn all_container_stats(&self) -> Vec<ContainerStats> {
+        let names = match self.container_names().await {
+            Ok(names) => names,
+            Err(e) => {
+                warn!("Failed to list managed containers: {}", e);
+                return Vec::new();
+            }
+        };
+        let results = futures_util::future::join_all(names.iter().map(|name| self.container_stats(name))).await;
+
+        results
+            .into_iter()
+            .zip(&names)
+            .filter_map(|(result, name)| match result {
+                Ok(stats) => Some(stats),
+                Err(e) => {
+                    warn!("Failed to get stats for container '{}': {}", name, e);
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Starts a managed container
     pub async fn start_container(&self, container_name: &str) -> Result<()> {
+        self.managed_container(container_name).await?;
+        self.start(container_name).await
+    }
+
+    /// Stops a managed container, unless it is protected
+    pub async fn stop_container(&self, container_name: &str) -> Result<()> {
+        self.unprotected_container(container_name).await?;
+        self.stop(container_name).await
+    }
+
+    /// Restarts a managed container, unless it is protected
+    pub async fn restart_container(&self, container_name: &str) -> Result<()> {
+        self.unprotected_container(container_name).await?;
+        self.restart(container_name).await
+    }
+
+    /// Starts all managed containers
+    pub async fn start_all_containers(&self) -> Result<()> {
+        info!("Starting all managed containers");
+
+        for name in self.container_names().await? {
+            if let Err(e) = self.start(&name).await {
+                error!("Failed to start container '{}': {}", name, e);
+                return Err(e);
+            }
+        }
+
+        info!("Successfully started all containers");
+        Ok(())
+    }
+
+    /// Stops all managed containers except the protected ones
+    pub async fn stop_all_containers(&self) -> Result<()> {
+        info!("Stopping all managed containers");
+
+        for name in self.unprotected_container_names().await? {
+            if let Err(e) = self.stop(&name).await {
+                error!("Failed to stop container '{}': {}", name, e);
+                return Err(e);
+            }
+        }
+
+        info!("Successfully stopped all containers");
+        Ok(())
+    }
+
+    /// Restarts all managed containers except the protected ones
+    pub async fn restart_all_containers(&self) -> Result<()> {
+        info!("Restarting all managed containers");
+
+        for name in self.unprotected_container_names().await? {
+            if let Err(e) = self.restart(&name).await {
+                error!("Failed to restart container '{}': {}", name, e);
+                return Err(e);
+            }
+        }
+
+        info!("Successfully restarted all containers");
+        Ok(())
+    }
+
+    /// Finds the managed service `container_name` is a container of
+    async fn managed_container(&self, container_name: &str) -> Result<ManagedService> {
+        self.services()
+            .await?
+            .into_iter()
+            .find(|service| service.containers.iter().any(|container| container == container_name))
+            .ok_or_else(|| MonitorError::UnknownContainer(container_name.to_string()))
+    }
+
+    /// Like `managed_container`, but refuses protected containers
+    async fn unprotected_container(&self, container_name: &str) -> Result<ManagedService> {
+        let service = self.managed_container(container_name).await?;
+        if self.is_protected(&service, container_name) {
+            warn!("Refusing to stop protected container: {}", container_name);
+            return Err(MonitorError::ProtectedContainer(container_name.to_string()));
+        }
+        Ok(service)
+    }
+
+    /// Managed containers that may be stopped
+    async fn unprotected_container_names(&self) -> Result<Vec<String>> {
+        let mut names = Vec::new();
+        for service in self.services().await? {
+            for container in &service.containers {
+                if self.is_protected(&service, container) {
+                    info!("Skipping protected container: {}", container);
+                } else {
+                    names.push(container.clone());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Whether a container is protected by its own name or its service's
+    fn is_protected(&self, service: &ManagedService, container_name: &str) -> bool {
+        self.protected
+            .iter()
+            .any(|name| *name == container_name || *name == service.name)
+    }
+
+    async fn start(&self, container_name: &str) -> Result<()> {
         info!("Starting container: {}", container_name);
 
-        self.docker
+        self.client().await?
             .start_container(container_name, None::<StartContainerOptions<String>>)
             .await
             .map_err(|e| MonitorError::Docker(format!(
@@ -217,14 +866,13 @@ impl DockerManager {
         Ok(())
     }
 
-    /// Stops a specific container
-    pub async fn stop_container(&self, container_name: &str) -> Result<()> {
+    async fn stop(&self, container_name: &str) -> Result<()> {
         info!("Stopping container: {}", container_name);
 
         // Give container 10 seconds to gracefully stop before force kill
         let options = StopContainerOptions { t: 10 };
 
-        self.docker
+        self.client().await?
             .stop_container(container_name, Some(options))
             .await
             .map_err(|e| MonitorError::Docker(format!(
@@ -236,14 +884,13 @@ impl DockerManager {
         Ok(())
     }
 
-    /// Restarts a specific container
-    pub async fn restart_container(&self, container_name: &str) -> Result<()> {
+    async fn restart(&self, container_name: &str) -> Result<()> {
         info!("Restarting container: {}", container_name);
 
         // Give container 10 seconds to gracefully stop before force kill
         let options = RestartContainerOptions { t: 10 };
 
-        self.docker
+        self.client().await?
             .restart_container(container_name, Some(options))
             .await
             .map_err(|e| MonitorError::Docker(format!(
@@ -255,190 +902,597 @@ impl DockerManager {
         Ok(())
     }
 
-    /// Starts all managed containers
-    pub async fn start_all_containers(&self) -> Result<()> {
-        info!("Starting all managed containers");
-
-        for name in &self.container_names {
-            if let Err(e) = self.start_container(name).await {
-                error!("Failed to start container '{}': {}", name, e);
-                return Err(e);
-            }
-        }
+    /// Reads the most recent log lines of a container
+    pub async fn container_logs(&self, container_name: &str, options: &LogOptions) -> Result<Vec<LogLine>> {
+        debug!("Reading logs for container: {}", container_name);
 
-        info!("Successfully started all containers");
-        Ok(())
-    }
-
-    /// Stops all managed containers
-    pub async fn stop_all_containers(&self) -> Result<()> {
-        info!("Stopping all managed containers");
+        let options = LogOptions {
+            follow: false,
+            ..options.clone()
+        };
 
-        for name in &self.container_names {
-            if let Err(e) = self.stop_container(name).await {
-                error!("Failed to stop container '{}': {}", name, e);
-                return Err(e);
-            }
+        let mut lines = Vec::new();
+        let mut stream = self.follow_container_logs(container_name, &options).await?;
+        while let Some(line) = stream.next().await {
+            lines.push(line?);
         }
 
-        info!("Successfully stopped all containers");
-        Ok(())
+        Ok(lines)
     }
 
-    /// Restarts all managed containers
-    pub async fn restart_all_containers(&self) -> Result<()> {
-        info!("Restarting all managed containers");
-
-        for name in &self.container_names {
-            if let Err(e) = self.restart_container(name).await {
-                error!("Failed to restart container '{}': {}", name, e);
-                return Err(e);
-            }
-        }
+    /// Streams the log lines of a managed container, following new output when `options.follow` is set
+    pub async fn follow_container_logs(
+        &self,
+        container_name: &str,
+        options: &LogOptions,
+    ) -> Result<impl Stream<Item = Result<LogLine>> + Send + 'static> {
+        self.managed_container(container_name).await?;
+        let name = container_name.to_string();
+        let options = LogsOptions::<String> {
+            follow: options.follow,
+            stdout: options.stdout,
+            stderr: options.stderr,
+            since: options.since,
+            until: 0,
+            timestamps: options.timestamps,
+            tail: options.tail.to_string(),
+        };
 
-        info!("Successfully restarted all containers");
-        Ok(())
+        Ok(self
+            .client()
+            .await?
+            .logs(&name, Some(options))
+            .map(move |chunk| match chunk {
+                Ok(output) => Ok(log_lines(output)),
+                Err(e) => Err(MonitorError::Docker(format!(
+                    "Failed to read logs for container '{}': {}",
+                    name, e
+                ))),
+            })
+            .flat_map(|lines| match lines {
+                Ok(lines) => stream::iter(lines.into_iter().map(Ok).collect::<Vec<_>>()),
+                Err(e) => stream::iter(vec![Err(e)]),
+            }))
     }
 
-    /// Pulls and restarts a single container using docker-compose
+    /// Pulls and restarts the service of a container using docker-compose
     pub async fn update_container(&self, container_name: &str) -> Result<UpdateResult> {
-        info!("Pulling and restarting container: {}", container_name);
+        self.update_container_streaming(container_name, |_| {}).await
+    }
+
+    /// Pulls and restarts the service of a container (or the named service itself),
+    /// passing each line of compose output to `on_line`. Protected services are refused.
+    pub async fn update_container_streaming<F>(&self, container_name: &str, on_line: F) -> Result<UpdateResult>
+    where
+        F: Fn(&str) + Send + Sync,
+    {
+        let service = self.resolve_updatable_service(container_name).await?;
+        info!("Pulling and restarting service: {}", service);
+
+        let services = vec![service.clone()];
+        let previous_images = self.snapshot_images(&services).await;
 
         // Step 1: Pull the latest image for the specific service
-        let pull_output = Command::new("docker")
-            .arg("compose")
-            .arg("-f")
-            .arg(&self.compose_file_path)
-            .arg("pull")
-            .arg(container_name)
-            .current_dir(&self.compose_dir)
-            .output()
-            .map_err(|e| MonitorError::Docker(format!(
-                "Failed to execute docker compose pull: {}",
-                e
-            )))?;
+        let pull_output = self.run_compose(&["pull", "--", &service], &on_line).await?;
 
-        let mut combined_output = String::from_utf8_lossy(&pull_output.stdout).to_string();
-        let pull_stderr = String::from_utf8_lossy(&pull_output.stderr).to_string();
+        let mut combined_output = pull_output.stdout;
 
-        if !pull_output.status.success() {
-            error!("Docker compose pull failed for '{}': {}", container_name, pull_stderr);
+        if !pull_output.success {
+            error!("Docker compose pull failed for '{}': {}", service, pull_output.stderr);
             return Ok(UpdateResult {
                 success: false,
                 output: combined_output,
-                error: Some(pull_stderr),
+                error: Some(pull_output.stderr),
+                rollbacks: Vec::new(),
             });
         }
 
         // Step 2: Start the container with the new image (without building)
-        let up_output = Command::new("docker")
-            .arg("compose")
-            .arg("-f")
-            .arg(&self.compose_file_path)
-            .arg("up")
-            .arg("-d")
-            .arg(container_name)
-            .current_dir(&self.compose_dir)
-            .output()
-            .map_err(|e| MonitorError::Docker(format!(
-                "Failed to execute docker compose up: {}",
-                e
-            )))?;
-
-        let up_stdout = String::from_utf8_lossy(&up_output.stdout).to_string();
-        let up_stderr = String::from_utf8_lossy(&up_output.stderr).to_string();
+        let up_output = self
+            .run_compose(&[&["up", "-d"], self.no_deps(), &["--", &service]].concat(), &on_line)
+            .await?;
 
-        combined_output.push_str("\n");
-        combined_output.push_str(&up_stdout);
+        combined_output.push('\n');
+        combined_output.push_str(&up_output.stdout);
 
-        if !up_output.status.success() {
-            error!("Docker compose up failed for '{}': {}", container_name, up_stderr);
+        if !up_output.success {
+            error!("Docker compose up failed for '{}': {}", service, up_output.stderr);
             return Ok(UpdateResult {
                 success: false,
                 output: combined_output,
-                error: Some(up_stderr),
+                error: Some(up_output.stderr),
+                rollbacks: Vec::new(),
             });
         }
 
-        info!("Successfully pulled and restarted container: {}", container_name);
+        let rollbacks = self.watch_and_roll_back(&services, &previous_images, &on_line).await;
+        if !rollbacks.is_empty() {
+            return Ok(rolled_back_result(combined_output, rollbacks));
+        }
+
+        info!("Successfully pulled and restarted service: {}", service);
         Ok(UpdateResult {
             success: true,
             output: combined_output,
             error: None,
+            rollbacks: Vec::new(),
         })
     }
 
     /// Pulls and restarts all containers using docker-compose
     pub async fn update_all_containers(&self) -> Result<UpdateResult> {
+        self.update_all_containers_streaming(|_| {}).await
+    }
+
+    /// Pulls and restarts all containers except the protected ones,
+    /// passing each line of compose output to `on_line`
+    pub async fn update_all_containers_streaming<F>(&self, on_line: F) -> Result<UpdateResult>
+    where
+        F: Fn(&str) + Send + Sync,
+    {
         info!("Pulling and restarting all containers");
 
-        // Execute: docker compose pull && docker compose up -d
+        let protected = self.protected_services();
+        let services: Vec<String> = self
+            .service_names()
+            .into_iter()
+            .filter(|service| !protected.contains(service))
+            .collect();
+        let mut notes = String::new();
+        if !protected.is_empty() {
+            let note = format!("Skipping protected service(s): {}", protected.join(", "));
+            info!("{}", note);
+            on_line(&note);
+            notes = format!("{}\n", note);
+        }
+        if services.is_empty() {
+            return Ok(UpdateResult {
+                success: true,
+                output: notes,
+                error: None,
+                rollbacks: Vec::new(),
+            });
+        }
+        let previous_images = self.snapshot_images(&services).await;
+
+        // Execute: docker compose pull && docker compose up -d, naming the
+        // services only when some are filtered out or protected
+        let filtered: Vec<&str> = match (&self.filter, protected.is_empty()) {
+            (ServiceFilter::All, true) => Vec::new(),
+            _ => services.iter().map(String::as_str).collect(),
+        };
 
         // Step 1: Pull latest images
-        let pull_output = Command::new("docker")
-            .arg("compose")
-            .arg("-f")
-            .arg(&self.compose_file_path)
-            .arg("pull")
-            .current_dir(&self.compose_dir)
-            .output()
-            .map_err(|e| MonitorError::Docker(format!(
-                "Failed to execute docker compose pull: {}",
-                e
-            )))?;
+        let pull_output = self.run_compose(&[&["pull", "--"], filtered.as_slice()].concat(), &on_line).await?;
 
-        if !pull_output.status.success() {
-            let stderr = String::from_utf8_lossy(&pull_output.stderr).to_string();
-            error!("Docker compose pull failed: {}", stderr);
+        if !pull_output.success {
+            error!("Docker compose pull failed: {}", pull_output.stderr);
             return Ok(UpdateResult {
                 success: false,
-                output: String::from_utf8_lossy(&pull_output.stdout).to_string(),
-                error: Some(stderr),
+                output: format!("{}{}", notes, pull_output.stdout),
+                error: Some(pull_output.stderr),
+                rollbacks: Vec::new(),
             });
         }
 
         debug!("Docker compose pull completed");
 
         // Step 2: Up without build
-        let up_output = Command::new("docker")
-            .arg("compose")
-            .arg("-f")
-            .arg(&self.compose_file_path)
-            .arg("up")
-            .arg("-d")
-            .current_dir(&self.compose_dir)
-            .output()
-            .map_err(|e| MonitorError::Docker(format!(
-                "Failed to execute docker compose up: {}",
-                e
-            )))?;
-
-        let stdout = String::from_utf8_lossy(&up_output.stdout).to_string();
-        let stderr = String::from_utf8_lossy(&up_output.stderr).to_string();
+        let up_output = self
+            .run_compose(&[&["up", "-d"], self.no_deps(), &["--"], filtered.as_slice()].concat(), &on_line)
+            .await?;
 
-        if !up_output.status.success() {
-            error!("Docker compose up failed: {}", stderr);
+        if !up_output.success {
+            error!("Docker compose up failed: {}", up_output.stderr);
             return Ok(UpdateResult {
                 success: false,
-                output: format!("{}\n{}",
-                    String::from_utf8_lossy(&pull_output.stdout),
-                    stdout
-                ),
-                error: Some(stderr),
+                output: format!("{}{}\n{}", notes, pull_output.stdout, up_output.stdout),
+                error: Some(up_output.stderr),
+                rollbacks: Vec::new(),
             });
         }
 
+        let output = format!("{}{}\n{}", notes, pull_output.stdout, up_output.stdout);
+
+        let rollbacks = self
+            .watch_and_roll_back(&services, &previous_images, &on_line)
+            .await;
+        if !rollbacks.is_empty() {
+            return Ok(rolled_back_result(output, rollbacks));
+        }
+
         info!("Successfully pulled and restarted all containers");
         Ok(UpdateResult {
             success: true,
-            output: format!("{}\n{}",
-                String::from_utf8_lossy(&pull_output.stdout),
-                stdout
-            ),
+            output,
             error: None,
+            rollbacks: Vec::new(),
         })
     }
 
+    /// Records the image each service is currently running before an automatic-rollback update
+    async fn snapshot_images(&self, services: &[String]) -> HashMap<String, ImageSnapshot> {
+        if self.rollback_grace_period.is_none() {
+            return HashMap::new();
+        }
+        self.current_images(services).await
+    }
+
+    /// Returns the image each of `services` is currently running (by its first replica),
+    /// skipping missing containers
+    pub async fn current_images(&self, services: &[String]) -> HashMap<String, ImageSnapshot> {
+        let mut snapshots = HashMap::new();
+        let containers = self.service_containers().await;
+
+        for service in services {
+            let Some(container) = containers.get(service).and_then(|containers| containers.first()) else {
+                debug!("No previous image recorded for '{}': no container", service);
+                continue;
+            };
+            match self.inspect(container).await {
+                Ok(inspect) => {
+                    let image_ref = inspect.config.as_ref().and_then(|config| config.image.clone());
+                    if let (Some(image_id), Some(image_ref)) = (inspect.image, image_ref) {
+                        snapshots.insert(service.clone(), ImageSnapshot { image_id, image_ref });
+                    }
+                }
+                Err(e) => debug!("No previous image recorded for '{}': {}", service, e),
+            }
+        }
+
+        snapshots
+    }
+
+    /// Containers of each managed service; empty if they cannot be listed
+    async fn service_containers(&self) -> HashMap<String, Vec<String>> {
+        match self.services().await {
+            Ok(services) => services
+                .into_iter()
+                .map(|service| (service.name, service.containers))
+                .collect(),
+            Err(e) => {
+                warn!("Failed to list managed containers: {}", e);
+                HashMap::new()
+            }
+        }
+    }
+
+    /// Watches updated services for the grace period and rolls back the ones that fail
+    async fn watch_and_roll_back<F>(
+        &self,
+        services: &[String],
+        previous_images: &HashMap<String, ImageSnapshot>,
+        on_line: &F,
+    ) -> Vec<Rollback>
+    where
+        F: Fn(&str) + Send + Sync,
+    {
+        let Some(grace_period) = self.rollback_grace_period else {
+            return Vec::new();
+        };
+
+        on_line(&format!("Watching {} service(s) for {}s after update", services.len(), grace_period.as_secs()));
+        info!("Watching {:?} for {:?} after update", services, grace_period);
+
+        let deadline = Instant::now() + grace_period;
+        let containers = self.service_containers().await;
+        let mut restart_baseline: HashMap<String, i64> = HashMap::new();
+        let mut failures: Vec<(String, String, String)> = Vec::new();
+
+        loop {
+            for service in services {
+                if failures.iter().any(|(failed, _, _)| failed == service) {
+                    continue;
+                }
+
+                // A service fails as soon as one of its replicas does
+                let replicas = containers
+                    .get(service)
+                    .filter(|replicas| !replicas.is_empty())
+                    .cloned()
+                    .unwrap_or_else(|| vec![service.clone()]);
+                for container in &replicas {
+                    let (reason, image) = match self.inspect(container).await {
+                        Ok(inspect) => {
+                            let restarts = inspect.restart_count.unwrap_or(0);
+                            let baseline = *restart_baseline.entry(container.clone()).or_insert(restarts);
+                            match failure_reason(&inspect, baseline) {
+                                Some(reason) => (reason, inspect.image.unwrap_or_default()),
+                                None => continue,
+                            }
+                        }
+                        Err(e) => (e.to_string(), String::new()),
+                    };
+                    let reason = if replicas.len() > 1 {
+                        format!("{}: {}", container, reason)
+                    } else {
+                        reason
+                    };
+                    warn!("Service '{}' failed after update: {}", service, reason);
+                    on_line(&format!("Service '{}' failed after update: {}", service, reason));
+                    failures.push((service.clone(), reason, image));
+                    break;
+                }
+            }
+
+            if failures.len() == services.len() || Instant::now() >= deadline {
+                break;
+            }
+            tokio::time::sleep(ROLLBACK_POLL_INTERVAL).await;
+        }
+
+        let mut rollbacks = Vec::new();
+        for (service, reason, failed_image) in failures {
+            let Some(previous) = previous_images.get(&service) else {
+                warn!("No previous image known for '{}', cannot roll back", service);
+                continue;
+            };
+            if previous.image_id == failed_image {
+                warn!("Service '{}' is still on its previous image, nothing to roll back", service);
+                continue;
+            }
+
+            on_line(&format!("Rolling back '{}' to {}", service, previous.image_id));
+            let result = self.restore_image(&service, previous, on_line).await;
+            rollbacks.push(Rollback {
+                service,
+                reason,
+                failed_image,
+                restored_image: previous.image_id.clone(),
+                success: result.is_ok(),
+                error: result.err().map(|e| e.to_string()),
+            });
+        }
+
+        rollbacks
+    }
+
+    /// Puts each service back on a previously recorded image
+    pub async fn restore_images<F>(&self, images: &HashMap<String, ImageSnapshot>, on_line: F) -> UpdateResult
+    where
+        F: Fn(&str) + Send + Sync,
+    {
+        let mut services: Vec<&String> = images.keys().collect();
+        services.sort();
+
+        let mut output = String::new();
+        let mut errors = Vec::new();
+        let protected = self.protected_services();
+
+        for service in services {
+            if protected.contains(service) {
+                info!("Not restoring protected service '{}'", service);
+                output.push_str(&format!("Skipped protected service '{}'\n", service));
+                continue;
+            }
+            let image = &images[service];
+            info!("Restoring '{}' to {} ({})", service, image.image_id, image.image_ref);
+            match self.restore_image(service, image, &on_line).await {
+                Ok(()) => output.push_str(&format!("Restored '{}' to {}\n", service, image.image_id)),
+                Err(e) => {
+                    error!("Failed to restore '{}': {}", service, e);
+                    errors.push(e.to_string());
+                }
+            }
+        }
+
+        UpdateResult {
+            success: errors.is_empty(),
+            output,
+            error: (!errors.is_empty()).then(|| errors.join("\n")),
+            rollbacks: Vec::new(),
+        }
+    }
+
+    /// Points the service's image reference back at a previous image and recreates it
+    async fn restore_image<F>(&self, service: &str, previous: &ImageSnapshot, on_line: &F) -> Result<()>
+    where
+        F: Fn(&str) + Send + Sync,
+    {
+        let (repo, tag) = split_image_ref(&previous.image_ref);
+
+        self.client().await?
+            .tag_image(
+                &previous.image_id,
+                Some(TagImageOptions {
+                    repo: repo.to_string(),
+                    tag: tag.to_string(),
+                }),
+            )
+            .await
+            .map_err(|e| MonitorError::Docker(format!(
+                "Failed to re-tag {} as {}: {}",
+                previous.image_id, previous.image_ref, e
+            )))?;
+
+        let up_output = self
+            .run_compose(&[&["up", "-d"], self.no_deps(), &["--", service]].concat(), on_line)
+            .await?;
+        if !up_output.success {
+            return Err(MonitorError::Docker(format!(
+                "docker compose up failed while rolling back '{}': {}",
+                service, up_output.stderr
+            )));
+        }
+
+        info!("Rolled back '{}' to {}", service, previous.image_id);
+        Ok(())
+    }
+
+    /// Runs `docker compose -f <file> <args>`, reading stdout and stderr line by line.
+    /// The process is killed if it outlives the compose timeout or the returned future is dropped.
+    async fn run_compose<F>(&self, args: &[&str], on_line: &F) -> Result<ComposeOutput>
+    where
+        F: Fn(&str) + Send + Sync,
+    {
+        debug!("Running docker compose {}", args.join(" "));
+
+        if let Some(tunnel) = &self.tunnel {
+            tunnel.ensure_open().await?;
+        }
+        let mut command = Command::new("docker");
+        self.endpoint.configure(&mut command);
+        let mut child = command
+            .arg("compose")
+            .arg("-f")
+            .arg(&self.compose_file_path)
+            .args(args)
+            .current_dir(&self.compose_dir)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| MonitorError::Docker(format!(
+                "Failed to execute docker compose {}: {}",
+                args.first().unwrap_or(&""),
+                e
+            )))?;
+
+        let mut stdout_lines = BufReader::new(child.stdout.take().expect("stdout is piped")).lines();
+        let mut stderr_lines = BufReader::new(child.stderr.take().expect("stderr is piped")).lines();
+
+        let mut stdout = String::new();
+        let mut stderr = String::new();
+
+        let collect = async {
+            let mut stdout_done = false;
+            let mut stderr_done = false;
+
+            // Compose writes its progress to stderr, so both streams are forwarded as they arrive
+            while !(stdout_done && stderr_done) {
+                tokio::select! {
+                    line = stdout_lines.next_line(), if !stdout_done => match line? {
+                        Some(line) => {
+                            on_line(&line);
+                            stdout.push_str(&line);
+                            stdout.push('\n');
+                        }
+                        None => stdout_done = true,
+                    },
+                    line = stderr_lines.next_line(), if !stderr_done => match line? {
+                        Some(line) => {
+                            on_line(&line);
+                            stderr.push_str(&line);
+                            stderr.push('\n');
+                        }
+                        None => stderr_done = true,
+                    },
+                }
+            }
+
+            child.wait().await
+        };
+
+        let status = match tokio::time::timeout(self.compose_timeout, collect).await {
+            Ok(status) => status?,
+            Err(_) => {
+                let _ = child.kill().await;
+                let command = args.first().unwrap_or(&"");
+                warn!("docker compose {} timed out after {:?}, killed", command, self.compose_timeout);
+                return Err(MonitorError::Docker(format!(
+                    "docker compose {} timed out after {}s",
+                    command,
+                    self.compose_timeout.as_secs()
+                )));
+            }
+        };
+
+        Ok(ComposeOutput {
+            success: status.success(),
+            stdout,
+            stderr,
+        })
+    }
+
+}
+
+/// Returns why a freshly updated container counts as failed, if it does
+fn failure_reason(inspect: &ContainerInspectResponse, restart_baseline: i64) -> Option<String> {
+    let state = inspect.state.as_ref()?;
+
+    let status: ContainerStatus = state.status.unwrap_or(ContainerStateStatusEnum::EMPTY).into();
+    match status {
+        ContainerStatus::Dead => return Some("container is dead".to_string()),
+        ContainerStatus::Restarting => return Some("container is restarting".to_string()),
+        _ => {}
+    }
+
+    let restarts = inspect.restart_count.unwrap_or(0);
+    if restarts > restart_baseline {
+        return Some(format!("container restarted {} time(s)", restarts - restart_baseline));
+    }
+
+    let health = state.health.as_ref().and_then(|health| health.status);
+    if health == Some(HealthStatusEnum::UNHEALTHY) {
+        return Some("container is unhealthy".to_string());
+    }
+
+    None
+}
+
+/// Splits an image reference into repository and tag, defaulting the tag to "latest"
+fn split_image_ref(image_ref: &str) -> (&str, &str) {
+    // Digest references cannot be re-tagged, so drop the digest
+    let image_ref = image_ref.split('@').next().unwrap_or(image_ref);
+
+    // A colon only separates the tag if it comes after the last slash (registry ports)
+    match image_ref.rfind(':') {
+        Some(colon) if colon > image_ref.rfind('/').unwrap_or(0) => (&image_ref[..colon], &image_ref[colon + 1..]),
+        _ => (image_ref, "latest"),
+    }
+}
+
+/// Builds the result of an update in which some services had to be rolled back
+fn rolled_back_result(output: String, rollbacks: Vec<Rollback>) -> UpdateResult {
+    let summary = rollbacks
+        .iter()
+        .map(|rollback| {
+            if rollback.success {
+                format!("'{}' rolled back ({})", rollback.service, rollback.reason)
+            } else {
+                format!(
+                    "'{}' failed ({}) and could not be rolled back: {}",
+                    rollback.service,
+                    rollback.reason,
+                    rollback.error.as_deref().unwrap_or("unknown error")
+                )
+            }
+        })
+        .collect::<Vec<_>>()
+        .join("; ");
+
+    error!("Update failed health checks: {}", summary);
+    UpdateResult {
+        success: false,
+        output,
+        error: Some(format!("Update failed health checks: {}", summary)),
+        rollbacks,
+    }
+}
+
+/// Splits a chunk of log output into lines, tagged with the stream they came from
+fn log_lines(output: LogOutput) -> Vec<LogLine> {
+    let (stream, message) = match output {
+        LogOutput::StdErr { message } => (LogStream::Stderr, message),
+        LogOutput::StdOut { message }
+        | LogOutput::StdIn { message }
+        | LogOutput::Console { message } => (LogStream::Stdout, message),
+    };
+
+    String::from_utf8_lossy(&message)
+        .lines()
+        .map(|line| LogLine {
+            stream,
+            message: line.to_string(),
+        })
+        .collect()
+}
+
+/// Captured output of a docker compose invocation
+struct ComposeOutput {
+    success: bool,
+    stdout: String,
+    stderr: String,
 }
 
 #[cfg(test)]
@@ -460,4 +1514,220 @@ mod tests {
         let status: ContainerStatus = ContainerStateStatusEnum::EXITED.into();
         assert_eq!(status, ContainerStatus::Stopped);
     }
+
+    #[test]
+    fn test_split_image_ref() {
+        assert_eq!(split_image_ref("nginx"), ("nginx", "latest"));
+        assert_eq!(split_image_ref("nginx:1.25"), ("nginx", "1.25"));
+        assert_eq!(split_image_ref("registry:5000/app"), ("registry:5000/app", "latest"));
+        assert_eq!(split_image_ref("registry:5000/app:v2"), ("registry:5000/app", "v2"));
+        assert_eq!(split_image_ref("app:v2@sha256:abc"), ("app", "v2"));
+    }
+
+    #[test]
+    fn test_failure_reason() {
+        use bollard::models::Health;
+
+        let inspect = |status, health, restarts| ContainerInspectResponse {
+            state: Some(ContainerState {
+                status: Some(status),
+                health: Some(Health {
+                    status: Some(health),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            restart_count: Some(restarts),
+            ..Default::default()
+        };
+
+        let healthy = inspect(ContainerStateStatusEnum::RUNNING, HealthStatusEnum::HEALTHY, 0);
+        assert_eq!(failure_reason(&healthy, 0), None);
+
+        let unhealthy = inspect(ContainerStateStatusEnum::RUNNING, HealthStatusEnum::UNHEALTHY, 0);
+        assert!(failure_reason(&unhealthy, 0).is_some());
+
+        let restarting = inspect(ContainerStateStatusEnum::RESTARTING, HealthStatusEnum::NONE, 0);
+        assert!(failure_reason(&restarting, 0).is_some());
+
+        // Restarts before the update started do not count
+        let crash_looped = inspect(ContainerStateStatusEnum::RUNNING, HealthStatusEnum::NONE, 3);
+        assert_eq!(failure_reason(&crash_looped, 3), None);
+        assert!(failure_reason(&crash_looped, 1).is_some());
+    }
+
+    #[test]
+    fn test_container_health() {
+        use bollard::models::{Health, HealthcheckResult};
+
+        let state = |health| ContainerState {
+            health: Some(health),
+            ..Default::default()
+        };
+
+        let unhealthy = state(Health {
+            status: Some(HealthStatusEnum::UNHEALTHY),
+            failing_streak: Some(4),
+            log: Some(vec![
+                HealthcheckResult {
+                    output: Some("ok".to_string()),
+                    ..Default::default()
+                },
+                HealthcheckResult {
+                    output: Some("connection refused\n".to_string()),
+                    ..Default::default()
+                },
+            ]),
+        });
+        assert_eq!(
+            ContainerHealth::from_state(&unhealthy),
+            Some(ContainerHealth {
+                status: HealthStatus::Unhealthy,
+                failing_streak: 4,
+                last_output: Some("connection refused".to_string()),
+            })
+        );
+
+        let starting = state(Health {
+            status: Some(HealthStatusEnum::STARTING),
+            ..Default::default()
+        });
+        assert_eq!(ContainerHealth::from_state(&starting).unwrap().status, HealthStatus::Starting);
+
+        // Containers without a healthcheck
+        let none = state(Health {
+            status: Some(HealthStatusEnum::NONE),
+            ..Default::default()
+        });
+        assert_eq!(ContainerHealth::from_state(&none), None);
+        assert_eq!(ContainerHealth::from_state(&ContainerState::default()), None);
+    }
+
+    #[test]
+    fn test_container_stats() {
+        let stats: Stats = serde_json::from_value(serde_json::json!({
+            "read": "2024-05-01T12:00:01Z",
+            "preread": "2024-05-01T12:00:00Z",
+            "num_procs": 0,
+            "pids_stats": {},
+            "networks": {
+                "eth0": {"rx_bytes": 1000, "tx_bytes": 200, "rx_packets": 0, "tx_packets": 0,
+                         "rx_errors": 0, "tx_errors": 0, "rx_dropped": 0, "tx_dropped": 0},
+                "eth1": {"rx_bytes": 24, "tx_bytes": 0, "rx_packets": 0, "tx_packets": 0,
+                         "rx_errors": 0, "tx_errors": 0, "rx_dropped": 0, "tx_dropped": 0}
+            },
+            "memory_stats": {"usage": 256, "limit": 1024},
+            "blkio_stats": {"io_service_bytes_recursive": [
+                {"major": 8, "minor": 0, "op": "read", "value": 4096},
+                {"major": 8, "minor": 0, "op": "Write", "value": 512},
+                {"major": 8, "minor": 0, "op": "Total", "value": 4608}
+            ]},
+            "cpu_stats": {
+                "cpu_usage": {"total_usage": 1_500_000, "usage_in_usermode": 0, "usage_in_kernelmode": 0},
+                "system_cpu_usage": 20_000_000,
+                "online_cpus": 4,
+                "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+            },
+            "precpu_stats": {
+                "cpu_usage": {"total_usage": 1_000_000, "usage_in_usermode": 0, "usage_in_kernelmode": 0},
+                "system_cpu_usage": 10_000_000,
+                "throttling_data": {"periods": 0, "throttled_periods": 0, "throttled_time": 0}
+            },
+            "storage_stats": {}
+        }))
+        .unwrap();
+
+        let sample = ContainerStats::from_stats("web", &stats);
+        assert_eq!(sample.name, "web");
+        assert!((sample.cpu_percent - 20.0).abs() < 1e-9);
+        assert_eq!((sample.memory_usage, sample.memory_limit), (256, 1024));
+        assert!((sample.memory_percent - 25.0).abs() < 1e-9);
+        assert_eq!((sample.network_rx, sample.network_tx), (1024, 200));
+        assert_eq!((sample.block_read, sample.block_write), (4096, 512));
+    }
+
+    #[test]
+    fn test_log_lines() {
+        let lines = log_lines(LogOutput::StdErr {
+            message: "first\nsecond\n".into(),
+        });
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].stream, LogStream::Stderr);
+        assert_eq!(lines[1].message, "second");
+    }
+
+    /// A manager for the services web, db, cache (container app-cache) and worker, with
+    /// db and app-cache protected. Without a compose project name nothing is sent to Docker.
+    async fn protected_manager(name: &str, filter: ServiceFilter, project: Option<&str>) -> DockerManager {
+        // The client only needs the socket to exist; nothing is sent to it
+        let socket = std::env::temp_dir().join(format!("ghd-test-{}-{}.sock", name, std::process::id()));
+        std::fs::write(&socket, "").unwrap();
+        let endpoint = DockerEndpoint {
+            host: DockerHost::Unix(socket.to_string_lossy().into_owned()),
+            context: None,
+        };
+        let manager = DockerManager::new("/srv/app/docker-compose.yml", filter, None, Duration::from_secs(60), endpoint)
+            .await
+            .unwrap()
+            .with_protected(vec!["db".to_string(), "app-cache".to_string()]);
+        std::fs::remove_file(&socket).unwrap();
+        *manager.project.write().unwrap() = ComposeProject {
+            name: project.map(str::to_string),
+            services: ["web", "db", "cache", "worker"]
+                .into_iter()
+                .map(|name| ComposeService {
+                    name: name.to_string(),
+                    container_name: (name == "cache").then(|| "app-cache".to_string()),
+                })
+                .collect(),
+        };
+        manager
+    }
+
+    #[tokio::test]
+    async fn test_protected_services() {
+        let filter = ServiceFilter::Except(vec!["worker".to_string()]);
+        let manager = protected_manager("protected", filter, Some("app")).await;
+
+        // By service name and by container_name
+        assert_eq!(manager.protected_services(), vec!["db", "cache"]);
+        assert_eq!(manager.no_deps(), ["--no-deps"]);
+
+        let manager = manager.with_protected_updates(true);
+        assert!(manager.protected_services().is_empty());
+        assert!(manager.no_deps().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resolve_managed_services() {
+        let manager = protected_manager("resolve", ServiceFilter::Except(vec!["worker".to_string()]), None).await;
+
+        // By service name or container name, only among the managed services
+        assert_eq!(manager.resolve_service("web").await.unwrap(), "web");
+        assert_eq!(manager.resolve_service("app-cache").await.unwrap(), "cache");
+        for name in ["worker", "missing"] {
+            let err = manager.resolve_service(name).await.unwrap_err();
+            assert!(matches!(err, MonitorError::UnknownContainer(_)), "{}: {}", name, err);
+        }
+
+        // Protected services may not be updated, by either name
+        assert_eq!(manager.resolve_updatable_service("web").await.unwrap(), "web");
+        for name in ["db", "app-cache", "cache"] {
+            let err = manager.resolve_updatable_service(name).await.unwrap_err();
+            assert!(matches!(err, MonitorError::ProtectedContainer(_)), "{}: {}", name, err);
+        }
+        assert!(matches!(
+            manager.resolve_updatable_service("worker").await,
+            Err(MonitorError::UnknownContainer(_))
+        ));
+
+        // ... nor stopped, and are left out when stopping everything
+        assert_eq!(manager.unprotected_container("web").await.unwrap().name, "web");
+        assert!(matches!(manager.unprotected_container("db").await, Err(MonitorError::ProtectedContainer(_))));
+        assert!(matches!(manager.unprotected_container("worker").await, Err(MonitorError::UnknownContainer(_))));
+        assert_eq!(manager.unprotected_container_names().await.unwrap(), vec!["web"]);
+
+        let manager = manager.with_protected_updates(true);
+        assert_eq!(manager.resolve_updatable_service("db").await.unwrap(), "db");
+    }
 }
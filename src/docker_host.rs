@@ -0,0 +1,445 @@
+use crate::error::{MonitorError, Result};
+use bollard::{Docker, API_DEFAULT_VERSION};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::io::AsyncReadExt;
+use tokio::process::{Child, Command};
+use tokio::sync::Mutex;
+use tracing::{debug, info, warn};
+
+/// Seconds a Docker API request may take, as bollard's own defaults
+const REQUEST_TIMEOUT: u64 = 120;
+
+/// How long to wait for an SSH tunnel to come up
+const TUNNEL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Socket of the Docker daemon on an SSH host when the URL names none
+const DEFAULT_REMOTE_SOCKET: &str = "/var/run/docker.sock";
+
+/// Distinguishes the tunnel sockets of several projects in one process
+static NEXT_TUNNEL: AtomicU64 = AtomicU64::new(1);
+
+/// Where the Docker daemon listens, in `DOCKER_HOST` syntax
+#[derive(Debug, Clone, PartialEq)]
+pub enum DockerHost {
+    /// unix:///var/run/docker.sock
+    Unix(String),
+    /// tcp://host:port, with TLS when `cert_path` holds `ca.pem`, `cert.pem` and `key.pem`
+    Tcp { address: String, cert_path: Option<String> },
+    /// ssh://[user@]host[:port][/socket], reached through a tunnel opened by the `ssh` client
+    Ssh {
+        destination: String,
+        port: Option<u16>,
+        socket: Option<String>,
+    },
+}
+
+impl DockerHost {
+    /// Parses a `DOCKER_HOST` URL; a bare path is taken as a Unix socket.
+    /// `cert_path` turns a TCP connection into a TLS one.
+    pub fn parse(url: &str, cert_path: Option<&str>) -> std::result::Result<Self, String> {
+        let url = url.trim();
+        if let Some(address) = url.strip_prefix("tcp://") {
+            let address = address.trim_end_matches('/');
+            if address.is_empty() {
+                return Err(format!("missing host in '{}'", url));
+            }
+            return Ok(DockerHost::Tcp {
+                address: address.to_string(),
+                cert_path: cert_path.map(str::to_string),
+            });
+        }
+        if let Some(rest) = url.strip_prefix("ssh://") {
+            let (authority, socket) = match rest.find('/') {
+                Some(index) => (&rest[..index], Some(rest[index..].to_string())),
+                None => (rest, None),
+            };
+            // The port follows the last ':' of the authority; user names cannot contain one
+            let (destination, port) = match authority.rsplit_once(':') {
+                Some((destination, port)) => (
+                    destination,
+                    Some(port.parse().map_err(|_| format!("invalid port in '{}'", url))?),
+                ),
+                None => (authority, None),
+            };
+            if destination.is_empty() || destination.ends_with('@') || destination.starts_with('-') {
+                return Err(format!("missing host in '{}'", url));
+            }
+            return Ok(DockerHost::Ssh {
+                destination: destination.to_string(),
+                port,
+                socket: socket.filter(|socket| socket != "/"),
+            });
+        }
+        let path = url.strip_prefix("unix://").unwrap_or(url);
+        if !path.starts_with('/') {
+            return Err(format!(
+                "unsupported Docker host '{}' (expected unix://, tcp:// or ssh://)",
+                url
+            ));
+        }
+        Ok(DockerHost::Unix(path.to_string()))
+    }
+
+    /// Looks up the endpoint of a `docker context`
+    pub async fn from_context(name: &str) -> Result<Self> {
+        let output = Command::new("docker")
+            .args([
+                "context",
+                "inspect",
+                "--format",
+                "{{.Endpoints.docker.Host}}\t{{.Storage.TLSPath}}",
+                "--",
+                name,
+            ])
+            .stdin(Stdio::null())
+            .kill_on_drop(true)
+            .output()
+            .await
+            .map_err(|e| MonitorError::Docker(format!("Failed to execute docker context inspect: {}", e)))?;
+        if !output.status.success() {
+            return Err(MonitorError::Docker(format!(
+                "Docker context '{}' not found: {}",
+                name,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        let (host, tls_path) = output.trim_end().split_once('\t').unwrap_or((output.trim_end(), ""));
+        // Contexts keep their client certificates in <TLSPath>/docker
+        let cert_path = Path::new(tls_path).join("docker");
+        let cert_path = cert_path
+            .join("ca.pem")
+            .exists()
+            .then(|| cert_path.to_string_lossy().into_owned());
+        DockerHost::parse(host, cert_path.as_deref())
+            .map_err(|e| MonitorError::Docker(format!("Docker context '{}': {}", name, e)))
+    }
+
+    /// Connects to the daemon; for SSH hosts this opens a tunnel that lives as long as the connection
+    pub async fn connect(&self) -> Result<DockerConnection> {
+        let connect_error = |e: bollard::errors::Error| {
+            MonitorError::Docker(format!("Failed to connect to Docker daemon at {}: {}", self, e))
+        };
+
+        let (docker, tunnel) = match self {
+            DockerHost::Unix(path) => (
+                Docker::connect_with_unix(path, REQUEST_TIMEOUT, API_DEFAULT_VERSION).map_err(connect_error)?,
+                None,
+            ),
+            DockerHost::Tcp { address, cert_path: None } => (
+                Docker::connect_with_http(address, REQUEST_TIMEOUT, API_DEFAULT_VERSION).map_err(connect_error)?,
+                None,
+            ),
+            DockerHost::Tcp {
+                address,
+                cert_path: Some(cert_path),
+            } => {
+                let cert_path = Path::new(cert_path);
+                let docker = Docker::connect_with_ssl(
+                    address,
+                    &cert_path.join("key.pem"),
+                    &cert_path.join("cert.pem"),
+                    &cert_path.join("ca.pem"),
+                    REQUEST_TIMEOUT,
+                    API_DEFAULT_VERSION,
+                )
+                .map_err(connect_error)?;
+                (docker, None)
+            }
+            DockerHost::Ssh {
+                destination,
+                port,
+                socket,
+            } => {
+                let tunnel =
+                    SshTunnel::open(destination, *port, socket.as_deref().unwrap_or(DEFAULT_REMOTE_SOCKET)).await?;
+                let docker = Docker::connect_with_unix(
+                    &tunnel.socket.to_string_lossy(),
+                    REQUEST_TIMEOUT,
+                    API_DEFAULT_VERSION,
+                )
+                .map_err(connect_error)?;
+                (docker, Some(tunnel))
+            }
+        };
+        Ok(DockerConnection { docker, tunnel })
+    }
+}
+
+impl fmt::Display for DockerHost {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DockerHost::Unix(path) => write!(f, "unix://{}", path),
+            DockerHost::Tcp { address, .. } => write!(f, "tcp://{}", address),
+            DockerHost::Ssh {
+                destination,
+                port,
+                socket,
+            } => {
+                write!(f, "ssh://{}", destination)?;
+                if let Some(port) = port {
+                    write!(f, ":{}", port)?;
+                }
+                write!(f, "{}", socket.as_deref().unwrap_or(""))
+            }
+        }
+    }
+}
+
+/// The daemon a project's containers run on, for the API client and the `docker` CLI
+#[derive(Debug, Clone, PartialEq)]
+pub struct DockerEndpoint {
+    pub host: DockerHost,
+    /// `docker context` the CLI is pointed at instead of `DOCKER_HOST`
+    pub context: Option<String>,
+}
+
+impl DockerEndpoint {
+    /// Uses the host of `context` when one is given, otherwise `socket`
+    pub async fn resolve(socket: &str, cert_path: Option<&str>, context: Option<&str>) -> Result<Self> {
+        match context {
+            Some(context) => Ok(Self {
+                host: DockerHost::from_context(context).await?,
+                context: Some(context.to_string()),
+            }),
+            None => Ok(Self {
+                host: DockerHost::parse(socket, cert_path).map_err(MonitorError::Config)?,
+                context: None,
+            }),
+        }
+    }
+
+    /// Points a `docker` CLI command at this endpoint, overriding the service's own environment
+    pub fn configure(&self, command: &mut Command) {
+        for (name, value) in self.cli_env() {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
+        }
+    }
+
+    /// Environment for the `docker` CLI; `None` removes the variable
+    fn cli_env(&self) -> Vec<(&'static str, Option<String>)> {
+        if let Some(context) = &self.context {
+            // DOCKER_HOST would take precedence over the context
+            return vec![
+                ("DOCKER_CONTEXT", Some(context.clone())),
+                ("DOCKER_HOST", None),
+                ("DOCKER_TLS_VERIFY", None),
+                ("DOCKER_CERT_PATH", None),
+            ];
+        }
+
+        let cert_path = match &self.host {
+            DockerHost::Tcp { cert_path, .. } => cert_path.clone(),
+            _ => None,
+        };
+        vec![
+            ("DOCKER_HOST", Some(self.host.to_string())),
+            ("DOCKER_CONTEXT", None),
+            ("DOCKER_TLS_VERIFY", cert_path.is_some().then(|| "1".to_string())),
+            ("DOCKER_CERT_PATH", cert_path),
+        ]
+    }
+}
+
+/// A Docker API client, with the SSH tunnel it talks through
+pub struct DockerConnection {
+    pub docker: Docker,
+    pub tunnel: Option<SshTunnel>,
+}
+
+/// `ssh -L` forwarding a local Unix socket to the daemon socket on an SSH host.
+/// The ssh process is reopened on the same socket when it has exited, and killed
+/// with the socket removed when the tunnel is dropped.
+pub struct SshTunnel {
+    destination: String,
+    port: Option<u16>,
+    remote_socket: String,
+    socket: PathBuf,
+    child: Mutex<Child>,
+}
+
+impl SshTunnel {
+    async fn open(destination: &str, port: Option<u16>, remote_socket: &str) -> Result<Self> {
+        let socket = std::env::temp_dir().join(format!(
+            "ghd-docker-{}-{}.sock",
+            std::process::id(),
+            NEXT_TUNNEL.fetch_add(1, Ordering::SeqCst)
+        ));
+        info!("Opening SSH tunnel to Docker on {}", destination);
+        let child = spawn_tunnel(destination, port, remote_socket, &socket).await?;
+        Ok(Self {
+            destination: destination.to_string(),
+            port,
+            remote_socket: remote_socket.to_string(),
+            socket,
+            child: Mutex::new(child),
+        })
+    }
+
+    /// Reopens the tunnel if ssh has exited, e.g. after the connection dropped.
+    /// Fails with a "tunnel down" error when it cannot be re-established; the next call tries again.
+    pub async fn ensure_open(&self) -> Result<()> {
+        let mut child = self.child.lock().await;
+        let Some(status) = child.try_wait()? else {
+            return Ok(());
+        };
+
+        warn!("SSH tunnel to {} exited ({}), reopening", self.destination, status);
+        *child = spawn_tunnel(&self.destination, self.port, &self.remote_socket, &self.socket)
+            .await
+            .map_err(|e| match e {
+                MonitorError::Docker(message) => MonitorError::Docker(format!("Tunnel down: {}", message)),
+                other => other,
+            })?;
+        info!("SSH tunnel to {} reopened", self.destination);
+        Ok(())
+    }
+
+    /// Local socket forwarded to the remote daemon
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+}
+
+/// Starts `ssh -L` and waits until it listens on `socket`
+async fn spawn_tunnel(destination: &str, port: Option<u16>, remote_socket: &str, socket: &Path) -> Result<Child> {
+    // A socket left behind by an earlier ssh would make the forward fail
+    let _ = std::fs::remove_file(socket);
+
+    let mut command = Command::new("ssh");
+    command
+        .args(["-nNT", "-o", "BatchMode=yes", "-o", "ExitOnForwardFailure=yes", "-o", "ServerAliveInterval=30"])
+        .arg("-L")
+        .arg(format!("{}:{}", socket.display(), remote_socket));
+    if let Some(port) = port {
+        command.arg("-p").arg(port.to_string());
+    }
+    let mut child = command
+        .arg("--")
+        .arg(destination)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| MonitorError::Docker(format!("Failed to execute ssh: {}", e)))?;
+
+    let deadline = Instant::now() + TUNNEL_TIMEOUT;
+    while !socket.exists() {
+        if let Some(status) = child.try_wait()? {
+            let mut stderr = String::new();
+            if let Some(mut pipe) = child.stderr.take() {
+                let _ = pipe.read_to_string(&mut stderr).await;
+            }
+            return Err(MonitorError::Docker(format!(
+                "SSH tunnel to {} failed ({}): {}",
+                destination,
+                status,
+                stderr.trim()
+            )));
+        }
+        if Instant::now() >= deadline {
+            return Err(MonitorError::Docker(format!(
+                "SSH tunnel to {} did not open within {}s",
+                destination,
+                TUNNEL_TIMEOUT.as_secs()
+            )));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    debug!("SSH tunnel to {} listening on {}", destination, socket.display());
+    Ok(child)
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.socket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_docker_host() {
+        assert_eq!(
+            DockerHost::parse("unix:///var/run/docker.sock", None),
+            Ok(DockerHost::Unix("/var/run/docker.sock".to_string()))
+        );
+        assert_eq!(
+            DockerHost::parse("/run/user/1000/docker.sock", None),
+            Ok(DockerHost::Unix("/run/user/1000/docker.sock".to_string()))
+        );
+        assert_eq!(
+            DockerHost::parse("tcp://10.0.0.5:2376", Some("/certs")),
+            Ok(DockerHost::Tcp {
+                address: "10.0.0.5:2376".to_string(),
+                cert_path: Some("/certs".to_string()),
+            })
+        );
+        let ssh = DockerHost::parse("ssh://deploy@docker.example.com:2222/run/docker.sock", None).unwrap();
+        assert_eq!(
+            ssh,
+            DockerHost::Ssh {
+                destination: "deploy@docker.example.com".to_string(),
+                port: Some(2222),
+                socket: Some("/run/docker.sock".to_string()),
+            }
+        );
+        assert_eq!(ssh.to_string(), "ssh://deploy@docker.example.com:2222/run/docker.sock");
+        assert_eq!(
+            DockerHost::parse("ssh://docker.example.com", None).unwrap().to_string(),
+            "ssh://docker.example.com"
+        );
+
+        assert!(DockerHost::parse("npipe:////./pipe/docker_engine", None).is_err());
+        assert!(DockerHost::parse("tcp://", None).is_err());
+        assert!(DockerHost::parse("ssh://deploy@", None).is_err());
+        assert!(DockerHost::parse("ssh://-oProxyCommand=x", None).is_err());
+        assert!(DockerHost::parse("ssh://host:port", None).is_err());
+    }
+
+    #[test]
+    fn test_cli_env() {
+        let endpoint = DockerEndpoint {
+            host: DockerHost::parse("tcp://10.0.0.5:2376", Some("/certs")).unwrap(),
+            context: None,
+        };
+        assert_eq!(
+            endpoint.cli_env(),
+            vec![
+                ("DOCKER_HOST", Some("tcp://10.0.0.5:2376".to_string())),
+                ("DOCKER_CONTEXT", None),
+                ("DOCKER_TLS_VERIFY", Some("1".to_string())),
+                ("DOCKER_CERT_PATH", Some("/certs".to_string())),
+            ]
+        );
+
+        let endpoint = DockerEndpoint {
+            host: DockerHost::parse("ssh://docker.example.com", None).unwrap(),
+            context: Some("remote".to_string()),
+        };
+        assert_eq!(endpoint.cli_env()[0], ("DOCKER_CONTEXT", Some("remote".to_string())));
+        assert_eq!(endpoint.cli_env()[1], ("DOCKER_HOST", None));
+    }
+
+    #[tokio::test]
+    async fn test_ssh_tunnel_failure_is_reported() {
+        let host = DockerHost::parse("ssh://nobody@127.0.0.1:1", None).unwrap();
+        match host.connect().await {
+            Err(MonitorError::Docker(message)) => assert!(message.contains("SSH tunnel"), "{}", message),
+            Err(other) => panic!("unexpected error: {}", other),
+            Ok(_) => panic!("connected through a closed port"),
+        }
+    }
+}
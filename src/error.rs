@@ -1,3 +1,5 @@
+use serde::Serialize;
+use std::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,6 +10,28 @@ pub enum MonitorError {
     #[error("Git error: {0}")]
     Git(String),
 
+    #[error("Git pull failed ({kind}): {message}")]
+    Pull { kind: PullFailure, message: String },
+
+    #[error("Signature verification failed: {0}")]
+    Signature(String),
+
+    #[error("Unknown container: {0}")]
+    UnknownContainer(String),
+
+    #[error("Container '{0}' is protected")]
+    ProtectedContainer(String),
+
+    #[error("Hook error: {0}")]
+    Hook(String),
+
+    #[error("Deploy error: {0}")]
+    Deploy(String),
+
+    /// A deploy, rollback or other change to the repository or containers is already running
+    #[error("Busy: {0}")]
+    Busy(String),
+
     #[error("Authentication error: {0}")]
     Authentication(String),
 
@@ -21,4 +45,29 @@ pub enum MonitorError {
     Json(#[from] serde_json::Error),
 }
 
+/// Why a pull could not bring the local branch up to date with the remote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PullFailure {
+    /// Local and remote history have diverged and the strategy cannot reconcile them
+    Diverged,
+    /// Merging or rebasing stopped on conflicting changes (the attempt is aborted)
+    Conflict,
+    /// The remote could not be fetched
+    Network,
+    /// Tracked files have uncommitted changes and the pull was not told to ignore them
+    Dirty,
+}
+
+impl fmt::Display for PullFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PullFailure::Diverged => write!(f, "diverged"),
+            PullFailure::Conflict => write!(f, "conflict"),
+            PullFailure::Network => write!(f, "network"),
+            PullFailure::Dirty => write!(f, "dirty"),
+        }
+    }
+}
+
 pub type Result<T> = std::result::Result<T, MonitorError>;
@@ -0,0 +1,631 @@
+use super::{
+    CommitInfo, CommitSignature, FileChange, GitBackend, GitCredentials, PullStrategy, SignaturePolicy, SubmoduleStatus,
+    WorkingTreeStatus, TOKEN_USERNAME,
+};
+use crate::error::{MonitorError, PullFailure, Result};
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use std::process::Stdio;
+use tokio::process::Command;
+use tracing::{debug, error};
+
+/// Runs the `git` executable. Only exit codes and plumbing output
+/// (hashes, path lists) are interpreted, never human-readable messages.
+///
+/// Commands are killed when their future is dropped, so a timed out or
+/// abandoned operation does not leave a `git` process behind.
+///
+/// git never prompts: terminal and askpass prompts are disabled and SSH runs
+/// in batch mode, so missing or rejected credentials fail the command instead
+/// of hanging it.
+#[derive(Debug, Clone)]
+pub struct CliBackend {
+    repo_path: String,
+    credentials: GitCredentials,
+}
+
+impl CliBackend {
+    pub fn new(repo_path: impl Into<String>) -> Self {
+        Self {
+            repo_path: repo_path.into(),
+            credentials: GitCredentials::default(),
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: GitCredentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        if self.credentials.token_file.is_some() {
+            // An empty value drops helpers from the user's and system config
+            command
+                .args(["-c", "credential.helper=", "-c"])
+                .arg(format!("credential.helper={}", token_helper()));
+        }
+        command
+            .args(args)
+            .current_dir(&self.repo_path)
+            .env("GIT_TERMINAL_PROMPT", "0")
+            .env("GIT_SSH_COMMAND", ssh_command(&self.credentials))
+            .env("SSH_ASKPASS_REQUIRE", "never")
+            .env_remove("GIT_ASKPASS")
+            .env_remove("SSH_ASKPASS")
+            .stdin(Stdio::null())
+            .kill_on_drop(true);
+        if let Some(token_file) = &self.credentials.token_file {
+            command.env("GHD_GIT_TOKEN_FILE", token_file);
+        }
+        if self.credentials.ssh_key.is_some() {
+            // Only the deploy key is offered, not whatever the agent holds
+            command.env_remove("SSH_AUTH_SOCK");
+        }
+        command
+    }
+
+    /// Runs git with `args` and returns stdout; `action` names the operation in errors
+    async fn git(&self, args: &[&str], action: &str) -> Result<String> {
+        self.execute(self.command(args), args[0], action).await
+    }
+
+    /// Runs a prepared git `command` and returns stdout; `name` is the git subcommand
+    async fn execute(&self, mut command: Command, name: &str, action: &str) -> Result<String> {
+        let output = command
+            .output()
+            .await
+            .map_err(|e| MonitorError::Git(format!("Failed to execute git {}: {}", name, e)))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            error!("Git {} failed: {}", name, stderr.trim());
+            return Err(MonitorError::Git(format!("{} failed: {}", action, stderr.trim())));
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        debug!("Git {} output: {}", name, stdout.trim());
+        Ok(stdout)
+    }
+
+    /// Runs git with `args` and reports whether it exited successfully
+    async fn succeeds(&self, args: &[&str]) -> Result<bool> {
+        let output = self
+            .command(args)
+            .output()
+            .await
+            .map_err(|e| MonitorError::Git(format!("Failed to execute git {}: {}", args[0], e)))?;
+        Ok(output.status.success())
+    }
+
+    /// Paths left unmerged by an interrupted merge or rebase
+    async fn unmerged_paths(&self) -> Result<Vec<String>> {
+        Ok(self
+            .git(&["diff", "--name-only", "--diff-filter=U", "-z"], "Listing conflicts")
+            .await?
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    /// Runs a merge or rebase, aborting it and reporting a conflict if it stops on one
+    async fn integrate_with(&self, args: &[&str], action: &str) -> Result<String> {
+        let err = match self.git(args, action).await {
+            Ok(output) => return Ok(output),
+            Err(err) => err,
+        };
+
+        let conflicts = self.unmerged_paths().await.unwrap_or_default();
+        // Fails harmlessly when nothing was started, e.g. a rebase refused on a dirty tree
+        let _ = self.succeeds(&[args[0], "--abort"]).await;
+
+        if conflicts.is_empty() {
+            return Err(err);
+        }
+        Err(MonitorError::Pull {
+            kind: PullFailure::Conflict,
+            message: format!("{} stopped on conflicts in {}", action, conflicts.join(", ")),
+        })
+    }
+}
+
+#[async_trait]
+impl GitBackend for CliBackend {
+    async fn fetch(&self, remote: &str, refspecs: &[String]) -> Result<()> {
+        let mut args = vec!["fetch", "--no-tags", remote];
+        args.extend(refspecs.iter().map(String::as_str));
+        self.git(&args, "Git fetch").await?;
+        Ok(())
+    }
+
+    async fn list_remote(&self, remote: &str) -> Result<Vec<(String, String)>> {
+        let output = self
+            .git(&["ls-remote", "--heads", "--tags", remote], "Listing remote refs")
+            .await?;
+        Ok(parse_ls_remote(&output))
+    }
+
+    async fn checkout_branch(&self, branch: &str, start: &str) -> Result<String> {
+        self.git(&["checkout", "-B", branch, start], "Git checkout").await
+    }
+
+    async fn checkout_detached(&self, rev: &str) -> Result<String> {
+        self.git(&["checkout", "--detach", rev], "Git checkout").await
+    }
+
+    async fn integrate(&self, upstream: &str, strategy: PullStrategy) -> Result<String> {
+        match strategy {
+            PullStrategy::FfOnly => {
+                // A branch only ahead of the remote is up to date, as with the native backend
+                if !self.succeeds(&["merge-base", "--is-ancestor", "HEAD", upstream]).await?
+                    && !self.succeeds(&["merge-base", "--is-ancestor", upstream, "HEAD"]).await?
+                {
+                    return Err(MonitorError::Pull {
+                        kind: PullFailure::Diverged,
+                        message: format!("local branch cannot be fast-forwarded to {}", upstream),
+                    });
+                }
+                self.git(&["merge", "--ff-only", upstream], "Git merge").await
+            }
+            PullStrategy::Rebase => self.integrate_with(&["rebase", upstream], "Git rebase").await,
+            PullStrategy::Merge => {
+                self.integrate_with(&["merge", "--no-edit", upstream], "Git merge")
+                    .await
+            }
+            PullStrategy::ResetToRemote => self.git(&["reset", "--hard", upstream], "Git reset").await,
+        }
+    }
+
+    async fn resolve_commit(&self, rev: &str) -> Result<String> {
+        let spec = format!("{}^{{commit}}", rev);
+        Ok(self
+            .git(&["rev-parse", "--verify", &spec], &format!("Resolving {}", rev))
+            .await?
+            .trim()
+            .to_string())
+    }
+
+    async fn current_branch(&self) -> Result<String> {
+        Ok(self
+            .git(&["rev-parse", "--abbrev-ref", "HEAD"], "Getting current branch")
+            .await?
+            .trim()
+            .to_string())
+    }
+
+    async fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>> {
+        Ok(self
+            .git(&["diff", "--name-only", "-z", from, to], "Listing changed files")
+            .await?
+            .split('\0')
+            .filter(|path| !path.is_empty())
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn log(&self, from: &str, to: &str, limit: usize) -> Result<Vec<CommitInfo>> {
+        // Fields separated by unit separators, records by record separators
+        let range = format!("{}..{}", from, to);
+        let limit = format!("--max-count={}", limit);
+        let output = self
+            .git(
+                &["log", "--format=%H%x1f%an%x1f%ae%x1f%at%x1f%s%x1e", &limit, &range],
+                "Listing commits",
+            )
+            .await?;
+
+        Ok(parse_log(&output))
+    }
+
+    async fn diff(&self, from: &str, to: &str) -> Result<(Vec<FileChange>, String)> {
+        let numstat = self
+            .git(&["diff", "--numstat", "-z", from, to], "Diffing commits")
+            .await?;
+        let patch = self.git(&["diff", "--no-color", from, to], "Diffing commits").await?;
+        Ok((parse_numstat(&numstat), patch))
+    }
+
+    async fn reset_hard(&self, commit: &str) -> Result<String> {
+        self.git(&["reset", "--hard", commit], "Git reset").await
+    }
+
+    async fn working_tree_status(&self) -> Result<WorkingTreeStatus> {
+        let output = self
+            .git(&["status", "--porcelain=v1", "-z"], "Reading working tree status")
+            .await?;
+        Ok(parse_porcelain_status(&output))
+    }
+
+    async fn ahead_behind(&self, local: &str, upstream: &str) -> Result<(usize, usize)> {
+        let range = format!("{}...{}", local, upstream);
+        let output = self
+            .git(&["rev-list", "--left-right", "--count", &range], "Counting commits")
+            .await?;
+
+        let mut counts = output.split_whitespace().map(str::parse::<usize>);
+        match (counts.next(), counts.next()) {
+            (Some(Ok(ahead)), Some(Ok(behind))) => Ok((ahead, behind)),
+            _ => Err(MonitorError::Git(format!("Unexpected rev-list output: {}", output.trim()))),
+        }
+    }
+
+    async fn stash(&self, message: &str) -> Result<String> {
+        self.git(&["stash", "push", "--include-untracked", "-m", message], "Git stash")
+            .await
+    }
+
+    async fn stash_pop(&self) -> Result<String> {
+        self.git(&["stash", "pop"], "Git stash pop").await
+    }
+
+    async fn stash_list(&self) -> Result<Vec<String>> {
+        Ok(self
+            .git(&["stash", "list", "--format=%gs"], "Listing stashes")
+            .await?
+            .lines()
+            .map(str::to_string)
+            .collect())
+    }
+
+    async fn discard_changes(&self) -> Result<String> {
+        let mut output = self.git(&["reset", "--hard", "HEAD"], "Discarding changes").await?;
+        output.push_str(&self.git(&["clean", "-fd"], "Removing untracked files").await?);
+        Ok(output)
+    }
+
+    async fn update_submodules(&self) -> Result<String> {
+        let mut output = self
+            .git(&["submodule", "sync", "--recursive"], "Submodule sync")
+            .await?;
+        output.push_str(
+            &self
+                .git(&["submodule", "update", "--init", "--recursive"], "Submodule update")
+                .await?,
+        );
+        Ok(output)
+    }
+
+    async fn submodule_status(&self) -> Result<Vec<SubmoduleStatus>> {
+        let output = self.git(&["submodule", "status"], "Reading submodule status").await?;
+        let mut submodules = parse_submodule_status(&output);
+
+        // A '+' entry shows the checked out commit; look up the recorded one
+        for submodule in submodules.iter_mut().filter(|submodule| submodule.recorded_commit.is_empty()) {
+            let spec = format!("HEAD:{}", submodule.path);
+            submodule.recorded_commit = self
+                .git(&["rev-parse", &spec], "Reading submodule status")
+                .await?
+                .trim()
+                .to_string();
+        }
+        Ok(submodules)
+    }
+
+    async fn lfs_pull(&self, remote: &str) -> Result<String> {
+        let mut output = self.git(&["lfs", "fetch", remote], "Git LFS fetch").await?;
+        output.push_str(&self.git(&["lfs", "checkout"], "Git LFS checkout").await?);
+        Ok(output)
+    }
+
+    async fn commit_signature(&self, commit: &str, policy: &SignaturePolicy) -> Result<CommitSignature> {
+        let allowed_signers = policy
+            .allowed_signers
+            .as_ref()
+            .map(|path| format!("gpg.ssh.allowedSignersFile={}", path));
+        let mut args = Vec::new();
+        if let Some(config) = &allowed_signers {
+            args.extend(["-c", config.as_str()]);
+        }
+        args.extend(["log", "-1", "--format=%G?%x1f%GS%x1f%GK", commit, "--"]);
+
+        let mut command = self.command(&args);
+        if let Some(gpg_home) = &policy.gpg_home {
+            command.env("GNUPGHOME", gpg_home);
+        }
+        let output = self.execute(command, "log", "Verifying signature").await?;
+        Ok(CommitSignature::parse(commit, &output))
+    }
+
+    async fn has_remote(&self, remote: &str) -> Result<bool> {
+        let output = self
+            .command(&["remote", "get-url", remote])
+            .output()
+            .await
+            .map_err(|e| MonitorError::Git(format!("Failed to validate remote: {}", e)))?;
+        Ok(output.status.success())
+    }
+
+    async fn clone_repository(&self, url: &str, remote: &str, branch: &str, depth: Option<u32>) -> Result<String> {
+        let depth = depth.map(|depth| depth.to_string());
+        let mut args = vec!["clone", "--no-tags", "--origin", remote, "--branch", branch];
+        if let Some(depth) = &depth {
+            args.extend(["--depth", depth]);
+        }
+        args.extend(["--", url, &self.repo_path]);
+
+        // The repository path does not exist yet, so run from the service's directory
+        let mut command = self.command(&args);
+        command.current_dir(".");
+        self.execute(command, "clone", "Git clone").await?;
+        Ok(format!("Cloned {} into {}\n", url, self.repo_path))
+    }
+}
+
+/// SSH command for git: never prompts, and uses the configured deploy key and known_hosts file
+fn ssh_command(credentials: &GitCredentials) -> String {
+    let mut command = "ssh -o BatchMode=yes".to_string();
+    if let Some(key) = &credentials.ssh_key {
+        command.push_str(&format!(" -i {} -o IdentitiesOnly=yes", shell_quote(key)));
+    }
+    if let Some(known_hosts) = &credentials.known_hosts {
+        command.push_str(&format!(
+            " -o UserKnownHostsFile={} -o StrictHostKeyChecking=yes",
+            shell_quote(known_hosts)
+        ));
+    }
+    command
+}
+
+/// Credential helper that answers `get` with the token read from `GHD_GIT_TOKEN_FILE`,
+/// so the token never appears on a command line
+fn token_helper() -> String {
+    format!(
+        "!f() {{ test \"$1\" = get || return 0; echo username={}; printf 'password=%s\\n' \"$(cat \"$GHD_GIT_TOKEN_FILE\")\"; }}; f",
+        TOKEN_USERNAME
+    )
+}
+
+/// Quotes `value` for the shell git runs `GIT_SSH_COMMAND` with
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// Parses `git log` output in the format requested by [`CliBackend::log`]
+fn parse_log(output: &str) -> Vec<CommitInfo> {
+    output
+        .split('\x1e')
+        .filter_map(|record| {
+            let mut fields = record.trim_start_matches('\n').split('\x1f');
+            let hash = fields.next().filter(|hash| !hash.is_empty())?;
+            let author = fields.next()?;
+            let email = fields.next()?;
+            let timestamp = fields.next()?.parse().ok()?;
+            let subject = fields.next()?;
+            Some(CommitInfo {
+                hash: hash.to_string(),
+                author: author.to_string(),
+                email: email.to_string(),
+                date: Utc.timestamp_opt(timestamp, 0).single()?,
+                subject: subject.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Parses `git diff --numstat -z` output.
+///
+/// Each entry is "added<TAB>deleted<TAB>path<NUL>"; renames leave the path empty
+/// and follow it with "old<NUL>new<NUL>". Binary files report "-" for both counts.
+fn parse_numstat(output: &str) -> Vec<FileChange> {
+    let mut files = Vec::new();
+    let mut fields = output.split('\0');
+
+    while let Some(entry) = fields.next() {
+        let mut parts = entry.splitn(3, '\t');
+        let (Some(additions), Some(deletions), Some(path)) = (parts.next(), parts.next(), parts.next()) else {
+            continue;
+        };
+        let path = if path.is_empty() {
+            // Rename: skip the old path, keep the new one
+            fields.next();
+            fields.next().unwrap_or_default()
+        } else {
+            path
+        };
+
+        files.push(FileChange {
+            path: path.to_string(),
+            additions: additions.parse().ok(),
+            deletions: deletions.parse().ok(),
+        });
+    }
+
+    files
+}
+
+/// Parses `git ls-remote` output ("<hash><TAB><ref>" per line). Annotated tags
+/// are listed twice, the second time as "<ref>^{}" with the commit they point to.
+fn parse_ls_remote(output: &str) -> Vec<(String, String)> {
+    let mut refs: Vec<(String, String)> = Vec::new();
+    for line in output.lines() {
+        let Some((hash, name)) = line.split_once('\t') else {
+            continue;
+        };
+        match name.strip_suffix("^{}") {
+            Some(tag) => match refs.iter_mut().find(|(existing, _)| existing == tag) {
+                Some(entry) => entry.1 = hash.to_string(),
+                None => refs.push((tag.to_string(), hash.to_string())),
+            },
+            None => refs.push((name.to_string(), hash.to_string())),
+        }
+    }
+    refs
+}
+
+/// Parses `git submodule status` output: one line per submodule made of a
+/// state character, the commit, the path and an optional description.
+///
+/// The state is ' ' when the checkout matches the recorded commit, '-' when the
+/// submodule is not initialised (the commit is the recorded one), and '+' or 'U'
+/// when it differs (the commit is the checked out one, so the recorded commit is
+/// left empty for the caller to fill in).
+fn parse_submodule_status(output: &str) -> Vec<SubmoduleStatus> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let state = line.chars().next()?;
+            let mut fields = line[state.len_utf8()..].split_whitespace();
+            let commit = fields.next()?.to_string();
+            let path = fields.next()?.to_string();
+            Some(match state {
+                '-' => SubmoduleStatus {
+                    path,
+                    recorded_commit: commit,
+                    checked_out_commit: None,
+                },
+                ' ' => SubmoduleStatus {
+                    path,
+                    recorded_commit: commit.clone(),
+                    checked_out_commit: Some(commit),
+                },
+                _ => SubmoduleStatus {
+                    path,
+                    recorded_commit: String::new(),
+                    checked_out_commit: Some(commit),
+                },
+            })
+        })
+        .collect()
+}
+
+/// Parses `git status --porcelain=v1 -z` output.
+///
+/// Each entry is "XY path<NUL>", where renames and copies are followed by the
+/// original path as a separate field. Untracked files have the status "??".
+fn parse_porcelain_status(output: &str) -> WorkingTreeStatus {
+    let mut status = WorkingTreeStatus::default();
+    let mut fields = output.split('\0');
+
+    while let Some(entry) = fields.next() {
+        let (Some(code), Some(path)) = (entry.get(..2), entry.get(3..)) else {
+            continue;
+        };
+        match code {
+            "??" => status.untracked.push(path.to_string()),
+            "!!" => {}
+            _ => {
+                if code.contains('R') || code.contains('C') {
+                    fields.next();
+                }
+                status.modified.push(path.to_string());
+            }
+        }
+    }
+
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_log() {
+        let output = "abc123\x1fAda\x1fada@example.com\x1f1700000000\x1fFix the build\x1e\n\
+                      def456\x1fBob\x1fbob@example.com\x1f1690000000\x1fUpdate docs\x1e\n";
+        let commits = parse_log(output);
+        assert_eq!(commits[0].hash, "abc123");
+        assert_eq!(commits[0].author, "Ada");
+        assert_eq!(commits[0].subject, "Fix the build");
+        assert_eq!(commits[0].date.timestamp(), 1_700_000_000);
+        assert_eq!(commits[1].email, "bob@example.com");
+    }
+
+    #[test]
+    fn test_parse_ls_remote() {
+        let output = "aaa\trefs/heads/main\nbbb\trefs/tags/v1.0\nccc\trefs/tags/v1.0^{}\nddd\trefs/tags/light\n";
+        assert_eq!(
+            parse_ls_remote(output),
+            vec![
+                ("refs/heads/main".to_string(), "aaa".to_string()),
+                ("refs/tags/v1.0".to_string(), "ccc".to_string()),
+                ("refs/tags/light".to_string(), "ddd".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_submodule_status() {
+        let output = " aaa lib (heads/main)\n-bbb vendor/ui\n+ccc docs (v1.0-2-gccc)\n";
+        let submodules = parse_submodule_status(output);
+        assert_eq!(submodules.len(), 3);
+        assert!(submodules[0].in_sync());
+        assert_eq!(submodules[1].path, "vendor/ui");
+        assert_eq!(submodules[1].checked_out_commit, None);
+        assert_eq!(submodules[2].checked_out_commit.as_deref(), Some("ccc"));
+        assert!(submodules[2].recorded_commit.is_empty());
+    }
+
+    #[test]
+    fn test_parse_porcelain_status() {
+        let output = " M config.yml\0R  new.txt\0old.txt\0?? scratch/\0D  gone.txt\0";
+        let status = parse_porcelain_status(output);
+        assert_eq!(status.modified, vec!["config.yml", "new.txt", "gone.txt"]);
+        assert_eq!(status.untracked, vec!["scratch/"]);
+    }
+
+    #[test]
+    fn test_parse_numstat() {
+        let output = "1\t1\tREADME.md\0-\t-\tlogo.png\x003\t0\t\0old.txt\0new.txt\0";
+        let files = parse_numstat(output);
+        assert_eq!(
+            files,
+            vec![
+                FileChange { path: "README.md".to_string(), additions: Some(1), deletions: Some(1) },
+                FileChange { path: "logo.png".to_string(), additions: None, deletions: None },
+                FileChange { path: "new.txt".to_string(), additions: Some(3), deletions: Some(0) },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ssh_command() {
+        assert_eq!(ssh_command(&GitCredentials::default()), "ssh -o BatchMode=yes");
+        let credentials = GitCredentials {
+            ssh_key: Some("/keys/it's/id_ed25519".to_string()),
+            known_hosts: Some("/keys/known_hosts".to_string()),
+            token_file: None,
+        };
+        assert_eq!(
+            ssh_command(&credentials),
+            "ssh -o BatchMode=yes -i '/keys/it'\\''s/id_ed25519' -o IdentitiesOnly=yes \
+             -o UserKnownHostsFile='/keys/known_hosts' -o StrictHostKeyChecking=yes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_token_credential_helper() {
+        use tokio::io::AsyncWriteExt;
+
+        let dir = std::env::temp_dir().join(format!("ghd-token-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let token_file = dir.join("token");
+        std::fs::write(&token_file, "secret-token\n").unwrap();
+        let backend = CliBackend::new(dir.to_string_lossy()).with_credentials(GitCredentials {
+            token_file: Some(token_file.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+
+        let mut child = backend
+            .command(&["credential", "fill"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(b"protocol=https\nhost=example.com\n\n")
+            .await
+            .unwrap();
+        let output = child.wait_with_output().await.unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{:?}", output);
+        assert!(stdout.contains("username=x-access-token\n"), "{}", stdout);
+        assert!(stdout.contains("password=secret-token\n"), "{}", stdout);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,1594 @@
+mod cli;
+mod native;
+
+use crate::error::{MonitorError, PullFailure, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+pub use cli::CliBackend;
+pub use native::NativeBackend;
+
+/// Low-level repository operations, implemented by the git CLI or natively
+///
+/// Dropping one of the returned futures should stop the underlying work
+/// where the implementation allows it.
+#[async_trait]
+pub trait GitBackend: Send + Sync {
+    /// Fetches `refspecs` ("+src:dst") from `remote`
+    async fn fetch(&self, remote: &str, refspecs: &[String]) -> Result<()>;
+    /// Branches and tags on `remote` as (full ref name, commit) pairs; tags are peeled to commits
+    async fn list_remote(&self, remote: &str) -> Result<Vec<(String, String)>>;
+    /// Creates or resets local `branch` to `start` and checks it out, keeping local changes
+    async fn checkout_branch(&self, branch: &str, start: &str) -> Result<String>;
+    /// Checks out `rev` with a detached HEAD, keeping local changes
+    async fn checkout_detached(&self, rev: &str) -> Result<String>;
+    /// Brings the checked out branch up to date with the already fetched `upstream`
+    /// using `strategy`, returning a log of what was done.
+    ///
+    /// Fails with [`MonitorError::Pull`] when the histories diverged or a merge or
+    /// rebase hit conflicts; an interrupted merge or rebase is aborted first.
+    async fn integrate(&self, upstream: &str, strategy: PullStrategy) -> Result<String>;
+    /// Full hash of the commit `rev` points at
+    async fn resolve_commit(&self, rev: &str) -> Result<String>;
+    /// Name of the checked out branch, or "HEAD" when detached
+    async fn current_branch(&self) -> Result<String>;
+    /// Paths changed between two commits, relative to the repository root
+    async fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>>;
+    /// Commits reachable from `to` but not from `from`, newest first, at most `limit`
+    async fn log(&self, from: &str, to: &str, limit: usize) -> Result<Vec<CommitInfo>>;
+    /// Per-file line counts and the unified diff between two commits
+    async fn diff(&self, from: &str, to: &str) -> Result<(Vec<FileChange>, String)>;
+    /// Resets the working tree and current branch to `commit`
+    async fn reset_hard(&self, commit: &str) -> Result<String>;
+    /// Modified tracked files and untracked (not ignored) files
+    async fn working_tree_status(&self) -> Result<WorkingTreeStatus>;
+    /// Number of commits only in `local` and only in `upstream`
+    async fn ahead_behind(&self, local: &str, upstream: &str) -> Result<(usize, usize)>;
+    /// Stashes local modifications and untracked files
+    async fn stash(&self, message: &str) -> Result<String>;
+    /// Applies and drops the most recent stash
+    async fn stash_pop(&self) -> Result<String>;
+    /// Descriptions of the stashes, most recent first
+    async fn stash_list(&self) -> Result<Vec<String>>;
+    /// Reverts modified tracked files and deletes untracked files
+    async fn discard_changes(&self) -> Result<String>;
+    /// Synchronises submodule URLs and checks out the recorded commit of every
+    /// submodule, recursively, initialising new ones
+    async fn update_submodules(&self) -> Result<String>;
+    /// Top-level submodules with their recorded and checked out commits
+    async fn submodule_status(&self) -> Result<Vec<SubmoduleStatus>>;
+    /// Downloads the LFS objects of the checked out commit from `remote` and
+    /// replaces the pointer files with them
+    async fn lfs_pull(&self, remote: &str) -> Result<String>;
+    /// Checks the signature on `commit` against the keys in `policy`
+    async fn commit_signature(&self, commit: &str, policy: &SignaturePolicy) -> Result<CommitSignature>;
+    async fn has_remote(&self, remote: &str) -> Result<bool>;
+    /// Clones `url` into the repository path with `branch` checked out, naming the remote `remote`.
+    /// `depth` makes a shallow clone with that many commits.
+    async fn clone_repository(&self, url: &str, remote: &str, branch: &str, depth: Option<u32>) -> Result<String>;
+}
+
+/// Which [`GitBackend`] implementation to use
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GitBackendKind {
+    /// Shell out to the `git` executable
+    #[default]
+    Cli,
+    /// Use libgit2 in-process
+    Native,
+}
+
+impl fmt::Display for GitBackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitBackendKind::Cli => write!(f, "cli"),
+            GitBackendKind::Native => write!(f, "native"),
+        }
+    }
+}
+
+impl FromStr for GitBackendKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "cli" => Ok(GitBackendKind::Cli),
+            "native" => Ok(GitBackendKind::Native),
+            other => Err(format!("unknown git backend '{}' (expected cli or native)", other)),
+        }
+    }
+}
+
+/// How a pull reconciles the local branch with the remote branch
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PullStrategy {
+    /// Only fast-forward; fail when the histories diverged
+    #[default]
+    FfOnly,
+    /// Replay local commits on top of the remote branch
+    Rebase,
+    /// Create a merge commit when a fast-forward is not possible
+    Merge,
+    /// Discard local commits and changes and check out the remote branch
+    ResetToRemote,
+}
+
+impl fmt::Display for PullStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PullStrategy::FfOnly => write!(f, "ff-only"),
+            PullStrategy::Rebase => write!(f, "rebase"),
+            PullStrategy::Merge => write!(f, "merge"),
+            PullStrategy::ResetToRemote => write!(f, "reset-to-remote"),
+        }
+    }
+}
+
+impl FromStr for PullStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "ff-only" => Ok(PullStrategy::FfOnly),
+            "rebase" => Ok(PullStrategy::Rebase),
+            "merge" => Ok(PullStrategy::Merge),
+            "reset-to-remote" => Ok(PullStrategy::ResetToRemote),
+            other => Err(format!(
+                "unknown pull strategy '{}' (expected ff-only, rebase, merge or reset-to-remote)",
+                other
+            )),
+        }
+    }
+}
+
+/// What a pull, checkout or reset updates besides the working tree
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct UpdateOptions {
+    /// Sync and update submodules to the commits recorded in the checked out commit
+    pub submodules: bool,
+    /// Fetch and check out Git LFS objects
+    pub lfs: bool,
+}
+
+/// Which commits must carry a trusted signature before they are checked out
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyMode {
+    /// No verification
+    #[default]
+    Off,
+    /// Only the commit that becomes HEAD
+    Head,
+    /// Every incoming commit
+    All,
+}
+
+impl fmt::Display for VerifyMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerifyMode::Off => write!(f, "off"),
+            VerifyMode::Head => write!(f, "head"),
+            VerifyMode::All => write!(f, "all"),
+        }
+    }
+}
+
+impl FromStr for VerifyMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "off" | "false" | "" => Ok(VerifyMode::Off),
+            "head" => Ok(VerifyMode::Head),
+            "all" => Ok(VerifyMode::All),
+            other => Err(format!("unknown verification mode '{}' (expected off, head or all)", other)),
+        }
+    }
+}
+
+/// Username sent with an HTTPS token; GitHub ignores it, other hosts accept any non-empty name
+pub const TOKEN_USERNAME: &str = "x-access-token";
+
+/// Credentials for private remotes, used instead of the service user's SSH agent and credential helpers
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GitCredentials {
+    /// Private SSH key (e.g. a deploy key) offered to SSH remotes
+    pub ssh_key: Option<String>,
+    /// known_hosts file SSH host keys are checked against
+    pub known_hosts: Option<String>,
+    /// File holding an access token for HTTPS remotes
+    pub token_file: Option<String>,
+}
+
+impl GitCredentials {
+    /// Reads the HTTPS token, if one is configured
+    pub fn token(&self) -> Result<Option<String>> {
+        let Some(path) = &self.token_file else {
+            return Ok(None);
+        };
+        let token = std::fs::read_to_string(path)
+            .map_err(|e| MonitorError::Git(format!("Failed to read token file {}: {}", path, e)))?;
+        let token = token.trim();
+        if token.is_empty() {
+            return Err(MonitorError::Git(format!("Token file {} is empty", path)));
+        }
+        Ok(Some(token.to_string()))
+    }
+}
+
+/// Trusted signing keys and which commits must be signed by them
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SignaturePolicy {
+    pub mode: VerifyMode,
+    /// SSH allowed signers file (see ssh-keygen(1), "ALLOWED SIGNERS")
+    pub allowed_signers: Option<String>,
+    /// GnuPG home directory holding the trusted public keys
+    pub gpg_home: Option<String>,
+}
+
+/// Outcome of checking a commit signature, as reported by git's `%G?`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignatureStatus {
+    /// Valid signature from a trusted key
+    Good,
+    /// Valid signature from a key that is not trusted
+    Untrusted,
+    /// The signature does not match the commit
+    Bad,
+    /// The signature or the key has expired
+    Expired,
+    /// The key has been revoked
+    Revoked,
+    /// The signature could not be checked, e.g. the key is missing
+    Unverifiable,
+    Unsigned,
+}
+
+impl SignatureStatus {
+    /// Maps a `%G?` status letter
+    fn from_code(code: &str) -> Self {
+        match code {
+            "G" => SignatureStatus::Good,
+            "U" => SignatureStatus::Untrusted,
+            "B" => SignatureStatus::Bad,
+            "X" | "Y" => SignatureStatus::Expired,
+            "R" => SignatureStatus::Revoked,
+            "E" => SignatureStatus::Unverifiable,
+            _ => SignatureStatus::Unsigned,
+        }
+    }
+}
+
+impl fmt::Display for SignatureStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureStatus::Good => write!(f, "good signature"),
+            SignatureStatus::Untrusted => write!(f, "signed by an untrusted key"),
+            SignatureStatus::Bad => write!(f, "bad signature"),
+            SignatureStatus::Expired => write!(f, "expired signature or key"),
+            SignatureStatus::Revoked => write!(f, "signed by a revoked key"),
+            SignatureStatus::Unverifiable => write!(f, "signature cannot be checked"),
+            SignatureStatus::Unsigned => write!(f, "not signed"),
+        }
+    }
+}
+
+/// Signature on a commit
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommitSignature {
+    pub commit: String,
+    pub status: SignatureStatus,
+    /// Signer identity (GPG user ID or SSH principal), when known
+    pub signer: Option<String>,
+    /// Key fingerprint or ID, when signed
+    pub key: Option<String>,
+}
+
+impl CommitSignature {
+    /// Parses `git log --format=%G?%x1f%GS%x1f%GK` output for `commit`
+    fn parse(commit: &str, output: &str) -> Self {
+        let mut fields = output.trim_end_matches('\n').split('\x1f');
+        let non_empty = |field: Option<&str>| field.filter(|value| !value.is_empty()).map(str::to_string);
+        CommitSignature {
+            commit: commit.to_string(),
+            status: SignatureStatus::from_code(fields.next().unwrap_or_default()),
+            signer: non_empty(fields.next()),
+            key: non_empty(fields.next()),
+        }
+    }
+
+    /// Whether the commit carries a valid signature from a trusted key
+    pub fn is_good(&self) -> bool {
+        self.status == SignatureStatus::Good
+    }
+}
+
+/// Options for a single pull
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PullOptions {
+    /// Overrides the configured pull strategy
+    #[serde(default)]
+    pub strategy: Option<PullStrategy>,
+    /// Pull even though tracked files have uncommitted changes
+    #[serde(default)]
+    pub allow_dirty: bool,
+}
+
+/// What the repository is deployed from: a branch that follows the remote,
+/// a tag, or a pinned commit
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "name", rename_all = "lowercase")]
+pub enum GitRef {
+    Branch(String),
+    Tag(String),
+    Commit(String),
+}
+
+impl GitRef {
+    /// Rejects names that git would not accept or could mistake for options
+    fn validate(&self) -> Result<()> {
+        let (name, valid) = match self {
+            GitRef::Branch(name) | GitRef::Tag(name) => (
+                name,
+                !name.is_empty()
+                    && !name.starts_with('-')
+                    && !name.contains("..")
+                    && !name.chars().any(|c| c.is_whitespace() || c.is_control() || "~^:?*[\\".contains(c)),
+            ),
+            GitRef::Commit(sha) => (sha, (4..=40).contains(&sha.len()) && sha.chars().all(|c| c.is_ascii_hexdigit())),
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(MonitorError::Git(format!("Invalid {} '{}'", self.kind(), name)))
+        }
+    }
+
+    fn kind(&self) -> &'static str {
+        match self {
+            GitRef::Branch(_) => "branch",
+            GitRef::Tag(_) => "tag",
+            GitRef::Commit(_) => "commit",
+        }
+    }
+}
+
+impl fmt::Display for GitRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GitRef::Branch(name) | GitRef::Tag(name) => write!(f, "{} {}", self.kind(), name),
+            GitRef::Commit(sha) => write!(f, "commit {}", &sha[..sha.len().min(8)]),
+        }
+    }
+}
+
+/// A branch or tag on the remote
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RemoteRef {
+    pub name: String,
+    pub commit: String,
+}
+
+/// Branches and tags available on the remote, sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RemoteRefs {
+    pub branches: Vec<RemoteRef>,
+    pub tags: Vec<RemoteRef>,
+}
+
+pub struct GitManager {
+    repo_path: String,
+    kind: GitBackendKind,
+    remote: String,
+    /// Ref currently deployed; starts as the configured branch
+    target: RwLock<GitRef>,
+    backend: Arc<dyn GitBackend>,
+    /// Strategy used when a pull does not override it
+    pull_strategy: PullStrategy,
+    updates: UpdateOptions,
+    signatures: SignaturePolicy,
+    /// Upper bound for any single git operation
+    timeout: Duration,
+}
+
+/// Largest number of commits returned by [`GitManager::incoming_commits`]
+const MAX_INCOMING_COMMITS: usize = 200;
+
+/// Largest unified diff returned by [`GitManager::incoming_diff`], in bytes
+const MAX_PATCH_BYTES: usize = 256 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct GitStatus {
+    pub local_commit: String,
+    pub remote_commit: String,
+    pub updates_available: bool,
+    pub current_branch: String,
+    /// Ref the repository is deployed from
+    pub target: GitRef,
+    /// Local commits not on the remote branch
+    pub ahead: usize,
+    /// Remote commits not checked out yet
+    pub behind: usize,
+    pub working_tree: WorkingTreeStatus,
+    /// Number of stashed change sets
+    pub stashes: usize,
+    pub submodules: Vec<SubmoduleStatus>,
+    /// Signature on HEAD, when signature verification is enabled
+    pub signature: Option<CommitSignature>,
+}
+
+impl GitStatus {
+    /// Submodules whose checkout does not match the commit recorded in HEAD
+    pub fn drifted_submodules(&self) -> impl Iterator<Item = &SubmoduleStatus> {
+        self.submodules.iter().filter(|submodule| !submodule.in_sync())
+    }
+}
+
+/// A submodule and the commit checked out in it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SubmoduleStatus {
+    pub path: String,
+    /// Commit recorded in the superproject's HEAD
+    pub recorded_commit: String,
+    /// Commit checked out in the submodule, `None` when it is not initialised
+    pub checked_out_commit: Option<String>,
+}
+
+impl SubmoduleStatus {
+    pub fn in_sync(&self) -> bool {
+        self.checked_out_commit.as_deref() == Some(self.recorded_commit.as_str())
+    }
+}
+
+/// Uncommitted changes in the working tree
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct WorkingTreeStatus {
+    /// Tracked files with staged or unstaged changes (including deletions and conflicts)
+    pub modified: Vec<String>,
+    /// Files git does not track and does not ignore; directories end with '/'
+    pub untracked: Vec<String>,
+}
+
+impl WorkingTreeStatus {
+    /// Whether tracked files have uncommitted changes; untracked files do not count
+    pub fn is_dirty(&self) -> bool {
+        !self.modified.is_empty()
+    }
+}
+
+impl GitManager {
+    pub fn new(
+        repo_path: impl Into<String>,
+        remote: impl Into<String>,
+        branch: impl Into<String>,
+        backend: GitBackendKind,
+        pull_strategy: PullStrategy,
+        updates: UpdateOptions,
+        timeout: Duration,
+    ) -> Self {
+        let repo_path = repo_path.into();
+        Self {
+            backend: Self::backend(backend, &repo_path, timeout, GitCredentials::default()),
+            kind: backend,
+            repo_path,
+            remote: remote.into(),
+            target: RwLock::new(GitRef::Branch(branch.into())),
+            pull_strategy,
+            updates,
+            signatures: SignaturePolicy::default(),
+            timeout,
+        }
+    }
+
+    fn backend(
+        kind: GitBackendKind,
+        repo_path: &str,
+        timeout: Duration,
+        credentials: GitCredentials,
+    ) -> Arc<dyn GitBackend> {
+        match kind {
+            GitBackendKind::Cli => Arc::new(CliBackend::new(repo_path).with_credentials(credentials)),
+            GitBackendKind::Native => Arc::new(NativeBackend::new(repo_path, timeout).with_credentials(credentials)),
+        }
+    }
+
+    /// Authenticates to the remote with `credentials`
+    pub fn with_credentials(mut self, credentials: GitCredentials) -> Self {
+        self.backend = Self::backend(self.kind, &self.repo_path, self.timeout, credentials);
+        self
+    }
+
+    /// Requires commits to be signed by the keys in `policy` before they are checked out
+    pub fn with_signature_policy(mut self, policy: SignaturePolicy) -> Self {
+        self.signatures = policy;
+        self
+    }
+
+    /// Runs a backend operation, giving up once the configured timeout has passed.
+    /// The operation's future is dropped on timeout, which kills a running git process.
+    async fn run<T>(&self, action: &str, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        match tokio::time::timeout(self.timeout, operation).await {
+            Ok(result) => result,
+            Err(_) => {
+                warn!("{} timed out after {:?}", action, self.timeout);
+                Err(MonitorError::Git(format!(
+                    "{} timed out after {}s",
+                    action,
+                    self.timeout.as_secs()
+                )))
+            }
+        }
+    }
+
+    /// The ref the repository is deployed from
+    pub fn target(&self) -> GitRef {
+        self.target.read().unwrap().clone()
+    }
+
+    /// Switches the deployed ref without touching the working tree, e.g. to restore
+    /// a ref that was checked out before a restart
+    pub fn set_target(&self, target: GitRef) {
+        *self.target.write().unwrap() = target;
+    }
+
+    /// The name of the tracked branch, or `None` when a tag or commit is deployed
+    pub fn tracked_branch(&self) -> Option<String> {
+        match self.target() {
+            GitRef::Branch(branch) => Some(branch),
+            GitRef::Tag(_) | GitRef::Commit(_) => None,
+        }
+    }
+
+    /// Revision that `target` resolves to after a fetch
+    fn upstream(&self, target: &GitRef) -> String {
+        match target {
+            GitRef::Branch(branch) => format!("refs/remotes/{}/{}", self.remote, branch),
+            GitRef::Tag(tag) => format!("refs/tags/{}", tag),
+            GitRef::Commit(sha) => sha.clone(),
+        }
+    }
+
+    /// Refspecs that bring `target` up to date. A commit is fetched with every
+    /// branch and tag, since servers need not allow fetching a bare SHA.
+    fn refspecs(&self, target: &GitRef) -> Vec<String> {
+        match target {
+            GitRef::Branch(branch) => vec![format!("+refs/heads/{0}:refs/remotes/{1}/{0}", branch, self.remote)],
+            GitRef::Tag(tag) => vec![format!("+refs/tags/{0}:refs/tags/{0}", tag)],
+            GitRef::Commit(_) => vec![
+                format!("+refs/heads/*:refs/remotes/{}/*", self.remote),
+                "+refs/tags/*:refs/tags/*".to_string(),
+            ],
+        }
+    }
+
+    /// Fetches the deployed ref; a pinned commit never changes, so nothing is fetched for it
+    pub async fn fetch(&self) -> Result<()> {
+        let target = self.target();
+        if let GitRef::Commit(_) = target {
+            debug!("Not fetching: deployed from pinned {}", target);
+            return Ok(());
+        }
+
+        info!("Fetching {} from {}", target, self.remote);
+        self.run("Git fetch", self.backend.fetch(&self.remote, &self.refspecs(&target)))
+            .await?;
+        info!("Successfully fetched {} from {}", target, self.remote);
+        Ok(())
+    }
+
+    /// Lists the branches and tags on the remote
+    pub async fn remote_refs(&self) -> Result<RemoteRefs> {
+        let mut refs = RemoteRefs::default();
+        for (name, commit) in self.run("Listing remote refs", self.backend.list_remote(&self.remote)).await? {
+            if let Some(branch) = name.strip_prefix("refs/heads/") {
+                refs.branches.push(RemoteRef { name: branch.to_string(), commit });
+            } else if let Some(tag) = name.strip_prefix("refs/tags/") {
+                refs.tags.push(RemoteRef { name: tag.to_string(), commit });
+            }
+        }
+        refs.branches.sort_by(|a, b| a.name.cmp(&b.name));
+        refs.tags.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(refs)
+    }
+
+    /// Fetches and checks out `target`, which becomes the deployed ref
+    ///
+    /// A branch is reset to the remote branch and followed by later pulls; a tag
+    /// or commit is checked out with a detached HEAD. Refuses to run while
+    /// tracked files have uncommitted changes.
+    pub async fn checkout(&self, target: GitRef) -> Result<CheckoutResult> {
+        target.validate()?;
+        info!("Checking out {}", target);
+
+        let working_tree = self.working_tree_status().await?;
+        if working_tree.is_dirty() {
+            return Err(MonitorError::Git(format!(
+                "Cannot check out {}: uncommitted changes to {}",
+                target,
+                working_tree.modified.join(", ")
+            )));
+        }
+
+        self.run("Git fetch", self.backend.fetch(&self.remote, &self.refspecs(&target)))
+            .await?;
+        let upstream = self.upstream(&target);
+        let commit = self
+            .run("Resolving checkout target", self.backend.resolve_commit(&upstream))
+            .await?;
+        if self.signatures.mode != VerifyMode::Off {
+            self.verify_commits(std::slice::from_ref(&commit)).await?;
+        }
+
+        let output = match &target {
+            GitRef::Branch(branch) => {
+                self.run("Git checkout", self.backend.checkout_branch(branch, &commit))
+                    .await?
+            }
+            GitRef::Tag(_) | GitRef::Commit(_) => {
+                self.run("Git checkout", self.backend.checkout_detached(&commit))
+                    .await?
+            }
+        };
+
+        // Store the full hash so the pin does not depend on abbreviation
+        let target = match target {
+            GitRef::Commit(_) => GitRef::Commit(commit),
+            other => other,
+        };
+        self.set_target(target);
+        Ok(self.update_extras_after(output).await)
+    }
+
+    /// Pulls with the configured strategy, refusing to when tracked files are modified
+    pub async fn pull(&self) -> Result<PullResult> {
+        self.pull_with(&PullOptions::default()).await
+    }
+
+    /// Fetches the remote branch and integrates it into the checked out branch
+    ///
+    /// Fails with [`PullFailure::Dirty`] when tracked files have uncommitted changes,
+    /// unless `options.allow_dirty` is set or the strategy discards them anyway.
+    pub async fn pull_with(&self, options: &PullOptions) -> Result<PullResult> {
+        let strategy = options.strategy.unwrap_or(self.pull_strategy);
+        let target = self.target();
+        info!("Pulling {} from {} ({})", target, self.remote, strategy);
+
+        if !options.allow_dirty && strategy != PullStrategy::ResetToRemote {
+            let working_tree = self.working_tree_status().await?;
+            if working_tree.is_dirty() {
+                return Err(MonitorError::Pull {
+                    kind: PullFailure::Dirty,
+                    message: format!(
+                        "uncommitted changes to {}; stash or discard them first",
+                        working_tree.modified.join(", ")
+                    ),
+                });
+            }
+        }
+
+        let previous_commit = self.get_local_commit().await?;
+        self.fetch().await.map_err(|e| MonitorError::Pull {
+            kind: PullFailure::Network,
+            message: e.to_string(),
+        })?;
+
+        let upstream = self.upstream(&target);
+        self.verify_incoming(&previous_commit, &upstream).await?;
+        let output = match (&target, strategy) {
+            (GitRef::Branch(_), _) => {
+                self.run("Git pull", self.backend.integrate(&upstream, strategy))
+                    .await?
+            }
+            (_, PullStrategy::ResetToRemote) => self.run("Git reset", self.backend.reset_hard(&upstream)).await?,
+            // A moved tag is simply checked out again
+            _ => {
+                self.run("Git checkout", self.backend.checkout_detached(&upstream))
+                    .await?
+            }
+        };
+        let CheckoutResult { output, extras_error } = self.update_extras_after(output).await;
+        let commit = self.get_local_commit().await?;
+
+        let already_up_to_date = previous_commit == commit;
+        let files_changed = if already_up_to_date {
+            0
+        } else {
+            self.changed_files(&previous_commit, &commit).await?.len()
+        };
+
+        let result = PullResult {
+            already_up_to_date,
+            files_changed,
+            previous_commit,
+            commit,
+            output,
+            extras_error,
+        };
+
+        info!("Pull completed: {} files changed", result.files_changed);
+        Ok(result)
+    }
+
+    pub async fn get_status(&self) -> Result<GitStatus> {
+        debug!("Getting git status for repository at {}", self.repo_path);
+
+        let local_commit = self.get_local_commit().await?;
+        let remote_commit = self.get_remote_commit().await?;
+        let current_branch = self
+            .run("Getting current branch", self.backend.current_branch())
+            .await?;
+
+        let updates_available = local_commit != remote_commit;
+        let (ahead, behind) = self
+            .run("Counting commits", self.backend.ahead_behind(&local_commit, &remote_commit))
+            .await?;
+        let working_tree = self.working_tree_status().await?;
+        let stashes = self.stash_list().await?.len();
+        let submodules = self
+            .run("Reading submodule status", self.backend.submodule_status())
+            .await?;
+        let signature = match self.signatures.mode {
+            VerifyMode::Off => None,
+            _ => Some(self.commit_signature(&local_commit).await?),
+        };
+
+        let status = GitStatus {
+            local_commit,
+            remote_commit,
+            updates_available,
+            current_branch,
+            target: self.target(),
+            ahead,
+            behind,
+            working_tree,
+            stashes,
+            submodules,
+            signature,
+        };
+
+        debug!("Git status: {:?}", status);
+        Ok(status)
+    }
+
+    pub async fn get_local_commit(&self) -> Result<String> {
+        self.run("Resolving HEAD", self.backend.resolve_commit("HEAD"))
+            .await
+    }
+
+    /// Commit the deployed ref points to on the remote, as of the last fetch
+    pub async fn get_remote_commit(&self) -> Result<String> {
+        let upstream = self.upstream(&self.target());
+        self.run("Resolving remote commit", self.backend.resolve_commit(&upstream))
+            .await
+    }
+
+    /// Lists the paths changed between two commits, relative to the repository root
+    pub async fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>> {
+        self.run("Listing changed files", self.backend.changed_files(from, to))
+            .await
+    }
+
+    /// Lists the commits on the remote branch that are not checked out yet, newest first
+    pub async fn incoming_commits(&self) -> Result<Vec<CommitInfo>> {
+        let local_commit = self.get_local_commit().await?;
+        let remote_commit = self.get_remote_commit().await?;
+        self.run(
+            "Listing incoming commits",
+            self.backend.log(&local_commit, &remote_commit, MAX_INCOMING_COMMITS),
+        )
+        .await
+    }
+
+    /// File-level diffstat and unified diff from the checked out commit to the remote branch
+    pub async fn incoming_diff(&self) -> Result<DiffSummary> {
+        let from = self.get_local_commit().await?;
+        let to = self.get_remote_commit().await?;
+        let (files, mut patch) = self
+            .run("Diffing incoming changes", self.backend.diff(&from, &to))
+            .await?;
+
+        let truncated = patch.len() > MAX_PATCH_BYTES;
+        if truncated {
+            let mut end = MAX_PATCH_BYTES;
+            while !patch.is_char_boundary(end) {
+                end -= 1;
+            }
+            patch.truncate(end);
+        }
+
+        Ok(DiffSummary {
+            from,
+            to,
+            files,
+            patch,
+            truncated,
+        })
+    }
+
+    /// Resets the working tree and current branch to `commit`, discarding local changes
+    pub async fn reset_hard(&self, commit: &str) -> Result<CheckoutResult> {
+        info!("Resetting repository to {}", commit);
+        let output = self.run("Git reset", self.backend.reset_hard(commit)).await?;
+        Ok(self.update_extras_after(output).await)
+    }
+
+    /// Checks the signatures required by the policy on the commits between `local` and `upstream`
+    async fn verify_incoming(&self, local: &str, upstream: &str) -> Result<()> {
+        let head = self
+            .run("Resolving remote commit", self.backend.resolve_commit(upstream))
+            .await?;
+        let commits = match self.signatures.mode {
+            VerifyMode::Off => return Ok(()),
+            _ if head == local => return Ok(()),
+            VerifyMode::Head => vec![head],
+            VerifyMode::All => self
+                .run("Listing incoming commits", self.backend.log(local, &head, usize::MAX))
+                .await?
+                .into_iter()
+                .map(|commit| commit.hash)
+                .collect(),
+        };
+        self.verify_commits(&commits).await
+    }
+
+    /// Fails with [`MonitorError::Signature`] unless every commit has a good signature
+    async fn verify_commits(&self, commits: &[String]) -> Result<()> {
+        for commit in commits {
+            let signature = self.commit_signature(commit).await?;
+            if !signature.is_good() {
+                warn!("Refusing commit {}: {}", commit, signature.status);
+                return Err(MonitorError::Signature(format!(
+                    "commit {} is {}{}",
+                    &commit[..commit.len().min(8)],
+                    signature.status,
+                    signature.signer.map(|signer| format!(" ({})", signer)).unwrap_or_default()
+                )));
+            }
+            debug!("Commit {} signed by {:?}", commit, signature.signer);
+        }
+        Ok(())
+    }
+
+    /// Checks the signature on `commit` against the configured keys
+    pub async fn commit_signature(&self, commit: &str) -> Result<CommitSignature> {
+        self.run(
+            "Verifying signature",
+            self.backend.commit_signature(commit, &self.signatures),
+        )
+        .await
+    }
+
+    /// Brings submodules and LFS objects in line with the checked out commit, as configured
+    /// Updates submodules and LFS objects once HEAD has moved. HEAD stays where it is
+    /// either way, so a failure is reported with the result rather than as an error.
+    async fn update_extras_after(&self, output: String) -> CheckoutResult {
+        match self.update_extras().await {
+            Ok(extras) => CheckoutResult {
+                output: output + &extras,
+                extras_error: None,
+            },
+            Err(e) => {
+                warn!("HEAD moved, but updating submodules or LFS objects failed: {}", e);
+                CheckoutResult {
+                    output,
+                    extras_error: Some(e.to_string()),
+                }
+            }
+        }
+    }
+
+    async fn update_extras(&self) -> Result<String> {
+        let mut output = String::new();
+        if self.updates.submodules {
+            info!("Updating submodules in {}", self.repo_path);
+            output.push_str(
+                &self
+                    .run("Submodule update", self.backend.update_submodules())
+                    .await?,
+            );
+        }
+        if self.updates.lfs {
+            info!("Fetching LFS objects in {}", self.repo_path);
+            output.push_str(&self.run("Git LFS pull", self.backend.lfs_pull(&self.remote)).await?);
+        }
+        Ok(output)
+    }
+
+    pub async fn working_tree_status(&self) -> Result<WorkingTreeStatus> {
+        self.run("Reading working tree status", self.backend.working_tree_status())
+            .await
+    }
+
+    /// Stashes local modifications and untracked files
+    pub async fn stash(&self) -> Result<String> {
+        info!("Stashing local changes in {}", self.repo_path);
+        let message = format!("ghd-manager {}", Utc::now().format("%Y-%m-%d %H:%M:%S UTC"));
+        self.run("Git stash", self.backend.stash(&message)).await
+    }
+
+    /// Restores the most recent stash
+    pub async fn stash_pop(&self) -> Result<String> {
+        info!("Restoring stashed changes in {}", self.repo_path);
+        self.run("Git stash pop", self.backend.stash_pop()).await
+    }
+
+    /// Descriptions of the stashes, most recent first
+    pub async fn stash_list(&self) -> Result<Vec<String>> {
+        self.run("Listing stashes", self.backend.stash_list()).await
+    }
+
+    /// Reverts modified tracked files and deletes untracked files
+    pub async fn discard_changes(&self) -> Result<String> {
+        info!("Discarding local changes in {}", self.repo_path);
+        self.run("Discarding changes", self.backend.discard_changes())
+            .await
+    }
+
+    pub fn pull_strategy(&self) -> PullStrategy {
+        self.pull_strategy
+    }
+
+    pub fn repo_path(&self) -> &str {
+        &self.repo_path
+    }
+
+    /// Whether the repository path holds a git repository yet
+    pub fn is_cloned(&self) -> bool {
+        Path::new(&self.repo_path).join(".git").exists()
+    }
+
+    /// Clones `url` into the repository path at `branch`; `depth` limits the history fetched
+    pub async fn clone_repository(&self, url: &str, branch: &str, depth: Option<u32>) -> Result<CheckoutResult> {
+        info!("Cloning {} ({}) into {}", url, branch, self.repo_path);
+        let output = self
+            .run(
+                "Git clone",
+                self.backend.clone_repository(url, &self.remote, branch, depth),
+            )
+            .await?;
+        Ok(self.update_extras_after(output).await)
+    }
+
+    pub async fn validate_repository(&self) -> Result<()> {
+        let repo_path = Path::new(&self.repo_path);
+
+        if !repo_path.exists() {
+            return Err(MonitorError::Git(format!("Repository path does not exist: {}", self.repo_path)));
+        }
+
+        let git_dir = repo_path.join(".git");
+        if !git_dir.exists() {
+            return Err(MonitorError::Git(format!("Not a git repository: {}", self.repo_path)));
+        }
+
+        if !self
+            .run("Validating remote", self.backend.has_remote(&self.remote))
+            .await?
+        {
+            return Err(MonitorError::Git(format!("Remote '{}' not found in repository", self.remote)));
+        }
+
+        info!("Repository validation successful: {}", self.repo_path);
+        Ok(())
+    }
+}
+
+/// A commit as shown in the incoming changes list
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CommitInfo {
+    pub hash: String,
+    pub author: String,
+    pub email: String,
+    pub date: DateTime<Utc>,
+    pub subject: String,
+}
+
+/// Lines added and removed in one file (`None` for binary files)
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FileChange {
+    pub path: String,
+    pub additions: Option<usize>,
+    pub deletions: Option<usize>,
+}
+
+/// Difference between the checked out commit and the remote branch
+#[derive(Debug, Clone, Serialize)]
+pub struct DiffSummary {
+    pub from: String,
+    pub to: String,
+    pub files: Vec<FileChange>,
+    pub patch: String,
+    /// Whether `patch` was cut off at [`MAX_PATCH_BYTES`]
+    pub truncated: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct PullResult {
+    pub already_up_to_date: bool,
+    pub files_changed: usize,
+    /// Commit checked out before the pull
+    pub previous_commit: String,
+    /// Commit checked out after the pull
+    pub commit: String,
+    pub output: String,
+    /// Why updating submodules or LFS objects failed after the new commit was checked out
+    pub extras_error: Option<String>,
+}
+
+/// Result of an operation that moved HEAD: a checkout, reset or clone
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckoutResult {
+    pub output: String,
+    /// Why updating submodules or LFS objects failed after the new commit was checked out
+    pub extras_error: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process::Command;
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .expect("Failed to run git");
+        assert!(status.status.success(), "git {:?} failed: {:?}", args, status);
+    }
+
+    /// Creates an upstream repository and a clone of it, returning (upstream, clone)
+    fn setup(name: &str) -> (PathBuf, PathBuf) {
+        let root = std::env::temp_dir().join(format!("ghd-git-test-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let upstream = root.join("upstream");
+        std::fs::create_dir_all(&upstream).unwrap();
+
+        git(&upstream, &["init", "-q", "-b", "main"]);
+        std::fs::write(upstream.join("README.md"), "hello\n").unwrap();
+        git(&upstream, &["add", "."]);
+        git(&upstream, &["commit", "-q", "-m", "initial"]);
+        git(&root, &["clone", "-q", "upstream", "clone"]);
+        let clone = root.join("clone");
+        // Merge and rebase create commits in the clone
+        git(&clone, &["config", "user.name", "Test"]);
+        git(&clone, &["config", "user.email", "test@example.com"]);
+
+        (upstream, clone)
+    }
+
+    const KINDS: [GitBackendKind; 2] = [GitBackendKind::Cli, GitBackendKind::Native];
+
+    /// How a test manager differs from one tracking origin/main
+    struct ManagerOptions {
+        remote: &'static str,
+        branch: &'static str,
+        update: UpdateOptions,
+        timeout: Duration,
+    }
+
+    impl Default for ManagerOptions {
+        fn default() -> Self {
+            Self {
+                remote: "origin",
+                branch: "main",
+                update: UpdateOptions::default(),
+                timeout: Duration::from_secs(30),
+            }
+        }
+    }
+
+    /// A manager for `repo` tracking origin/main
+    fn manager(repo: &Path, kind: GitBackendKind) -> GitManager {
+        manager_with(repo, kind, ManagerOptions::default())
+    }
+
+    fn manager_with(repo: &Path, kind: GitBackendKind, options: ManagerOptions) -> GitManager {
+        GitManager::new(
+            repo.to_string_lossy(),
+            options.remote,
+            options.branch,
+            kind,
+            PullStrategy::FfOnly,
+            options.update,
+            options.timeout,
+        )
+    }
+
+    /// Commits a change to README.md in `dir`
+    fn commit_readme(dir: &Path, contents: &str, message: &str) {
+        std::fs::write(dir.join("README.md"), contents).unwrap();
+        git(dir, &["commit", "-q", "-am", message]);
+    }
+
+    #[tokio::test]
+    async fn test_backends_report_structured_pull_results() {
+        for kind in KINDS {
+            let (upstream, clone) = setup(&kind.to_string());
+            let manager = manager(&clone, kind);
+            manager.validate_repository().await.unwrap();
+
+            std::fs::create_dir_all(upstream.join("web")).unwrap();
+            std::fs::write(upstream.join("web/index.html"), "<h1>hi</h1>\n").unwrap();
+            std::fs::write(upstream.join("README.md"), "hello again\n").unwrap();
+            git(&upstream, &["add", "."]);
+            git(&upstream, &["commit", "-q", "-m", "update"]);
+
+            manager.fetch().await.unwrap();
+            let status = manager.get_status().await.unwrap();
+            assert!(status.updates_available, "{}", kind);
+            assert_eq!(status.current_branch, "main");
+
+            let incoming = manager.incoming_commits().await.unwrap();
+            assert_eq!(incoming.len(), 1, "{}", kind);
+            assert_eq!(incoming[0].subject, "update");
+            assert_eq!(incoming[0].author, "Test");
+            assert_eq!(incoming[0].hash, status.remote_commit);
+
+            let diff = manager.incoming_diff().await.unwrap();
+            let mut files = diff.files.clone();
+            files.sort_by(|a, b| a.path.cmp(&b.path));
+            assert_eq!(
+                files,
+                vec![
+                    FileChange { path: "README.md".to_string(), additions: Some(1), deletions: Some(1) },
+                    FileChange { path: "web/index.html".to_string(), additions: Some(1), deletions: Some(0) },
+                ],
+                "{}",
+                kind
+            );
+            assert!(diff.patch.contains("+hello again"), "{}", kind);
+            assert!(!diff.truncated);
+
+            let pull = manager.pull().await.unwrap();
+            assert!(!pull.already_up_to_date, "{}", kind);
+            assert_eq!(pull.files_changed, 2, "{}", kind);
+            assert_eq!(pull.commit, status.remote_commit);
+            let mut changed = manager.changed_files(&pull.previous_commit, &pull.commit).await.unwrap();
+            changed.sort();
+            assert_eq!(changed, vec!["README.md".to_string(), "web/index.html".to_string()]);
+
+            assert!(manager.pull().await.unwrap().already_up_to_date, "{}", kind);
+            assert!(manager.incoming_commits().await.unwrap().is_empty());
+            assert!(!manager.get_status().await.unwrap().updates_available);
+
+            manager.reset_hard(&pull.previous_commit).await.unwrap();
+            assert_eq!(manager.get_local_commit().await.unwrap(), pull.previous_commit);
+
+            let _ = std::fs::remove_dir_all(upstream.parent().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_operations_time_out() {
+        let (upstream, clone) = setup("timeout");
+        let options = ManagerOptions { timeout: Duration::from_millis(10), ..Default::default() };
+        let manager = manager_with(&clone, GitBackendKind::Cli, options);
+        let slow = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(())
+        };
+        let err = manager.run("Git fetch", slow).await.unwrap_err();
+        assert!(err.to_string().contains("timed out"), "{}", err);
+
+        let _ = std::fs::remove_dir_all(upstream.parent().unwrap());
+    }
+
+    /// Where the clone stands relative to the remote before a pull
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Scenario {
+        /// A local commit the remote does not have
+        Ahead,
+        /// Uncommitted edits while HEAD is at the remote
+        Dirty,
+        /// Local and remote commits to different files
+        Diverged,
+        /// Local and remote commits to the same line
+        Conflicting,
+        /// The remote cannot be fetched
+        Unreachable,
+    }
+
+    /// What a pull is expected to do
+    #[derive(Debug, Clone, Copy)]
+    enum Outcome {
+        /// Nothing to pull; local commits stay
+        UpToDate,
+        /// Local and remote commits both end up in HEAD
+        Integrates,
+        /// HEAD and the working tree match the remote
+        Resets,
+        /// The pull fails and leaves HEAD and the working tree alone
+        Fails(PullFailure),
+    }
+
+    #[tokio::test]
+    async fn test_pull_strategies() {
+        use Outcome::*;
+        use PullStrategy::*;
+        use Scenario::*;
+
+        let cases = [
+            (Ahead, FfOnly, UpToDate),
+            (Ahead, Rebase, UpToDate),
+            (Ahead, Merge, UpToDate),
+            (Ahead, ResetToRemote, Resets),
+            (Dirty, FfOnly, Fails(PullFailure::Dirty)),
+            (Dirty, Rebase, Fails(PullFailure::Dirty)),
+            (Dirty, Merge, Fails(PullFailure::Dirty)),
+            (Dirty, ResetToRemote, Resets),
+            (Diverged, FfOnly, Fails(PullFailure::Diverged)),
+            (Diverged, Rebase, Integrates),
+            (Diverged, Merge, Integrates),
+            (Diverged, ResetToRemote, Resets),
+            (Conflicting, FfOnly, Fails(PullFailure::Diverged)),
+            (Conflicting, Rebase, Fails(PullFailure::Conflict)),
+            (Conflicting, Merge, Fails(PullFailure::Conflict)),
+            (Conflicting, ResetToRemote, Resets),
+            (Unreachable, FfOnly, Fails(PullFailure::Network)),
+        ];
+
+        for kind in KINDS {
+            for (scenario, strategy, outcome) in cases {
+                let case = format!("{} {:?} {}", kind, scenario, strategy);
+                let (upstream, clone) = setup(&format!("strategies-{}-{:?}-{}", kind, scenario, strategy));
+                match scenario {
+                    Ahead => commit_readme(&clone, "local\n", "local readme"),
+                    Dirty => std::fs::write(clone.join("README.md"), "edited\n").unwrap(),
+                    Diverged => {
+                        std::fs::write(upstream.join("NOTES.md"), "notes\n").unwrap();
+                        git(&upstream, &["add", "."]);
+                        git(&upstream, &["commit", "-q", "-m", "upstream change"]);
+                        std::fs::write(clone.join("LOCAL.md"), "local\n").unwrap();
+                        git(&clone, &["add", "."]);
+                        git(&clone, &["commit", "-q", "-m", "local change"]);
+                    }
+                    Conflicting => {
+                        commit_readme(&upstream, "upstream\n", "upstream readme");
+                        commit_readme(&clone, "local\n", "local readme");
+                    }
+                    Unreachable => git(&clone, &["remote", "add", "unreachable", "/nonexistent/ghd-upstream"]),
+                }
+                let remote = if scenario == Unreachable { "unreachable" } else { "origin" };
+                let manager = manager_with(&clone, kind, ManagerOptions { remote, ..Default::default() });
+                let before = manager.get_local_commit().await.unwrap();
+                let readme = std::fs::read_to_string(clone.join("README.md")).unwrap();
+
+                let result = manager.pull_with(&PullOptions { strategy: Some(strategy), ..Default::default() }).await;
+                match outcome {
+                    UpToDate => {
+                        let pull = result.unwrap();
+                        assert!(pull.already_up_to_date, "{}", case);
+                        assert_eq!(pull.commit, before, "{}", case);
+                        assert_eq!(std::fs::read_to_string(clone.join("README.md")).unwrap(), readme, "{}", case);
+                    }
+                    Integrates => {
+                        assert!(!result.unwrap().already_up_to_date, "{}", case);
+                        assert!(clone.join("NOTES.md").exists(), "{}", case);
+                        assert!(clone.join("LOCAL.md").exists(), "{}", case);
+                        assert!(manager.incoming_commits().await.unwrap().is_empty(), "{}", case);
+                    }
+                    Resets => {
+                        let pull = result.unwrap();
+                        assert_eq!(pull.commit, manager.get_status().await.unwrap().remote_commit, "{}", case);
+                        let read = |repo: &Path| std::fs::read_to_string(repo.join("README.md")).unwrap();
+                        assert_eq!(read(&clone), read(&upstream), "{}", case);
+                        assert!(!clone.join("LOCAL.md").exists(), "{}", case);
+                    }
+                    Fails(failure) => {
+                        let err = result.unwrap_err();
+                        let failed = matches!(&err, MonitorError::Pull { kind, .. } if *kind == failure);
+                        assert!(failed, "{}: {}", case, err);
+                        if failure == PullFailure::Conflict {
+                            assert!(err.to_string().contains("README.md"), "{}: {}", case, err);
+                        }
+                        // A failed attempt is aborted and leaves the tree as it was
+                        assert_eq!(manager.get_local_commit().await.unwrap(), before, "{}", case);
+                        assert_eq!(std::fs::read_to_string(clone.join("README.md")).unwrap(), readme, "{}", case);
+                    }
+                }
+
+                let _ = std::fs::remove_dir_all(upstream.parent().unwrap());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dirty_tree_blocks_pull_until_stashed() {
+        for kind in KINDS {
+            let (upstream, clone) = setup(&format!("dirty-{}", kind));
+            commit_readme(&upstream, "upstream\n", "upstream readme");
+
+            let manager = manager(&clone, kind);
+            manager.fetch().await.unwrap();
+            std::fs::write(clone.join("README.md"), "hand edited\n").unwrap();
+            std::fs::write(clone.join("scratch.txt"), "scratch\n").unwrap();
+
+            let status = manager.get_status().await.unwrap();
+            assert_eq!(status.working_tree.modified, vec!["README.md".to_string()], "{}", kind);
+            assert_eq!(status.working_tree.untracked, vec!["scratch.txt".to_string()], "{}", kind);
+            assert_eq!((status.ahead, status.behind), (0, 1), "{}", kind);
+            assert_eq!(status.stashes, 0);
+
+            let err = manager.pull().await.unwrap_err();
+            assert!(matches!(err, MonitorError::Pull { kind: PullFailure::Dirty, .. }), "{}: {}", kind, err);
+
+            manager.stash().await.unwrap();
+            let status = manager.get_status().await.unwrap();
+            assert_eq!(status.working_tree, WorkingTreeStatus::default(), "{}", kind);
+            assert_eq!(status.stashes, 1, "{}", kind);
+
+            manager.pull().await.unwrap();
+            assert_eq!(std::fs::read_to_string(clone.join("README.md")).unwrap(), "upstream\n");
+
+            // Popping onto the changed README conflicts; discard restores a clean tree
+            let _ = manager.stash_pop().await;
+            manager.discard_changes().await.unwrap();
+            let status = manager.get_status().await.unwrap();
+            assert_eq!(status.working_tree, WorkingTreeStatus::default(), "{}", kind);
+            assert!(!clone.join("scratch.txt").exists(), "{}", kind);
+
+            let _ = std::fs::remove_dir_all(upstream.parent().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checkout_branches_tags_and_commits() {
+        for kind in KINDS {
+            let (upstream, clone) = setup(&format!("checkout-{}", kind));
+            let first = String::from_utf8(
+                Command::new("git").args(["rev-parse", "HEAD"]).current_dir(&upstream).output().unwrap().stdout,
+            )
+            .unwrap()
+            .trim()
+            .to_string();
+            git(&upstream, &["tag", "-a", "v1.0", "-m", "release"]);
+            git(&upstream, &["checkout", "-q", "-b", "hotfix"]);
+            commit_readme(&upstream, "hotfix\n", "hotfix");
+            git(&upstream, &["checkout", "-q", "main"]);
+
+            let manager = manager(&clone, kind);
+
+            let refs = manager.remote_refs().await.unwrap();
+            let names: Vec<_> = refs.branches.iter().map(|r| r.name.as_str()).collect();
+            assert_eq!(names, vec!["hotfix", "main"], "{}", kind);
+            assert_eq!(refs.tags, vec![RemoteRef { name: "v1.0".to_string(), commit: first.clone() }], "{}", kind);
+
+            manager.checkout(GitRef::Branch("hotfix".to_string())).await.unwrap();
+            let status = manager.get_status().await.unwrap();
+            assert_eq!(status.current_branch, "hotfix", "{}", kind);
+            assert_eq!(status.target, GitRef::Branch("hotfix".to_string()));
+            assert!(!status.updates_available);
+            assert_eq!(std::fs::read_to_string(clone.join("README.md")).unwrap(), "hotfix\n");
+
+            manager.checkout(GitRef::Tag("v1.0".to_string())).await.unwrap();
+            let status = manager.get_status().await.unwrap();
+            assert_eq!(status.current_branch, "HEAD", "{}", kind);
+            assert_eq!(status.local_commit, first);
+            assert!(!status.updates_available);
+            assert!(manager.pull().await.unwrap().already_up_to_date, "{}", kind);
+            assert_eq!(manager.tracked_branch(), None);
+
+            manager.checkout(GitRef::Commit(first[..10].to_string())).await.unwrap();
+            assert_eq!(manager.target(), GitRef::Commit(first.clone()));
+            manager.fetch().await.unwrap();
+            assert!(!manager.get_status().await.unwrap().updates_available);
+
+            manager.checkout(GitRef::Branch("main".to_string())).await.unwrap();
+            assert_eq!(manager.get_status().await.unwrap().current_branch, "main", "{}", kind);
+
+            assert!(manager.checkout(GitRef::Branch("--force".to_string())).await.is_err());
+            assert!(manager.checkout(GitRef::Commit("not-a-sha".to_string())).await.is_err());
+
+            let _ = std::fs::remove_dir_all(upstream.parent().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_pull_updates_submodules() {
+        for kind in KINDS {
+            let (upstream, clone) = setup(&format!("submodules-{}", kind));
+            let library = upstream.parent().unwrap().join("library");
+            std::fs::create_dir_all(&library).unwrap();
+            git(&library, &["init", "-q", "-b", "main"]);
+            std::fs::write(library.join("lib.txt"), "v1\n").unwrap();
+            git(&library, &["add", "."]);
+            git(&library, &["commit", "-q", "-m", "v1"]);
+
+            // Git only clones submodules from local paths when told to on the command line;
+            // a repository's own config does not reach the clone, so the fixture clones it
+            git(
+                &upstream,
+                &["-c", "protocol.file.allow=always", "submodule", "add", "-q", library.to_str().unwrap(), "lib"],
+            );
+            git(&upstream, &["commit", "-q", "-m", "add lib"]);
+            git(&clone, &["pull", "-q"]);
+            git(&clone, &["-c", "protocol.file.allow=always", "submodule", "update", "--init", "-q"]);
+
+            let update = UpdateOptions { submodules: true, lfs: false };
+            let manager = manager_with(&clone, kind, ManagerOptions { update, ..Default::default() });
+            let pull = manager.pull().await.unwrap();
+            assert_eq!(pull.extras_error, None, "{}", kind);
+            assert_eq!(std::fs::read_to_string(clone.join("lib/lib.txt")).unwrap(), "v1\n", "{}", kind);
+            let status = manager.get_status().await.unwrap();
+            assert_eq!(status.submodules.len(), 1, "{}", kind);
+            assert_eq!(status.drifted_submodules().count(), 0, "{}", kind);
+
+            // Bump the submodule upstream; the pull moves the checkout along
+            std::fs::write(library.join("lib.txt"), "v2\n").unwrap();
+            git(&library, &["commit", "-q", "-am", "v2"]);
+            git(&upstream.join("lib"), &["pull", "-q", "origin", "main"]);
+            git(&upstream, &["commit", "-q", "-am", "bump lib"]);
+
+            manager.fetch().await.unwrap();
+            manager.pull().await.unwrap();
+            assert_eq!(std::fs::read_to_string(clone.join("lib/lib.txt")).unwrap(), "v2\n", "{}", kind);
+
+            // A submodule that cannot be fetched fails the update, not the pull that moved HEAD
+            std::fs::write(library.join("lib.txt"), "v3\n").unwrap();
+            git(&library, &["commit", "-q", "-am", "v3"]);
+            git(&upstream.join("lib"), &["pull", "-q", "origin", "main"]);
+            git(&upstream, &["commit", "-q", "-am", "bump lib again"]);
+            std::fs::remove_dir_all(&library).unwrap();
+            git(&clone, &["config", "fetch.recurseSubmodules", "false"]);
+
+            manager.fetch().await.unwrap();
+            let pull = manager.pull().await.unwrap();
+            assert!(!pull.already_up_to_date, "{}", kind);
+            assert_eq!(pull.commit, manager.get_status().await.unwrap().remote_commit, "{}", kind);
+            assert!(pull.extras_error.is_some(), "{}", kind);
+            // Nor a reset that moved HEAD
+            let reset = manager.reset_hard(&pull.commit).await.unwrap();
+            assert!(reset.extras_error.is_some(), "{}", kind);
+            assert_eq!(manager.get_local_commit().await.unwrap(), pull.commit, "{}", kind);
+
+            // Drift is reported when the checkout no longer matches the recorded commit
+            git(&clone.join("lib"), &["checkout", "-q", "HEAD~1"]);
+            let status = manager.get_status().await.unwrap();
+            let drifted: Vec<_> = status.drifted_submodules().map(|s| s.path.as_str()).collect();
+            assert_eq!(drifted, vec!["lib"], "{}", kind);
+
+            let _ = std::fs::remove_dir_all(upstream.parent().unwrap());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signature_verification_refuses_untrusted_commits() {
+        for kind in KINDS {
+            let (upstream, clone) = setup(&format!("signatures-{}", kind));
+            let root = upstream.parent().unwrap();
+            let key = root.join("signing_key");
+            let output = Command::new("ssh-keygen")
+                .args(["-q", "-t", "ed25519", "-N", "", "-C", "test", "-f"])
+                .arg(&key)
+                .output()
+                .expect("Failed to run ssh-keygen");
+            assert!(output.status.success(), "ssh-keygen failed: {:?}", output);
+            let public_key = std::fs::read_to_string(root.join("signing_key.pub")).unwrap();
+            let allowed_signers = root.join("allowed_signers");
+            std::fs::write(&allowed_signers, format!("test@example.com namespaces=\"git\" {}", public_key)).unwrap();
+            let signed_commit = |message: &str| {
+                std::fs::write(upstream.join("README.md"), format!("{}\n", message)).unwrap();
+                git(
+                    &upstream,
+                    &["-c", "gpg.format=ssh", "-c", &format!("user.signingkey={}", key.display()), "commit", "-q", "-S", "-am", message],
+                );
+            };
+            let with_policy = |mode| {
+                manager(&clone, kind)
+                .with_signature_policy(SignaturePolicy {
+                    mode,
+                    allowed_signers: Some(allowed_signers.to_string_lossy().into_owned()),
+                    gpg_home: None,
+                })
+            };
+            let head_only = with_policy(VerifyMode::Head);
+            let all = with_policy(VerifyMode::All);
+
+            signed_commit("signed");
+            head_only.fetch().await.unwrap();
+            head_only.pull().await.unwrap();
+            let signature = head_only.get_status().await.unwrap().signature.unwrap();
+            assert_eq!(signature.status, SignatureStatus::Good, "{}", kind);
+            assert_eq!(signature.signer.as_deref(), Some("test@example.com"), "{}", kind);
+
+            // An unsigned commit buried under a signed one only passes the head check
+            commit_readme(&upstream, "unsigned\n", "unsigned");
+            signed_commit("signed again");
+            all.fetch().await.unwrap();
+            assert!(matches!(all.pull().await, Err(MonitorError::Signature(_))), "{}", kind);
+            assert_eq!(std::fs::read_to_string(clone.join("README.md")).unwrap(), "signed\n");
+            head_only.pull().await.unwrap();
+            assert_eq!(std::fs::read_to_string(clone.join("README.md")).unwrap(), "signed again\n");
+
+            commit_readme(&upstream, "unsigned head\n", "unsigned head");
+            head_only.fetch().await.unwrap();
+            match head_only.pull().await {
+                Err(MonitorError::Signature(message)) => assert!(message.contains("not signed"), "{}", message),
+                other => panic!("expected a signature failure, got {:?}", other),
+            }
+
+            let _ = std::fs::remove_dir_all(root);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_clone_missing_repository() {
+        for kind in KINDS {
+            let (upstream, _) = setup(&format!("clone-{}", kind));
+            let root = upstream.parent().unwrap();
+            commit_readme(&upstream, "second\n", "second");
+            git(&upstream, &["branch", "release"]);
+            commit_readme(&upstream, "third\n", "third");
+
+            let manager = |path: &str| {
+                let options = ManagerOptions { remote: "upstream", branch: "release", ..Default::default() };
+                manager_with(&root.join(path), kind, options)
+            };
+            let url = format!("file://{}", upstream.display());
+
+            let full = manager("deploy/full");
+            assert!(!full.is_cloned());
+            full.clone_repository(&url, "release", None).await.unwrap();
+            assert!(full.is_cloned(), "{}", kind);
+            full.validate_repository().await.unwrap();
+            let status = full.get_status().await.unwrap();
+            assert_eq!(status.current_branch, "release", "{}", kind);
+            assert!(!status.updates_available, "{}", kind);
+            assert_eq!(std::fs::read_to_string(root.join("deploy/full/README.md")).unwrap(), "second\n");
+
+            assert!(manager("deploy/missing").clone_repository(&url, "no-such-branch", None).await.is_err());
+
+            // libgit2's local transport cannot make shallow clones
+            if kind == GitBackendKind::Native {
+                let _ = std::fs::remove_dir_all(root);
+                continue;
+            }
+            let shallow = manager("deploy/shallow");
+            shallow.clone_repository(&url, "release", Some(1)).await.unwrap();
+            let commits = Command::new("git")
+                .args(["rev-list", "--count", "HEAD"])
+                .current_dir(root.join("deploy/shallow"))
+                .output()
+                .unwrap();
+            assert_eq!(String::from_utf8_lossy(&commits.stdout).trim(), "1", "{}", kind);
+
+            // A shallow clone still follows the remote branch
+            git(&upstream, &["checkout", "-q", "release"]);
+            commit_readme(&upstream, "fourth\n", "fourth");
+            shallow.fetch().await.unwrap();
+            assert!(!shallow.pull().await.unwrap().already_up_to_date, "{}", kind);
+            assert_eq!(std::fs::read_to_string(root.join("deploy/shallow/README.md")).unwrap(), "fourth\n");
+
+            let _ = std::fs::remove_dir_all(root);
+        }
+    }
+
+    #[test]
+    fn test_pull_strategy_from_str() {
+        assert_eq!("Rebase".parse::<PullStrategy>(), Ok(PullStrategy::Rebase));
+        assert_eq!("reset-to-remote".parse::<PullStrategy>(), Ok(PullStrategy::ResetToRemote));
+        assert!("squash".parse::<PullStrategy>().is_err());
+        assert_eq!(PullStrategy::FfOnly.to_string().parse::<PullStrategy>(), Ok(PullStrategy::FfOnly));
+    }
+
+    #[test]
+    fn test_backend_kind_from_str() {
+        assert_eq!("Native".parse::<GitBackendKind>(), Ok(GitBackendKind::Native));
+        assert_eq!(" cli ".parse::<GitBackendKind>(), Ok(GitBackendKind::Cli));
+        assert!("gix".parse::<GitBackendKind>().is_err());
+    }
+}
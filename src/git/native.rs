@@ -0,0 +1,784 @@
+use super::{
+    CliBackend, CommitInfo, CommitSignature, FileChange, GitBackend, GitCredentials, PullStrategy, SignaturePolicy,
+    SubmoduleStatus, WorkingTreeStatus, TOKEN_USERNAME,
+};
+use crate::error::{MonitorError, PullFailure, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use chrono::{TimeZone, Utc};
+use git2::build::{CheckoutBuilder, RepoBuilder};
+use git2::cert::CertHostkey;
+use git2::{
+    AnnotatedCommit, AutotagOption, CertificateCheckStatus, Cred, CredentialType, DiffFormat, Direction, ErrorCode, FetchOptions, Index, Oid, Patch, RemoteCallbacks,
+    Repository, ResetType, Signature, Sort, StashFlags, Status, StatusOptions, SubmoduleUpdateOptions,
+};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+/// Number of times a credential callback may be invoked before fetching gives up
+const MAX_CREDENTIAL_ATTEMPTS: usize = 3;
+
+/// Uses libgit2 directly; results come from repository objects rather than command output
+///
+/// libgit2 calls block, so every operation runs on the blocking thread pool.
+/// Fetches abort themselves once `timeout` has passed, since a blocking task
+/// cannot be cancelled from the outside.
+#[derive(Debug, Clone)]
+pub struct NativeBackend {
+    repo_path: String,
+    timeout: Duration,
+    credentials: GitCredentials,
+}
+
+impl NativeBackend {
+    pub fn new(repo_path: impl Into<String>, timeout: Duration) -> Self {
+        Self {
+            repo_path: repo_path.into(),
+            timeout,
+            credentials: GitCredentials::default(),
+        }
+    }
+
+    pub fn with_credentials(mut self, credentials: GitCredentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    /// CLI backend for the operations libgit2 cannot do
+    fn cli(&self) -> CliBackend {
+        CliBackend::new(self.repo_path.clone()).with_credentials(self.credentials.clone())
+    }
+
+    /// Runs `f` on the blocking thread pool
+    async fn blocking<T, F>(&self, f: F) -> Result<T>
+    where
+        F: FnOnce(&NativeBackend) -> Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let backend = self.clone();
+        tokio::task::spawn_blocking(move || f(&backend))
+            .await
+            .map_err(|e| MonitorError::Git(format!("Git task failed: {}", e)))?
+    }
+
+    // git2 repositories are not Sync, so each operation opens its own handle
+    fn open(&self) -> Result<Repository> {
+        Repository::open(&self.repo_path).map_err(|e| git_error("Failed to open repository", e))
+    }
+
+    /// Callbacks for talking to a remote; `config` supplies credential helpers
+    fn remote_callbacks(&self, config: git2::Config, deadline: Instant) -> Result<RemoteCallbacks<'static>> {
+        let ssh_key = self.credentials.ssh_key.clone();
+        let token = self.credentials.token()?;
+        let mut attempts = 0;
+
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username, allowed| {
+            attempts += 1;
+            if attempts > MAX_CREDENTIAL_ATTEMPTS {
+                return Err(git2::Error::from_str("No usable credentials for remote"));
+            }
+            if allowed.contains(CredentialType::SSH_KEY) {
+                match &ssh_key {
+                    Some(key) => Cred::ssh_key(username.unwrap_or("git"), None, Path::new(key), None),
+                    None => Cred::ssh_key_from_agent(username.unwrap_or("git")),
+                }
+            } else if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                match &token {
+                    Some(token) => Cred::userpass_plaintext(TOKEN_USERNAME, token),
+                    None => Cred::credential_helper(&config, url, username),
+                }
+            } else {
+                Cred::default()
+            }
+        });
+        if let Some(path) = &self.credentials.known_hosts {
+            let known_hosts = std::fs::read_to_string(path)
+                .map_err(|e| MonitorError::Git(format!("Failed to read known_hosts file {}: {}", path, e)))?;
+            callbacks.certificate_check(move |cert, host| match cert.as_hostkey().and_then(CertHostkey::hostkey) {
+                Some(key) if is_known_host(&known_hosts, host, key) => Ok(CertificateCheckStatus::CertificateOk),
+                Some(_) => Err(git2::Error::from_str(&format!(
+                    "Host key for {} is not in the known_hosts file",
+                    host
+                ))),
+                // TLS certificates keep libgit2's own verification
+                None => Ok(CertificateCheckStatus::CertificatePassthrough),
+            });
+        }
+        // Returning false aborts the transfer
+        callbacks.transfer_progress(move |_| Instant::now() < deadline);
+        Ok(callbacks)
+    }
+
+    fn fetch_options(&self, config: git2::Config, deadline: Instant) -> Result<FetchOptions<'static>> {
+        let mut options = FetchOptions::new();
+        options
+            .remote_callbacks(self.remote_callbacks(config, deadline)?)
+            .download_tags(AutotagOption::None);
+        Ok(options)
+    }
+
+    /// Syncs and updates the submodules of `repo` and, recursively, their submodules
+    fn update_submodules_in(&self, repo: &Repository, prefix: &str, output: &mut String) -> Result<()> {
+        let deadline = Instant::now() + self.timeout;
+        let submodules = repo.submodules().map_err(|e| git_error("Submodule update failed", e))?;
+
+        for mut submodule in submodules {
+            let path = format!("{}{}", prefix, submodule.path().display());
+            submodule.sync().map_err(|e| git_error("Submodule sync failed", e))?;
+
+            let mut options = SubmoduleUpdateOptions::new();
+            options.fetch(self.fetch_options(config(repo)?, deadline)?);
+            submodule
+                .update(true, Some(&mut options))
+                .map_err(|e| git_error(&format!("Updating submodule '{}' failed", path), e))?;
+            if let Some(commit) = submodule.head_id() {
+                output.push_str(&format!("Submodule path '{}': checked out '{}'\n", path, commit));
+            }
+
+            let nested = submodule
+                .open()
+                .map_err(|e| git_error(&format!("Opening submodule '{}' failed", path), e))?;
+            self.update_submodules_in(&nested, &format!("{}/", path), output)?;
+        }
+        Ok(())
+    }
+
+    fn find_remote<'r>(repo: &'r Repository, remote: &str) -> Result<git2::Remote<'r>> {
+        repo.find_remote(remote)
+            .map_err(|e| git_error(&format!("Remote '{}' not found", remote), e))
+    }
+
+    fn fetch_into(&self, repo: &Repository, remote: &str, refspecs: &[String]) -> Result<()> {
+        let mut handle = Self::find_remote(repo, remote)?;
+        let deadline = Instant::now() + self.timeout;
+
+        handle
+            .fetch(refspecs, Some(&mut self.fetch_options(config(repo)?, deadline)?), None)
+            .map_err(|e| {
+                if Instant::now() >= deadline {
+                    MonitorError::Git(format!("Git fetch timed out after {}s", self.timeout.as_secs()))
+                } else {
+                    git_error("Git fetch failed", e)
+                }
+            })
+    }
+
+    fn resolve(repo: &Repository, rev: &str) -> Result<Oid> {
+        repo.revparse_single(rev)
+            .and_then(|object| object.peel_to_commit())
+            .map(|commit| commit.id())
+            .map_err(|e| git_error(&format!("Resolving {} failed", rev), e))
+    }
+
+    fn fast_forward(repo: &Repository, target: Oid, upstream: &str) -> Result<String> {
+        let previous = Self::resolve(repo, "HEAD")?;
+        let commit = repo.find_commit(target).map_err(|e| git_error("Git pull failed", e))?;
+        // Update the working tree first so a conflicting local change leaves HEAD untouched
+        repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))
+            .map_err(|e| git_error("Git pull failed", e))?;
+        Self::move_head(repo, target, &format!("pull: fast-forward to {}", upstream))?;
+
+        debug!("Fast-forwarded {} to {}", previous, target);
+        Ok(format!("Fast-forward {}..{}\n", short(previous), short(target)))
+    }
+
+    fn merge(repo: &Repository, target: Oid, upstream: &str) -> Result<String> {
+        let head = repo
+            .head()
+            .and_then(|head| head.peel_to_commit())
+            .map_err(|e| git_error("Git merge failed", e))?;
+        let theirs = repo.find_commit(target).map_err(|e| git_error("Git merge failed", e))?;
+        let mut index = repo
+            .merge_commits(&head, &theirs, None)
+            .map_err(|e| git_error("Git merge failed", e))?;
+        if index.has_conflicts() {
+            return Err(conflict_error("Git merge", &index));
+        }
+
+        let tree_id = index.write_tree_to(repo).map_err(|e| git_error("Git merge failed", e))?;
+        let tree = repo.find_tree(tree_id).map_err(|e| git_error("Git merge failed", e))?;
+        repo.checkout_tree(tree.as_object(), Some(CheckoutBuilder::new().safe()))
+            .map_err(|e| git_error("Git merge failed", e))?;
+
+        let signature = signature(repo)?;
+        let message = format!("Merge {}", upstream);
+        let merge = repo
+            .commit(None, &signature, &signature, &message, &tree, &[&head, &theirs])
+            .map_err(|e| git_error("Git merge failed", e))?;
+        Self::move_head(repo, merge, &format!("pull: merge {}", upstream))?;
+
+        Ok(format!("Merged {} into {} as {}\n", short(target), short(head.id()), short(merge)))
+    }
+
+    fn rebase(repo: &Repository, upstream_commit: &AnnotatedCommit<'_>, upstream: &str) -> Result<String> {
+        let signature = signature(repo)?;
+        let mut rebase = repo
+            .rebase(None, Some(upstream_commit), None, None)
+            .map_err(|e| git_error("Git rebase failed", e))?;
+
+        let mut applied = 0;
+        while let Some(operation) = rebase.next() {
+            let result = operation.map_err(|e| git_error("Git rebase failed", e)).and_then(|_| {
+                let index = repo.index().map_err(|e| git_error("Git rebase failed", e))?;
+                if index.has_conflicts() {
+                    return Err(conflict_error("Git rebase", &index));
+                }
+                match rebase.commit(None, &signature, None) {
+                    Ok(_) => Ok(()),
+                    // The commit's changes are already upstream
+                    Err(e) if e.code() == ErrorCode::Applied => Ok(()),
+                    Err(e) => Err(git_error("Git rebase failed", e)),
+                }
+            });
+            if let Err(e) = result {
+                let _ = rebase.abort();
+                return Err(e);
+            }
+            applied += 1;
+        }
+
+        rebase
+            .finish(Some(&signature))
+            .map_err(|e| git_error("Git rebase failed", e))?;
+        Ok(format!("Rebased {} local commit(s) onto {}\n", applied, upstream))
+    }
+
+    /// Points the checked out branch (or a detached HEAD) at `target`
+    fn move_head(repo: &Repository, target: Oid, message: &str) -> Result<()> {
+        let head = repo.head().map_err(|e| git_error("Git pull failed", e))?;
+        if head.is_branch() {
+            let mut head = head;
+            head.set_target(target, message)
+                .map_err(|e| git_error("Git pull failed", e))?;
+        } else {
+            repo.set_head_detached(target).map_err(|e| git_error("Git pull failed", e))?;
+        }
+        Ok(())
+    }
+
+    fn tree_diff<'r>(repo: &'r Repository, from: &str, to: &str, context: &str) -> Result<git2::Diff<'r>> {
+        let tree = |rev: &str| -> Result<git2::Tree<'_>> {
+            repo.find_commit(Self::resolve(repo, rev)?)
+                .and_then(|commit| commit.tree())
+                .map_err(|e| git_error(context, e))
+        };
+
+        repo.diff_tree_to_tree(Some(&tree(from)?), Some(&tree(to)?), None)
+            .map_err(|e| git_error(context, e))
+    }
+}
+
+#[async_trait]
+impl GitBackend for NativeBackend {
+    async fn fetch(&self, remote: &str, refspecs: &[String]) -> Result<()> {
+        let (remote, refspecs) = (remote.to_string(), refspecs.to_vec());
+        self.blocking(move |backend| backend.fetch_into(&backend.open()?, &remote, &refspecs))
+            .await
+    }
+
+    async fn list_remote(&self, remote: &str) -> Result<Vec<(String, String)>> {
+        let remote = remote.to_string();
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let mut handle = Self::find_remote(&repo, &remote)?;
+            let callbacks = backend.remote_callbacks(config(&repo)?, Instant::now() + backend.timeout)?;
+            let connection = handle
+                .connect_auth(Direction::Fetch, Some(callbacks), None)
+                .map_err(|e| git_error("Listing remote refs failed", e))?;
+
+            let mut refs: Vec<(String, String)> = Vec::new();
+            for head in connection.list().map_err(|e| git_error("Listing remote refs failed", e))? {
+                let name = head.name();
+                if !name.starts_with("refs/heads/") && !name.starts_with("refs/tags/") {
+                    continue;
+                }
+                // Annotated tags are followed by "<tag>^{}" pointing at the commit
+                match name.strip_suffix("^{}") {
+                    Some(tag) => match refs.iter_mut().find(|(existing, _)| existing == tag) {
+                        Some(entry) => entry.1 = head.oid().to_string(),
+                        None => refs.push((tag.to_string(), head.oid().to_string())),
+                    },
+                    None => refs.push((name.to_string(), head.oid().to_string())),
+                }
+            }
+            Ok(refs)
+        })
+        .await
+    }
+
+    async fn checkout_branch(&self, branch: &str, start: &str) -> Result<String> {
+        let (branch, start) = (branch.to_string(), start.to_string());
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let target = Self::resolve(&repo, &start)?;
+            let commit = repo.find_commit(target).map_err(|e| git_error("Git checkout failed", e))?;
+            repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))
+                .map_err(|e| git_error("Git checkout failed", e))?;
+
+            // libgit2 refuses to force-update the checked out branch, so move it directly
+            let refname = format!("refs/heads/{}", branch);
+            let head = repo.head().ok();
+            if head.as_ref().and_then(|head| head.name()) == Some(refname.as_str()) {
+                Self::move_head(&repo, target, &format!("checkout: reset {} to {}", branch, short(target)))?;
+            } else {
+                repo.branch(&branch, &commit, true)
+                    .map_err(|e| git_error("Git checkout failed", e))?;
+                repo.set_head(&refname).map_err(|e| git_error("Git checkout failed", e))?;
+            }
+            Ok(format!("Switched to branch '{}' at {}\n", branch, short(target)))
+        })
+        .await
+    }
+
+    async fn checkout_detached(&self, rev: &str) -> Result<String> {
+        let rev = rev.to_string();
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let target = Self::resolve(&repo, &rev)?;
+            let commit = repo.find_commit(target).map_err(|e| git_error("Git checkout failed", e))?;
+            repo.checkout_tree(commit.as_object(), Some(CheckoutBuilder::new().safe()))
+                .map_err(|e| git_error("Git checkout failed", e))?;
+            repo.set_head_detached(target).map_err(|e| git_error("Git checkout failed", e))?;
+            Ok(format!("HEAD is now at {} {}\n", short(target), commit.summary().unwrap_or_default()))
+        })
+        .await
+    }
+
+    async fn integrate(&self, upstream: &str, strategy: PullStrategy) -> Result<String> {
+        let upstream = upstream.to_string();
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let target = Self::resolve(&repo, &upstream)?;
+            let annotated = repo
+                .find_annotated_commit(target)
+                .map_err(|e| git_error("Git pull failed", e))?;
+            let (analysis, _) = repo
+                .merge_analysis(&[&annotated])
+                .map_err(|e| git_error("Git pull failed", e))?;
+
+            // Also discards local commits and edits when HEAD is ahead of or at the remote
+            if strategy == PullStrategy::ResetToRemote {
+                return reset_to(&repo, target);
+            }
+            if analysis.is_up_to_date() {
+                return Ok(format!("HEAD is up to date with {}\n", upstream));
+            }
+            if analysis.is_fast_forward() {
+                return Self::fast_forward(&repo, target, &upstream);
+            }
+
+            match strategy {
+                PullStrategy::FfOnly => Err(MonitorError::Pull {
+                    kind: PullFailure::Diverged,
+                    message: format!("local branch cannot be fast-forwarded to {}", upstream),
+                }),
+                PullStrategy::Merge => Self::merge(&repo, target, &upstream),
+                PullStrategy::Rebase => Self::rebase(&repo, &annotated, &upstream),
+                PullStrategy::ResetToRemote => unreachable!("handled above"),
+            }
+        })
+        .await
+    }
+
+    async fn resolve_commit(&self, rev: &str) -> Result<String> {
+        let rev = rev.to_string();
+        self.blocking(move |backend| Ok(Self::resolve(&backend.open()?, &rev)?.to_string()))
+            .await
+    }
+
+    async fn current_branch(&self) -> Result<String> {
+        self.blocking(|backend| {
+            let repo = backend.open()?;
+            let head = repo.head().map_err(|e| git_error("Getting current branch failed", e))?;
+            if !head.is_branch() {
+                return Ok("HEAD".to_string());
+            }
+            Ok(head.shorthand().unwrap_or("HEAD").to_string())
+        })
+        .await
+    }
+
+    async fn changed_files(&self, from: &str, to: &str) -> Result<Vec<String>> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let diff = Self::tree_diff(&repo, &from, &to, "Listing changed files failed")?;
+
+            Ok(diff
+                .deltas()
+                .filter_map(|delta| delta.new_file().path().or_else(|| delta.old_file().path()))
+                .map(|path| path.to_string_lossy().to_string())
+                .collect())
+        })
+        .await
+    }
+
+    async fn log(&self, from: &str, to: &str, limit: usize) -> Result<Vec<CommitInfo>> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let mut walk = repo.revwalk().map_err(|e| git_error("Listing commits failed", e))?;
+            walk.set_sorting(Sort::TIME).map_err(|e| git_error("Listing commits failed", e))?;
+            walk.push(Self::resolve(&repo, &to)?)
+                .map_err(|e| git_error("Listing commits failed", e))?;
+            walk.hide(Self::resolve(&repo, &from)?)
+                .map_err(|e| git_error("Listing commits failed", e))?;
+
+            walk.take(limit)
+                .map(|oid| {
+                    let commit = oid
+                        .and_then(|oid| repo.find_commit(oid))
+                        .map_err(|e| git_error("Listing commits failed", e))?;
+                    let author = commit.author();
+                    Ok(CommitInfo {
+                        hash: commit.id().to_string(),
+                        author: author.name().unwrap_or_default().to_string(),
+                        email: author.email().unwrap_or_default().to_string(),
+                        date: Utc.timestamp_opt(author.when().seconds(), 0).single().unwrap_or_default(),
+                        subject: commit.summary().unwrap_or_default().to_string(),
+                    })
+                })
+                .collect()
+        })
+        .await
+    }
+
+    async fn diff(&self, from: &str, to: &str) -> Result<(Vec<FileChange>, String)> {
+        let (from, to) = (from.to_string(), to.to_string());
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            let diff = Self::tree_diff(&repo, &from, &to, "Diffing commits failed")?;
+
+            let mut files = Vec::new();
+            for (index, delta) in diff.deltas().enumerate() {
+                let path = delta
+                    .new_file()
+                    .path()
+                    .or_else(|| delta.old_file().path())
+                    .map(|path| path.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                let patch = Patch::from_diff(&diff, index).map_err(|e| git_error("Diffing commits failed", e))?;
+                // Binary files have no line counts
+                let counts = match patch {
+                    Some(patch) if !delta.flags().is_binary() => {
+                        let (_, additions, deletions) =
+                            patch.line_stats().map_err(|e| git_error("Diffing commits failed", e))?;
+                        (Some(additions), Some(deletions))
+                    }
+                    _ => (None, None),
+                };
+                files.push(FileChange {
+                    path,
+                    additions: counts.0,
+                    deletions: counts.1,
+                });
+            }
+
+            let mut text = String::new();
+            diff.print(DiffFormat::Patch, |_, _, line| {
+                if matches!(line.origin(), '+' | '-' | ' ') {
+                    text.push(line.origin());
+                }
+                text.push_str(&String::from_utf8_lossy(line.content()));
+                true
+            })
+            .map_err(|e| git_error("Diffing commits failed", e))?;
+
+            Ok((files, text))
+        })
+        .await
+    }
+
+    async fn reset_hard(&self, commit: &str) -> Result<String> {
+        let commit = commit.to_string();
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            reset_to(&repo, Self::resolve(&repo, &commit)?)
+        })
+        .await
+    }
+
+    async fn working_tree_status(&self) -> Result<WorkingTreeStatus> {
+        self.blocking(|backend| {
+            let repo = backend.open()?;
+            let mut status = WorkingTreeStatus::default();
+            for (path, flags) in statuses(&repo)? {
+                if flags == Status::WT_NEW {
+                    status.untracked.push(path);
+                } else {
+                    status.modified.push(path);
+                }
+            }
+            Ok(status)
+        })
+        .await
+    }
+
+    async fn ahead_behind(&self, local: &str, upstream: &str) -> Result<(usize, usize)> {
+        let (local, upstream) = (local.to_string(), upstream.to_string());
+        self.blocking(move |backend| {
+            let repo = backend.open()?;
+            repo.graph_ahead_behind(Self::resolve(&repo, &local)?, Self::resolve(&repo, &upstream)?)
+                .map_err(|e| git_error("Counting commits failed", e))
+        })
+        .await
+    }
+
+    async fn stash(&self, message: &str) -> Result<String> {
+        let message = message.to_string();
+        self.blocking(move |backend| {
+            let mut repo = backend.open()?;
+            let signature = signature(&repo)?;
+            match repo.stash_save(&signature, &message, Some(StashFlags::INCLUDE_UNTRACKED)) {
+                Ok(oid) => Ok(format!("Saved working directory and index state {} ({})\n", message, short(oid))),
+                Err(e) if e.code() == ErrorCode::NotFound => Ok("No local changes to save\n".to_string()),
+                Err(e) => Err(git_error("Git stash failed", e)),
+            }
+        })
+        .await
+    }
+
+    async fn stash_pop(&self) -> Result<String> {
+        self.blocking(|backend| {
+            let mut repo = backend.open()?;
+            repo.stash_pop(0, None)
+                .map_err(|e| git_error("Git stash pop failed", e))?;
+            Ok("Restored the most recent stash\n".to_string())
+        })
+        .await
+    }
+
+    async fn stash_list(&self) -> Result<Vec<String>> {
+        self.blocking(|backend| {
+            let mut repo = backend.open()?;
+            let mut stashes = Vec::new();
+            repo.stash_foreach(|_, message, _| {
+                stashes.push(message.to_string());
+                true
+            })
+            .map_err(|e| git_error("Listing stashes failed", e))?;
+            Ok(stashes)
+        })
+        .await
+    }
+
+    async fn discard_changes(&self) -> Result<String> {
+        self.blocking(|backend| {
+            let repo = backend.open()?;
+            let mut output = reset_to(&repo, Self::resolve(&repo, "HEAD")?)?;
+
+            let workdir = repo
+                .workdir()
+                .ok_or_else(|| MonitorError::Git("Repository has no working directory".to_string()))?;
+            for (path, flags) in statuses(&repo)? {
+                if flags != Status::WT_NEW {
+                    continue;
+                }
+                let full_path = workdir.join(&path);
+                if full_path.is_dir() {
+                    std::fs::remove_dir_all(&full_path)?;
+                } else {
+                    std::fs::remove_file(&full_path)?;
+                }
+                output.push_str(&format!("Removing {}\n", path));
+            }
+            Ok(output)
+        })
+        .await
+    }
+
+    async fn update_submodules(&self) -> Result<String> {
+        self.blocking(|backend| {
+            let mut output = String::new();
+            backend.update_submodules_in(&backend.open()?, "", &mut output)?;
+            Ok(output)
+        })
+        .await
+    }
+
+    async fn submodule_status(&self) -> Result<Vec<SubmoduleStatus>> {
+        self.blocking(|backend| {
+            let repo = backend.open()?;
+            let submodules = repo
+                .submodules()
+                .map_err(|e| git_error("Reading submodule status failed", e))?;
+            Ok(submodules
+                .iter()
+                .filter_map(|submodule| {
+                    let recorded = submodule.head_id().or_else(|| submodule.index_id())?;
+                    Some(SubmoduleStatus {
+                        path: submodule.path().to_string_lossy().to_string(),
+                        recorded_commit: recorded.to_string(),
+                        checked_out_commit: submodule.workdir_id().map(|oid| oid.to_string()),
+                    })
+                })
+                .collect())
+        })
+        .await
+    }
+
+    /// libgit2 has no LFS support, so this runs the git-lfs executable
+    async fn lfs_pull(&self, remote: &str) -> Result<String> {
+        self.cli().lfs_pull(remote).await
+    }
+
+    /// libgit2 can extract signatures but not verify them, so this runs git
+    async fn commit_signature(&self, commit: &str, policy: &SignaturePolicy) -> Result<CommitSignature> {
+        self.cli().commit_signature(commit, policy).await
+    }
+
+    async fn has_remote(&self, remote: &str) -> Result<bool> {
+        let remote = remote.to_string();
+        self.blocking(move |backend| Ok(backend.open()?.find_remote(&remote).is_ok()))
+            .await
+    }
+
+    async fn clone_repository(&self, url: &str, remote: &str, branch: &str, depth: Option<u32>) -> Result<String> {
+        let (url, remote, branch) = (url.to_string(), remote.to_string(), branch.to_string());
+        self.blocking(move |backend| {
+            let deadline = Instant::now() + backend.timeout;
+            // There is no repository yet, so credential helpers come from the user's and system config
+            let config = git2::Config::open_default().map_err(|e| git_error("Failed to read git config", e))?;
+            let mut options = backend.fetch_options(config, deadline)?;
+            if let Some(depth) = depth {
+                options.depth(i32::try_from(depth).unwrap_or(i32::MAX));
+            }
+
+            RepoBuilder::new()
+                .branch(&branch)
+                .fetch_options(options)
+                .remote_create(move |repo, _, url| repo.remote(&remote, url))
+                .clone(&url, Path::new(&backend.repo_path))
+                .map_err(|e| {
+                    if Instant::now() >= deadline {
+                        MonitorError::Git(format!("Git clone timed out after {}s", backend.timeout.as_secs()))
+                    } else {
+                        git_error("Git clone failed", e)
+                    }
+                })?;
+            Ok(format!("Cloned {} into {}\n", url, backend.repo_path))
+        })
+        .await
+    }
+}
+
+/// Configuration of `repo`, for credential helpers
+fn config(repo: &Repository) -> Result<git2::Config> {
+    repo.config().map_err(|e| git_error("Failed to read git config", e))
+}
+
+/// Whether `known_hosts` lists `key` (raw SSH wire format) for `host`.
+/// Hashed host names, wildcards and markers such as `@cert-authority` are not supported.
+fn is_known_host(known_hosts: &str, host: &str, key: &[u8]) -> bool {
+    known_hosts
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let hosts = fields.next().filter(|hosts| !hosts.starts_with(['#', '@']))?;
+            let _key_type = fields.next()?;
+            Some((hosts, fields.next()?))
+        })
+        .any(|(hosts, encoded)| {
+            hosts.split(',').any(|pattern| {
+                // "[host]:port" entries name a host on a non-standard port
+                let name = pattern
+                    .strip_prefix('[')
+                    .and_then(|rest| rest.split_once("]:"))
+                    .map_or(pattern, |(name, _)| name);
+                name == host
+            }) && BASE64.decode(encoded).is_ok_and(|decoded| decoded == key)
+        })
+}
+
+/// Hard-resets the working tree and current branch to `oid`
+fn reset_to(repo: &Repository, oid: Oid) -> Result<String> {
+    let object = repo.find_object(oid, None).map_err(|e| git_error("Git reset failed", e))?;
+    repo.reset(&object, ResetType::Hard, None)
+        .map_err(|e| git_error("Git reset failed", e))?;
+
+    let summary = object
+        .as_commit()
+        .and_then(|commit| commit.summary())
+        .unwrap_or_default()
+        .to_string();
+    Ok(format!("HEAD is now at {} {}\n", short(oid), summary))
+}
+
+/// Changed and untracked (not ignored) paths with their status flags.
+/// Untracked directories are reported once, with a trailing '/'.
+fn statuses(repo: &Repository) -> Result<Vec<(String, Status)>> {
+    let mut options = StatusOptions::new();
+    options
+        .include_untracked(true)
+        .recurse_untracked_dirs(false)
+        .include_ignored(false);
+
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .map_err(|e| git_error("Reading working tree status failed", e))?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| !entry.status().is_ignored() && entry.status() != Status::CURRENT)
+        .filter_map(|entry| Some((entry.path()?.to_string(), entry.status())))
+        .collect())
+}
+
+/// Committer identity from the git config, or a fixed one when none is configured
+fn signature(repo: &Repository) -> Result<Signature<'static>> {
+    repo.signature()
+        .or_else(|_| Signature::now("ghd-manager", "ghd-manager@localhost"))
+        .map_err(|e| git_error("Failed to create commit signature", e))
+}
+
+fn conflict_error(action: &str, index: &Index) -> MonitorError {
+    let mut paths: Vec<String> = index
+        .conflicts()
+        .map(|conflicts| {
+            conflicts
+                .flatten()
+                .filter_map(|conflict| conflict.our.or(conflict.their).or(conflict.ancestor))
+                .map(|entry| String::from_utf8_lossy(&entry.path).to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    paths.dedup();
+
+    MonitorError::Pull {
+        kind: PullFailure::Conflict,
+        message: format!("{} stopped on conflicts in {}", action, paths.join(", ")),
+    }
+}
+
+fn short(oid: Oid) -> String {
+    oid.to_string().chars().take(7).collect()
+}
+
+fn git_error(context: &str, e: git2::Error) -> MonitorError {
+    MonitorError::Git(format!("{}: {}", context, e.message()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_known_host() {
+        let key = b"\x00\x00\x00\x0bssh-ed25519key";
+        let encoded = BASE64.encode(key);
+        let known_hosts = format!(
+            "# comment\n\
+             github.com,140.82.112.3 ssh-ed25519 {encoded}\n\
+             [git.example.com]:2222 ssh-ed25519 {encoded}\n\
+             @revoked other.example.com ssh-ed25519 {encoded}\n"
+        );
+        assert!(is_known_host(&known_hosts, "github.com", key));
+        assert!(is_known_host(&known_hosts, "140.82.112.3", key));
+        assert!(is_known_host(&known_hosts, "git.example.com", key));
+        assert!(!is_known_host(&known_hosts, "github.com", b"other key"));
+        assert!(!is_known_host(&known_hosts, "other.example.com", key));
+        assert!(!is_known_host(&known_hosts, "gitlab.com", key));
+    }
+}
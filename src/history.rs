@@ -0,0 +1,374 @@
+use crate::error::{MonitorError, Result};
+use chrono::{DateTime, Utc};
+use serde::{de, Deserialize, Deserializer, Serialize};
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs::OpenOptions;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tokio::sync::{Mutex, RwLock};
+use tracing::{debug, error, info, warn};
+
+/// Name of the history file inside the data directory
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// The history file is moved to `history.jsonl.1` once it grows past this size
+const MAX_FILE_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Entries kept in memory for queries; older ones remain only in the files
+const MAX_ENTRIES: usize = 10_000;
+
+/// Output beyond this many bytes is cut from the front, keeping the end where errors usually are
+const MAX_OUTPUT_BYTES: usize = 64 * 1024;
+
+/// Largest page size accepted by [`HistoryStore::query`]
+const MAX_PAGE_SIZE: usize = 500;
+
+/// A single recorded operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    /// Who triggered the operation (e.g. "dashboard (127.0.0.1)")
+    pub actor: String,
+    /// Project the operation applied to
+    #[serde(default)]
+    pub project: Option<String>,
+    /// What was done (e.g. "git.pull", "docker.restart")
+    pub action: String,
+    /// Container, deploy or other object the operation applied to
+    pub target: Option<String>,
+    /// Commit checked out when the operation finished
+    pub commit: Option<String>,
+    pub success: bool,
+    pub message: Option<String>,
+    pub output: Option<String>,
+}
+
+/// A new entry to record, before it is assigned an ID and timestamp
+#[derive(Debug, Clone, Default)]
+pub struct NewHistoryEntry {
+    pub actor: String,
+    pub project: Option<String>,
+    pub action: String,
+    pub target: Option<String>,
+    pub commit: Option<String>,
+    pub success: bool,
+    pub message: Option<String>,
+    pub output: Option<String>,
+}
+
+/// Filters and pagination for history queries
+#[derive(Debug, Clone, Deserialize)]
+pub struct HistoryQuery {
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub project: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub action: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub target: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub actor: Option<String>,
+    #[serde(default, deserialize_with = "empty_as_none")]
+    pub success: Option<bool>,
+    #[serde(default = "default_page_size")]
+    pub limit: usize,
+    #[serde(default)]
+    pub offset: usize,
+}
+
+fn default_page_size() -> usize {
+    50
+}
+
+/// Treats empty query parameters (as submitted by an HTML form) as absent
+fn empty_as_none<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    match Option::<String>::deserialize(deserializer)?.as_deref().map(str::trim) {
+        None | Some("") => Ok(None),
+        Some(value) => value.parse().map(Some).map_err(de::Error::custom),
+    }
+}
+
+impl Default for HistoryQuery {
+    fn default() -> Self {
+        Self {
+            project: None,
+            action: None,
+            target: None,
+            actor: None,
+            success: None,
+            limit: default_page_size(),
+            offset: 0,
+        }
+    }
+}
+
+impl HistoryQuery {
+    fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.project.as_ref().is_none_or(|project| entry.project.as_ref() == Some(project))
+            && self.action.as_ref().is_none_or(|action| entry.action.starts_with(action.as_str()))
+            && self.target.as_ref().is_none_or(|target| entry.target.as_ref() == Some(target))
+            && self.actor.as_ref().is_none_or(|actor| entry.actor.contains(actor.as_str()))
+            && self.success.is_none_or(|success| entry.success == success)
+    }
+}
+
+/// One page of history entries, newest first
+#[derive(Debug, Clone, Serialize)]
+pub struct HistoryPage {
+    pub total: usize,
+    pub offset: usize,
+    pub limit: usize,
+    pub entries: Vec<HistoryEntry>,
+}
+
+/// Append-only operation history stored as JSON lines, rotated once it gets large
+pub struct HistoryStore {
+    path: PathBuf,
+    entries: RwLock<VecDeque<HistoryEntry>>,
+    /// Keeps appends in ID order without holding `entries` during file I/O
+    writer: Mutex<()>,
+}
+
+impl HistoryStore {
+    /// Opens the history file in `data_dir`, creating the directory if needed
+    pub fn open(data_dir: impl AsRef<Path>) -> Result<Self> {
+        let data_dir = data_dir.as_ref();
+        std::fs::create_dir_all(data_dir).map_err(|e| {
+            MonitorError::Config(format!(
+                "Failed to create data directory {}: {}",
+                data_dir.display(),
+                e
+            ))
+        })?;
+
+        let path = data_dir.join(HISTORY_FILE);
+        let mut entries = VecDeque::new();
+
+        for file_path in [rotated_path(&path), path.clone()] {
+            if !file_path.exists() {
+                continue;
+            }
+            let file = std::fs::File::open(&file_path)?;
+            for (index, line) in BufReader::new(file).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match serde_json::from_str::<HistoryEntry>(&line) {
+                    Ok(entry) => entries.push_back(entry),
+                    Err(e) => warn!("Skipping malformed line {} of {}: {}", index + 1, file_path.display(), e),
+                }
+                if entries.len() > MAX_ENTRIES {
+                    entries.pop_front();
+                }
+            }
+        }
+
+        info!("Loaded {} history entries from {}", entries.len(), path.display());
+
+        Ok(Self {
+            path,
+            entries: RwLock::new(entries),
+            writer: Mutex::new(()),
+        })
+    }
+
+    /// Records an entry. Failures are logged rather than returned so that a
+    /// broken history file never fails the operation being recorded.
+    pub async fn record(&self, entry: NewHistoryEntry) {
+        let mut entries = self.entries.write().await;
+
+        let entry = HistoryEntry {
+            id: entries.back().map_or(1, |last| last.id + 1),
+            timestamp: Utc::now(),
+            actor: entry.actor,
+            project: entry.project,
+            action: entry.action,
+            target: entry.target,
+            commit: entry.commit,
+            success: entry.success,
+            message: entry.message,
+            output: entry.output.map(truncate_output),
+        };
+
+        debug!("Recording history entry {}: {}", entry.id, entry.action);
+
+        entries.push_back(entry.clone());
+        if entries.len() > MAX_ENTRIES {
+            entries.pop_front();
+        }
+        // Taken before `entries` is released, so that entries are appended in ID order
+        let _writer = self.writer.lock().await;
+        drop(entries);
+
+        let path = self.path.clone();
+        let written = tokio::task::spawn_blocking(move || append(&path, &entry))
+            .await
+            .unwrap_or_else(|e| Err(MonitorError::Io(std::io::Error::other(e))));
+        if let Err(e) = written {
+            error!("Failed to write history entry to {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Returns the entries matching `query`, newest first
+    pub async fn query(&self, query: &HistoryQuery) -> HistoryPage {
+        let entries = self.entries.read().await;
+        let limit = query.limit.clamp(1, MAX_PAGE_SIZE);
+
+        let matching: Vec<&HistoryEntry> = entries.iter().rev().filter(|e| query.matches(e)).collect();
+
+        HistoryPage {
+            total: matching.len(),
+            offset: query.offset,
+            limit,
+            entries: matching
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+/// Appends an entry to the history file, first rotating it if it has grown too large
+fn append(path: &Path, entry: &HistoryEntry) -> Result<()> {
+    if std::fs::metadata(path).is_ok_and(|metadata| metadata.len() >= MAX_FILE_BYTES) {
+        info!("Rotating {}", path.display());
+        std::fs::rename(path, rotated_path(path))?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let mut line = serde_json::to_string(entry)?;
+    line.push('\n');
+    file.write_all(line.as_bytes())?;
+    Ok(())
+}
+
+/// Where the previous history file is kept after a rotation
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+/// Keeps the last `MAX_OUTPUT_BYTES` of an operation's output
+fn truncate_output(output: String) -> String {
+    if output.len() <= MAX_OUTPUT_BYTES {
+        return output;
+    }
+    let mut start = output.len() - MAX_OUTPUT_BYTES;
+    while !output.is_char_boundary(start) {
+        start += 1;
+    }
+    format!("[{} bytes truncated]\n{}", start, &output[start..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_and_query() {
+        let dir = std::env::temp_dir().join(format!("ghd-history-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = HistoryStore::open(&dir).expect("Failed to open history store");
+        for (project, action, target, success) in [
+            ("shop", "git.pull", None, true),
+            ("shop", "docker.restart", Some("web"), true),
+            ("blog", "docker.restart", Some("db"), false),
+        ] {
+            store
+                .record(NewHistoryEntry {
+                    actor: "test".to_string(),
+                    project: Some(project.to_string()),
+                    action: action.to_string(),
+                    target: target.map(str::to_string),
+                    success,
+                    ..Default::default()
+                })
+                .await;
+        }
+
+        let page = store
+            .query(&HistoryQuery {
+                action: Some("docker".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(page.total, 2);
+        // Newest first
+        assert_eq!(page.entries[0].target.as_deref(), Some("db"));
+
+        let page = store
+            .query(&HistoryQuery {
+                success: Some(true),
+                limit: 1,
+                offset: 1,
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].action, "git.pull");
+
+        let page = store
+            .query(&HistoryQuery {
+                project: Some("blog".to_string()),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].target.as_deref(), Some("db"));
+
+        // Entries survive a reload
+        let reloaded = HistoryStore::open(&dir).expect("Failed to reopen history store");
+        assert_eq!(reloaded.query(&HistoryQuery::default()).await.total, 3);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_output_truncation_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("ghd-history-rotation-test-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let store = HistoryStore::open(&dir).expect("Failed to open history store");
+        store
+            .record(NewHistoryEntry {
+                actor: "test".to_string(),
+                action: "git.pull".to_string(),
+                output: Some(format!("{}error: tail", "x".repeat(MAX_OUTPUT_BYTES))),
+                ..Default::default()
+            })
+            .await;
+        let output = store.query(&HistoryQuery::default()).await.entries[0].output.clone().unwrap();
+        assert!(output.starts_with("[11 bytes truncated]\n"), "{}", &output[..40]);
+        assert!(output.ends_with("error: tail"));
+
+        // A full file is moved aside before the next append, and both are read back
+        let path = dir.join(HISTORY_FILE);
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().set_len(MAX_FILE_BYTES).unwrap();
+        store
+            .record(NewHistoryEntry {
+                actor: "test".to_string(),
+                action: "docker.restart".to_string(),
+                ..Default::default()
+            })
+            .await;
+        assert_eq!(std::fs::metadata(rotated_path(&path)).unwrap().len(), MAX_FILE_BYTES);
+        let reloaded = HistoryStore::open(&dir).expect("Failed to reopen history store");
+        let page = reloaded.query(&HistoryQuery::default()).await;
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries[0].id, 2);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,206 @@
+use crate::error::{MonitorError, Result};
+use std::fmt;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{info, warn};
+
+/// Point of a deploy at which a hook runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookPoint {
+    /// Before the repository is pulled
+    PrePull,
+    /// After a successful pull, before any container is touched
+    PostPull,
+    /// Before `docker compose up`
+    PreUp,
+    /// After the containers were updated
+    PostUp,
+}
+
+impl HookPoint {
+    /// Every hook point, in the order a deploy reaches them
+    pub const ALL: [HookPoint; 4] = [HookPoint::PrePull, HookPoint::PostPull, HookPoint::PreUp, HookPoint::PostUp];
+
+    /// Name of the deploy step the hook runs as
+    pub fn step_name(self) -> &'static str {
+        match self {
+            HookPoint::PrePull => "pre-pull hook",
+            HookPoint::PostPull => "post-pull hook",
+            HookPoint::PreUp => "pre-up hook",
+            HookPoint::PostUp => "post-up hook",
+        }
+    }
+}
+
+impl fmt::Display for HookPoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HookPoint::PrePull => write!(f, "pre-pull"),
+            HookPoint::PostPull => write!(f, "post-pull"),
+            HookPoint::PreUp => write!(f, "pre-up"),
+            HookPoint::PostUp => write!(f, "post-up"),
+        }
+    }
+}
+
+/// Result of running a hook
+#[derive(Debug, Clone, PartialEq)]
+pub struct HookOutput {
+    /// stdout followed by stderr
+    pub output: String,
+    /// Why the hook failed, `None` when it exited successfully
+    pub error: Option<String>,
+}
+
+/// Shell commands run at fixed points of a deploy, from the repository directory
+///
+/// A hook runs in its own process group, which is killed, with everything the hook
+/// started, if it outlives the timeout or its future is dropped.
+#[derive(Debug, Clone)]
+pub struct Hooks {
+    commands: Vec<(HookPoint, String)>,
+    working_dir: String,
+    timeout: Duration,
+}
+
+impl Hooks {
+    pub fn new(working_dir: impl Into<String>, timeout: Duration) -> Self {
+        Self {
+            commands: Vec::new(),
+            working_dir: working_dir.into(),
+            timeout,
+        }
+    }
+
+    /// Runs `command` at `point`; `None` leaves the point without a hook
+    pub fn with(mut self, point: HookPoint, command: Option<String>) -> Self {
+        self.commands.retain(|(existing, _)| *existing != point);
+        if let Some(command) = command {
+            self.commands.push((point, command));
+        }
+        self
+    }
+
+    /// The command configured for `point`
+    pub fn command(&self, point: HookPoint) -> Option<&str> {
+        self.commands
+            .iter()
+            .find(|(existing, _)| *existing == point)
+            .map(|(_, command)| command.as_str())
+    }
+
+    /// Runs the hook for `point` with `sh -c`, adding `env` to its environment
+    pub async fn run(&self, point: HookPoint, env: &[(&str, String)]) -> Result<HookOutput> {
+        let command = self
+            .command(point)
+            .ok_or_else(|| MonitorError::Hook(format!("no {} hook configured", point)))?;
+        info!("Running {} hook: {}", point, command);
+
+        let child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .current_dir(&self.working_dir)
+            .env("GHD_HOOK", point.to_string())
+            .envs(env.iter().map(|(name, value)| (name, value)))
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|e| MonitorError::Hook(format!("failed to run {} hook: {}", point, e)))?;
+        let group = ProcessGroup(child.id());
+
+        let output = match tokio::time::timeout(self.timeout, child.wait_with_output()).await {
+            Ok(output) => {
+                // Whatever the hook left running in the background is its own business
+                group.disarm();
+                output.map_err(|e| MonitorError::Hook(format!("failed to run {} hook: {}", point, e)))?
+            }
+            Err(_) => {
+                warn!("{} hook timed out after {:?}, killed", point, self.timeout);
+                return Err(MonitorError::Hook(format!(
+                    "{} hook timed out after {}s",
+                    point,
+                    self.timeout.as_secs()
+                )));
+            }
+        };
+
+        let mut text = String::from_utf8_lossy(&output.stdout).to_string();
+        text.push_str(&String::from_utf8_lossy(&output.stderr));
+        let error = (!output.status.success()).then(|| {
+            warn!("{} hook failed: {}", point, output.status);
+            format!("{} hook failed: {}", point, output.status)
+        });
+        Ok(HookOutput { output: text, error })
+    }
+}
+
+/// Kills a process group when dropped, unless disarmed
+struct ProcessGroup(Option<u32>);
+
+impl ProcessGroup {
+    fn disarm(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        if let Some(pgid) = self.0 {
+            // SAFETY: kill(2) has no memory safety requirements
+            unsafe {
+                libc::kill(-(pgid as libc::pid_t), libc::SIGKILL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_run_hooks() {
+        let dir = std::env::temp_dir().join(format!("ghd-hooks-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("marker"), "").unwrap();
+
+        let hooks = Hooks::new(dir.to_string_lossy(), Duration::from_secs(5))
+            .with(HookPoint::PrePull, Some("ls; echo \"$GHD_HOOK $GHD_COMMIT\"; echo warn >&2".to_string()))
+            .with(HookPoint::PostPull, Some("echo migrating; exit 3".to_string()))
+            .with(HookPoint::PreUp, Some("sleep 10".to_string()));
+
+        let output = hooks
+            .run(HookPoint::PrePull, &[("GHD_COMMIT", "abc123".to_string())])
+            .await
+            .unwrap();
+        assert_eq!(output.output, "marker\npre-pull abc123\nwarn\n");
+        assert_eq!(output.error, None);
+
+        let output = hooks.run(HookPoint::PostPull, &[]).await.unwrap();
+        assert_eq!(output.output, "migrating\n");
+        assert!(output.error.unwrap().contains("exit status: 3"));
+
+        let hooks = Hooks {
+            timeout: Duration::from_millis(100),
+            ..hooks
+        };
+        assert!(matches!(hooks.run(HookPoint::PreUp, &[]).await, Err(MonitorError::Hook(_))));
+
+        // A timeout kills what the hook started too, not just the shell
+        let hooks = hooks.with(HookPoint::PreUp, Some("sleep 30 & echo $! > child; wait".to_string()));
+        assert!(hooks.run(HookPoint::PreUp, &[]).await.is_err());
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let child = std::fs::read_to_string(dir.join("child")).unwrap();
+        let stat = std::fs::read_to_string(format!("/proc/{}/stat", child.trim())).unwrap_or_default();
+        // Gone, or a zombie waiting for init to reap it
+        assert!(stat.is_empty() || stat.contains(") Z "), "{}", stat);
+        assert!(hooks.run(HookPoint::PostUp, &[]).await.is_err());
+        assert_eq!(hooks.command(HookPoint::PostUp), None);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
@@ -1,12 +1,25 @@
 mod auth;
+mod compose;
 mod config;
+mod deploy;
 mod docker;
+mod docker_host;
 mod error;
 mod git;
+mod history;
+mod hooks;
+mod operations;
+mod poller;
+mod project;
 mod routes;
+mod stats;
+mod supervisor;
+mod webhook;
 
 use anyhow::Result;
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 use tower::ServiceBuilder;
 use tower_http::trace::TraceLayer;
 use tower_sessions::{Expiry, SessionManagerLayer};
@@ -14,9 +27,13 @@ use tower_sessions_memory_store::MemoryStore;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 use config::Config;
-use docker::DockerManager;
-use git::GitManager;
+use history::{HistoryQuery, HistoryStore};
+use operations::OperationRegistry;
+use poller::Poller;
+use project::{Project, ProjectRegistry};
 use routes::{create_router, AppState};
+use stats::StatsSampler;
+use supervisor::Supervisor;
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -35,30 +52,89 @@ async fn main() -> Result<()> {
     let config = Config::load()?;
     tracing::info!("Configuration loaded successfully");
     tracing::info!("Server will listen on {}:{}", config.server.host, config.server.port);
-    tracing::info!("Git repository: {}", config.git.repo_path);
-    tracing::info!("Docker compose file: {}", config.docker.compose_file);
-    tracing::info!("Managing {} container(s)", config.docker.containers.len());
+    tracing::info!("Managing {} project(s)", config.projects.len());
 
     // Hash the password for authentication
     let password_hash = auth::hash_password(&config.auth.password)?;
     tracing::info!("Password hash generated");
 
-    // Initialize Git Manager
-    let git_manager = GitManager::new(
-        config.git.repo_path.clone(),
-        config.git.remote.clone(),
-        config.git.branch.clone(),
-    );
-    git_manager.validate_repository()?;
-    tracing::info!("Git manager initialized and validated");
+    // Open operation history
+    let history = Arc::new(HistoryStore::open(&config.storage.data_dir)?);
+    tracing::info!("Operation history stored in {}", config.storage.data_dir);
+
+    // Initialize the Git and Docker managers of every project
+    let mut projects = Vec::new();
+    for project_config in &config.projects {
+        let project = Project::open(project_config.clone()).await?;
+        tracing::info!("[{}] Project initialized and validated", project.name());
+
+        // Remember recent webhook deliveries so that redeliveries after a restart are ignored;
+        // failed ones are left out so GitHub can still redeliver them
+        let recent_webhooks = history
+            .query(&HistoryQuery {
+                project: Some(project.name().to_string()),
+                action: Some("webhook".to_string()),
+                success: Some(true),
+                limit: 500,
+                ..Default::default()
+            })
+            .await;
+        for entry in recent_webhooks.entries.iter().rev() {
+            if let Some(delivery_id) = &entry.target {
+                project.webhook_deliveries.insert(delivery_id);
+            }
+        }
+        if project.config.git.webhook_secret.is_some() {
+            tracing::info!(
+                "[{}] GitHub webhook enabled at /api/projects/{}/webhooks/github",
+                project.name(),
+                project.name()
+            );
+        }
+
+        // Start the background poller
+        if project.config.git.poll_interval > 0 {
+            tracing::info!("[{}] Background fetch every {}s", project.name(), project.config.git.poll_interval);
+            Poller {
+                project: project.name().to_string(),
+                git: project.git.clone(),
+                deploys: project.deploys.clone(),
+                history: history.clone(),
+                cache: project.git_status.clone(),
+                data_dir: project.config.data_dir.clone().into(),
+                interval: Duration::from_secs(project.config.git.poll_interval),
+                auto_deploy: project.config.git.auto_deploy,
+            }
+            .spawn();
+        }
+
+        // Start sampling resource usage for the dashboard
+        if project.config.docker.stats_interval > 0 {
+            StatsSampler {
+                project: project.name().to_string(),
+                docker: project.docker.clone(),
+                history: project.stats.clone(),
+                interval: Duration::from_secs(project.config.docker.stats_interval),
+            }
+            .spawn();
+        }
+
+        // Start the restart supervisor
+        if project.config.docker.restart_unhealthy {
+            Supervisor {
+                project: project.name().to_string(),
+                docker: project.docker.clone(),
+                deploys: project.deploys.clone(),
+                history: history.clone(),
+                interval: Duration::from_secs(project.config.docker.health_check_interval),
+                threshold: Duration::from_secs(project.config.docker.unhealthy_threshold),
+                max_restarts: project.config.docker.max_restarts,
+            }
+            .spawn();
+        }
 
-    // Initialize Docker Manager
-    let docker_manager = DockerManager::new(
-        config.docker.compose_file.clone(),
-        config.docker.containers.clone(),
-    )?;
-    docker_manager.validate().await?;
-    tracing::info!("Docker manager initialized and validated");
+        projects.push(project);
+    }
 
     // Create session store
     let session_store = MemoryStore::default();
@@ -72,9 +148,9 @@ async fn main() -> Result<()> {
 
     // Create application state
     let state = AppState {
-        config: Arc::new(config.clone()),
-        git: Arc::new(git_manager),
-        docker: Arc::new(docker_manager),
+        projects: Arc::new(ProjectRegistry::new(projects)),
+        operations: Arc::new(OperationRegistry::default()),
+        history,
         password_hash: Arc::new(password_hash),
     };
 
@@ -93,7 +169,7 @@ async fn main() -> Result<()> {
     tracing::info!("GitHub + Docker Manager is ready!");
     tracing::info!("Visit http://{} to access the dashboard", addr);
 
-    axum::serve(listener, app)
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
         .await?;
 
     Ok(())
@@ -0,0 +1,224 @@
+use crate::docker::UpdateResult;
+use chrono::{DateTime, Utc};
+use futures_util::stream::{self, Stream};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// Number of operations kept around for late subscribers
+const MAX_OPERATIONS: usize = 20;
+
+/// Capacity of the live event channel of an operation
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// An event emitted by a running operation
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OperationEvent {
+    /// One line of subprocess output
+    Line { line: String },
+    /// The operation finished
+    Done { result: UpdateResult },
+}
+
+struct OperationState {
+    lines: Vec<String>,
+    result: Option<UpdateResult>,
+    // Dropped once the operation finishes so that subscribers see the end of the stream
+    sender: Option<broadcast::Sender<OperationEvent>>,
+}
+
+/// A long-running operation whose output can be followed while it runs
+pub struct Operation {
+    pub id: String,
+    pub description: String,
+    pub started_at: DateTime<Utc>,
+    state: Mutex<OperationState>,
+}
+
+impl Operation {
+    /// Records a line of output and forwards it to live subscribers
+    pub fn push_line(&self, line: &str) {
+        let mut state = self.state.lock().unwrap();
+        state.lines.push(line.to_string());
+        if let Some(sender) = &state.sender {
+            // No receivers is fine, the line is kept for later subscribers
+            let _ = sender.send(OperationEvent::Line { line: line.to_string() });
+        }
+    }
+
+    /// Marks the operation as finished and closes the live stream
+    pub fn finish(&self, result: UpdateResult) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(sender) = state.sender.take() {
+            let _ = sender.send(OperationEvent::Done { result: result.clone() });
+        }
+        state.result = Some(result);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.state.lock().unwrap().result.is_some()
+    }
+
+    /// Returns the events emitted so far together with a receiver for the
+    /// ones still to come (`None` once the operation has finished)
+    pub fn subscribe(&self) -> (Vec<OperationEvent>, Option<broadcast::Receiver<OperationEvent>>) {
+        self.subscribe_from(0)
+    }
+
+    /// Like `subscribe`, but skips the first `skip` lines
+    fn subscribe_from(&self, skip: usize) -> (Vec<OperationEvent>, Option<broadcast::Receiver<OperationEvent>>) {
+        let state = self.state.lock().unwrap();
+
+        let mut events: Vec<OperationEvent> = state
+            .lines
+            .iter()
+            .skip(skip)
+            .map(|line| OperationEvent::Line { line: line.clone() })
+            .collect();
+        if let Some(result) = &state.result {
+            events.push(OperationEvent::Done { result: result.clone() });
+        }
+
+        (events, state.sender.as_ref().map(|sender| sender.subscribe()))
+    }
+
+    /// Streams every event of the operation, from the first line to the end.
+    /// A subscriber that falls behind the live channel is caught up from the
+    /// recorded lines, so no output is lost.
+    pub fn follow(self: &Arc<Self>) -> impl Stream<Item = OperationEvent> + Send + 'static {
+        let (history, receiver) = self.subscribe();
+        let state = (self.clone(), VecDeque::from(history), receiver, 0usize);
+
+        stream::unfold(state, |(operation, mut pending, mut receiver, mut lines_sent)| async move {
+            loop {
+                if let Some(event) = pending.pop_front() {
+                    if matches!(event, OperationEvent::Line { .. }) {
+                        lines_sent += 1;
+                    }
+                    return Some((event, (operation, pending, receiver, lines_sent)));
+                }
+                match receiver.as_mut()?.recv().await {
+                    Ok(event) => pending.push_back(event),
+                    Err(RecvError::Closed) => return None,
+                    Err(RecvError::Lagged(_)) => {
+                        let (missed, live) = operation.subscribe_from(lines_sent);
+                        pending.extend(missed);
+                        receiver = live;
+                    }
+                }
+            }
+        })
+    }
+}
+
+/// Keeps track of recent operations by ID
+#[derive(Default)]
+pub struct OperationRegistry {
+    operations: Mutex<VecDeque<Arc<Operation>>>,
+    next_id: AtomicU64,
+}
+
+impl OperationRegistry {
+    /// Registers a new operation
+    pub fn create(&self, description: impl Into<String>) -> Arc<Operation> {
+        let (sender, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+        let operation = Arc::new(Operation {
+            id: format!("op-{}", self.next_id.fetch_add(1, Ordering::SeqCst) + 1),
+            description: description.into(),
+            started_at: Utc::now(),
+            state: Mutex::new(OperationState {
+                lines: Vec::new(),
+                result: None,
+                sender: Some(sender),
+            }),
+        });
+
+        let mut operations = self.operations.lock().unwrap();
+        operations.push_back(operation.clone());
+        // Forget the oldest finished operations once we hold too many
+        while operations.len() > MAX_OPERATIONS {
+            match operations.iter().position(|op| op.is_finished()) {
+                Some(index) => {
+                    operations.remove(index);
+                }
+                None => break,
+            }
+        }
+
+        operation
+    }
+
+    /// Looks up an operation by ID
+    pub fn get(&self, id: &str) -> Option<Arc<Operation>> {
+        self.operations
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|op| op.id == id)
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscribe_replays_history() {
+        let registry = OperationRegistry::default();
+        let op = registry.create("test");
+
+        op.push_line("first");
+        let (events, receiver) = op.subscribe();
+        assert_eq!(events.len(), 1);
+        let mut receiver = receiver.expect("operation should still be live");
+
+        op.push_line("second");
+        op.finish(UpdateResult {
+            success: true,
+            output: String::new(),
+            error: None,
+            rollbacks: Vec::new(),
+        });
+
+        assert!(matches!(receiver.try_recv(), Ok(OperationEvent::Line { line }) if line == "second"));
+        assert!(matches!(receiver.try_recv(), Ok(OperationEvent::Done { .. })));
+
+        // Late subscribers get the full history and no live receiver
+        let (events, receiver) = op.subscribe();
+        assert_eq!(events.len(), 3);
+        assert!(receiver.is_none());
+        assert!(registry.get(&op.id).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_follow_catches_up_after_lagging() {
+        use futures_util::StreamExt;
+
+        let registry = OperationRegistry::default();
+        let op = registry.create("test");
+        let stream = op.follow();
+
+        // More lines than the live channel holds before the subscriber reads any
+        for i in 0..EVENT_CHANNEL_CAPACITY * 2 {
+            op.push_line(&i.to_string());
+        }
+        op.finish(UpdateResult {
+            success: true,
+            output: String::new(),
+            error: None,
+            rollbacks: Vec::new(),
+        });
+
+        let events: Vec<OperationEvent> = stream.collect().await;
+        assert_eq!(events.len(), EVENT_CHANNEL_CAPACITY * 2 + 1);
+        for (i, event) in events[..EVENT_CHANNEL_CAPACITY * 2].iter().enumerate() {
+            assert!(matches!(event, OperationEvent::Line { line } if *line == i.to_string()));
+        }
+        assert!(matches!(events.last(), Some(OperationEvent::Done { .. })));
+    }
+}
@@ -0,0 +1,245 @@
+use crate::deploy::{DeployManager, DeployOptions, DeployStatus};
+use crate::error::MonitorError;
+use crate::git::{GitManager, GitStatus};
+use crate::history::{HistoryStore, NewHistoryEntry};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Upper bound for the delay between polls after repeated failures
+const MAX_BACKOFF: Duration = Duration::from_secs(3600);
+
+/// File in the project's data directory with the remote commit last auto-deployed
+const LAST_AUTO_DEPLOY_FILE: &str = "last_auto_deploy";
+
+/// Fraction of the interval added as random jitter so that instances do not poll in lockstep
+const JITTER_FRACTION: f64 = 0.1;
+
+/// Outcome of the most recent background poll
+#[derive(Debug, Clone, Serialize)]
+pub struct PollState {
+    /// Last successfully fetched status
+    pub status: Option<GitStatus>,
+    pub checked_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub consecutive_failures: u32,
+}
+
+impl PollState {
+    /// The polled status, unless the last poll failed or is older than `max_age`
+    pub fn fresh_status(&self, max_age: Duration) -> Option<&GitStatus> {
+        let age = (Utc::now() - self.checked_at).to_std().unwrap_or_default();
+        self.status.as_ref().filter(|_| self.last_error.is_none() && age <= max_age)
+    }
+}
+
+/// Latest poll result shared with the route handlers
+pub type StatusCache = Arc<RwLock<Option<PollState>>>;
+
+/// Periodically fetches from the remote and optionally deploys new commits
+pub struct Poller {
+    /// Name of the polled project, recorded with auto-deploys
+    pub project: String,
+    pub git: Arc<GitManager>,
+    pub deploys: Arc<DeployManager>,
+    pub history: Arc<HistoryStore>,
+    pub cache: StatusCache,
+    /// Where the last auto-deployed commit is kept across restarts
+    pub data_dir: PathBuf,
+    pub interval: Duration,
+    pub auto_deploy: bool,
+}
+
+impl Poller {
+    /// Starts polling on a background task
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    async fn run(self) {
+        info!(
+            "[{}] Polling {} every {:?} (auto-deploy: {})",
+            self.project,
+            self.git.repo_path(),
+            self.interval,
+            self.auto_deploy
+        );
+
+        let mut failures: u32 = 0;
+        // Remote commit we last tried to auto-deploy, so a failing deploy is not retried forever
+        let mut last_auto_deployed = self.load_last_auto_deployed();
+
+        loop {
+            tokio::time::sleep(next_delay(self.interval, failures, jitter())).await;
+
+            let result = match self.git.fetch().await {
+                Ok(()) => self.git.get_status().await,
+                Err(e) => Err(e),
+            };
+
+            match result {
+                Ok(status) => {
+                    failures = 0;
+                    debug!("[{}] Poll complete, updates available: {}", self.project, status.updates_available);
+
+                    let mut deploy = self.auto_deploy
+                        && status.updates_available
+                        && last_auto_deployed.as_ref() != Some(&status.remote_commit);
+                    if deploy && self.deploys.is_held(&status.remote_commit).await {
+                        debug!("[{}] Not deploying {}, held since a rollback", self.project, status.remote_commit);
+                        deploy = false;
+                    }
+
+                    let remote_commit = status.remote_commit.clone();
+                    self.store(Some(status), None, failures).await;
+
+                    // A commit is only marked once its deploy ran, so one skipped
+                    // while another deploy held the lock is retried next poll
+                    if deploy && self.deploy().await {
+                        self.save_last_auto_deployed(&remote_commit);
+                        last_auto_deployed = Some(remote_commit);
+                    }
+                }
+                Err(e) => {
+                    failures = failures.saturating_add(1);
+                    warn!("[{}] Background fetch failed ({} in a row): {}", self.project, failures, e);
+                    let status = self.cache.read().await.as_ref().and_then(|state| state.status.clone());
+                    self.store(status, Some(e.to_string()), failures).await;
+                }
+            }
+        }
+    }
+
+    fn load_last_auto_deployed(&self) -> Option<String> {
+        std::fs::read_to_string(self.data_dir.join(LAST_AUTO_DEPLOY_FILE))
+            .ok()
+            .map(|commit| commit.trim().to_string())
+            .filter(|commit| !commit.is_empty())
+    }
+
+    fn save_last_auto_deployed(&self, commit: &str) {
+        let path = self.data_dir.join(LAST_AUTO_DEPLOY_FILE);
+        if let Err(e) = std::fs::write(&path, commit) {
+            warn!("[{}] Failed to write {}: {}", self.project, path.display(), e);
+        }
+    }
+
+    async fn store(&self, status: Option<GitStatus>, last_error: Option<String>, consecutive_failures: u32) {
+        *self.cache.write().await = Some(PollState {
+            status,
+            checked_at: Utc::now(),
+            last_error,
+            consecutive_failures,
+        });
+    }
+
+    /// Returns false when the deploy did not start because another one, or a
+    /// rollback or update, held the lock
+    async fn deploy(&self) -> bool {
+        info!("[{}] New commits detected on the tracked branch, starting auto-deploy", self.project);
+
+        let (success, message, output) = match self.deploys.deploy(DeployOptions::default()).await {
+            Ok(deploy) => {
+                let success = deploy.status == DeployStatus::Succeeded;
+                let message = format!(
+                    "Deploy {} {}",
+                    deploy.id,
+                    if success { "succeeded" } else { "failed" }
+                );
+                (success, message, Some(deploy.output()))
+            }
+            Err(MonitorError::Busy(e)) => {
+                info!("[{}] Auto-deploy postponed: {}", self.project, e);
+                return false;
+            }
+            Err(e) => {
+                error!("[{}] Auto-deploy failed: {}", self.project, e);
+                (false, format!("Deploy failed: {}", e), None)
+            }
+        };
+
+        self.history
+            .record(NewHistoryEntry {
+                actor: "poller".to_string(),
+                project: Some(self.project.clone()),
+                action: "deploy".to_string(),
+                target: None,
+                commit: self.git.get_local_commit().await.ok(),
+                success,
+                message: Some(message),
+                output,
+            })
+            .await;
+        true
+    }
+}
+
+/// Delay before the next poll: the interval, doubled for each consecutive
+/// failure (capped), plus `jitter` (0.0..1.0) times a fraction of the interval
+pub fn next_delay(interval: Duration, failures: u32, jitter: f64) -> Duration {
+    let backoff = interval
+        .saturating_mul(2u32.saturating_pow(failures.min(16)))
+        .min(MAX_BACKOFF.max(interval));
+    backoff + interval.mul_f64(JITTER_FRACTION * jitter.clamp(0.0, 1.0))
+}
+
+/// Cheap pseudo-random value in 0.0..1.0; good enough to spread out polls
+fn jitter() -> f64 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.subsec_nanos())
+        .unwrap_or(0);
+    f64::from(nanos % 1000) / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_next_delay() {
+        let interval = Duration::from_secs(60);
+
+        assert_eq!(next_delay(interval, 0, 0.0), interval);
+        assert_eq!(next_delay(interval, 2, 0.0), Duration::from_secs(240));
+        assert_eq!(next_delay(interval, 0, 1.0), Duration::from_secs(66));
+
+        // Backoff is capped, but never below the interval itself
+        assert_eq!(next_delay(interval, 30, 0.0), MAX_BACKOFF);
+        let long = Duration::from_secs(7200);
+        assert_eq!(next_delay(long, 3, 0.0), long);
+    }
+
+    #[test]
+    fn test_fresh_status() {
+        let mut poll = PollState {
+            status: Some(GitStatus {
+                local_commit: "abc".to_string(),
+                remote_commit: "abc".to_string(),
+                updates_available: false,
+                current_branch: "main".to_string(),
+                target: crate::git::GitRef::Branch("main".to_string()),
+                ahead: 0,
+                behind: 0,
+                working_tree: Default::default(),
+                stashes: 0,
+                submodules: Vec::new(),
+                signature: None,
+            }),
+            checked_at: Utc::now() - chrono::Duration::seconds(30),
+            last_error: None,
+            consecutive_failures: 0,
+        };
+        assert!(poll.fresh_status(Duration::from_secs(60)).is_some());
+        assert!(poll.fresh_status(Duration::from_secs(10)).is_none());
+
+        // The status kept from before a failed poll is not served
+        poll.last_error = Some("fetch failed".to_string());
+        assert!(poll.fresh_status(Duration::from_secs(60)).is_none());
+    }
+}
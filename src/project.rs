@@ -0,0 +1,234 @@
+use crate::compose::ServiceFilter;
+use crate::config::ProjectConfig;
+use crate::deploy::DeployManager;
+use crate::docker::DockerManager;
+use crate::docker_host::DockerEndpoint;
+use crate::error::Result;
+use crate::git::{CheckoutResult, GitCredentials, GitManager, GitRef, GitStatus, SignaturePolicy, UpdateOptions, VerifyMode};
+use crate::hooks::{HookPoint, Hooks};
+use crate::poller::StatusCache;
+use crate::stats::StatsHistory;
+use crate::webhook::DeliveryLog;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// File in the project's data directory that records the deployed ref
+const DEPLOYED_REF_FILE: &str = "deployed_ref.json";
+
+/// A repository and its compose project, with the managers that operate on them
+pub struct Project {
+    pub config: ProjectConfig,
+    pub git: Arc<GitManager>,
+    pub docker: Arc<DockerManager>,
+    pub deploys: Arc<DeployManager>,
+    /// Latest result of the background poller (empty when polling is disabled)
+    pub git_status: StatusCache,
+    pub webhook_deliveries: DeliveryLog,
+    /// Recent resource usage of the containers (empty when sampling is disabled)
+    pub stats: Arc<StatsHistory>,
+}
+
+impl Project {
+    /// Creates the managers for a project and validates its repository and Docker setup
+    pub async fn open(config: ProjectConfig) -> Result<Self> {
+        info!("[{}] Git repository: {} ({} backend)", config.name, config.git.repo_path, config.git.backend);
+        info!("[{}] Pull strategy: {}", config.name, config.git.pull_strategy);
+        if config.git.verify_signatures != VerifyMode::Off {
+            info!("[{}] Verifying commit signatures ({})", config.name, config.git.verify_signatures);
+        }
+        info!("[{}] Docker compose file: {}", config.name, config.docker.compose_file);
+        if config.docker.auto_rollback {
+            info!(
+                "[{}] Automatic rollback enabled ({}s grace period)",
+                config.name, config.docker.rollback_grace_period
+            );
+        }
+
+        let git = GitManager::new(
+            config.git.repo_path.clone(),
+            config.git.remote.clone(),
+            config.git.branch.clone(),
+            config.git.backend,
+            config.git.pull_strategy,
+            UpdateOptions {
+                submodules: config.git.submodules,
+                lfs: config.git.lfs,
+            },
+            Duration::from_secs(config.git.timeout),
+        )
+        .with_credentials(GitCredentials {
+            ssh_key: config.git.ssh_key.clone(),
+            known_hosts: config.git.known_hosts.clone(),
+            token_file: config.git.token_file.clone(),
+        })
+        .with_signature_policy(SignaturePolicy {
+            mode: config.git.verify_signatures,
+            allowed_signers: config.git.allowed_signers.clone(),
+            gpg_home: config.git.gpg_home.clone(),
+        });
+        let cloned = match &config.git.url {
+            Some(url) if !git.is_cloned() => {
+                let depth = (config.git.clone_depth > 0).then_some(config.git.clone_depth);
+                info!("[{}] Cloning {} into {}", config.name, url, config.git.repo_path);
+                let clone = git.clone_repository(url, &config.git.branch, depth).await?;
+                if let Some(err) = clone.extras_error {
+                    warn!("[{}] Cloned, but updating submodules or LFS objects failed: {}", config.name, err);
+                }
+                true
+            }
+            _ => false,
+        };
+        git.validate_repository().await?;
+        if let Some(target) = load_deployed_ref(&config.data_dir) {
+            info!("[{}] Deployed from {} (checked out earlier)", config.name, target);
+            if cloned && target != git.target() {
+                // A fresh clone is at the configured branch, not the recorded ref
+                let checkout = git.checkout(target).await?;
+                if let Some(err) = checkout.extras_error {
+                    warn!("[{}] Checked out, but updating submodules or LFS objects failed: {}", config.name, err);
+                }
+            } else {
+                git.set_target(target);
+            }
+        }
+
+        let endpoint = DockerEndpoint::resolve(
+            &config.docker.socket,
+            config.docker.tls_cert_path().as_deref(),
+            config.docker.context.as_deref(),
+        )
+        .await?;
+        info!("[{}] Docker host: {}", config.name, endpoint.host);
+        let docker = DockerManager::new(
+            config.docker.compose_file.clone(),
+            ServiceFilter::new(config.docker.containers.clone(), config.docker.exclude_containers.clone()),
+            config
+                .docker
+                .auto_rollback
+                .then(|| Duration::from_secs(config.docker.rollback_grace_period)),
+            Duration::from_secs(config.docker.compose_timeout),
+            endpoint,
+        )
+        .await?
+        .with_protected(config.docker.protected_containers.clone())
+        .with_protected_updates(config.docker.update_protected);
+        docker.validate().await?;
+        info!("[{}] Managing service(s): {}", config.name, docker.service_names().join(", "));
+        if !config.docker.protected_containers.is_empty() {
+            info!(
+                "[{}] Protected from stop, restart{}: {}",
+                config.name,
+                if config.docker.update_protected { "" } else { " and update" },
+                config.docker.protected_containers.join(", ")
+            );
+        }
+
+        let git = Arc::new(git);
+        let docker = Arc::new(docker);
+        let hooks = Hooks::new(config.git.repo_path.clone(), Duration::from_secs(config.hooks.timeout))
+            .with(HookPoint::PrePull, config.hooks.pre_pull.clone())
+            .with(HookPoint::PostPull, config.hooks.post_pull.clone())
+            .with(HookPoint::PreUp, config.hooks.pre_up.clone())
+            .with(HookPoint::PostUp, config.hooks.post_up.clone());
+        for point in HookPoint::ALL {
+            if let Some(command) = hooks.command(point) {
+                info!("[{}] {} hook: {}", config.name, point, command);
+            }
+        }
+        let deploys = Arc::new(DeployManager::new(git.clone(), docker.clone(), hooks, &config.data_dir)?);
+
+        Ok(Self {
+            config,
+            git,
+            docker,
+            deploys,
+            git_status: StatusCache::default(),
+            webhook_deliveries: DeliveryLog::default(),
+            stats: Arc::default(),
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    /// Git status as of the last background poll while that is recent and HEAD has not
+    /// moved since; otherwise, or with polling disabled, it is read from the repository
+    pub async fn current_git_status(&self) -> Result<GitStatus> {
+        // A poll is due within the interval plus some jitter
+        let max_age = Duration::from_secs(self.config.git.poll_interval.saturating_mul(2));
+        let cached = self
+            .git_status
+            .read()
+            .await
+            .as_ref()
+            .and_then(|poll| poll.fresh_status(max_age).cloned());
+        if let Some(status) = cached {
+            if self.git.get_local_commit().await.is_ok_and(|head| head == status.local_commit) {
+                return Ok(status);
+            }
+        }
+        self.git.get_status().await
+    }
+
+    /// Runs an operation on the working tree while no deploy or rollback is running,
+    /// and stops serving the polled status it made stale
+    pub async fn change_working_tree<T>(&self, operation: impl Future<Output = Result<T>>) -> Result<T> {
+        let result = self.deploys.exclusive(operation).await;
+        if let Some(poll) = self.git_status.write().await.as_mut() {
+            poll.status = None;
+        }
+        result
+    }
+
+    /// Checks out `target` and records it as the deployed ref, so it survives a restart
+    pub async fn checkout(&self, target: GitRef) -> Result<CheckoutResult> {
+        let result = self.git.checkout(target).await?;
+        let path = deployed_ref_path(&self.config.data_dir);
+        std::fs::write(&path, serde_json::to_string(&self.git.target())?)?;
+        Ok(result)
+    }
+}
+
+fn deployed_ref_path(data_dir: &str) -> PathBuf {
+    Path::new(data_dir).join(DEPLOYED_REF_FILE)
+}
+
+/// Reads the ref recorded by [`Project::checkout`], if any
+fn load_deployed_ref(data_dir: &str) -> Option<GitRef> {
+    let path = deployed_ref_path(data_dir);
+    let contents = std::fs::read_to_string(&path).ok()?;
+    match serde_json::from_str(&contents) {
+        Ok(target) => Some(target),
+        Err(e) => {
+            warn!("Ignoring malformed {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+/// The configured projects, in configuration order
+#[derive(Default)]
+pub struct ProjectRegistry {
+    projects: Vec<Arc<Project>>,
+}
+
+impl ProjectRegistry {
+    pub fn new(projects: Vec<Project>) -> Self {
+        Self {
+            projects: projects.into_iter().map(Arc::new).collect(),
+        }
+    }
+
+    /// Looks up a project by name
+    pub fn get(&self, name: &str) -> Option<Arc<Project>> {
+        self.projects.iter().find(|project| project.name() == name).cloned()
+    }
+
+    pub fn all(&self) -> &[Arc<Project>] {
+        &self.projects
+    }
+}
@@ -1,27 +1,40 @@
 use axum::{
-    extract::State,
-    response::{Html, IntoResponse, Redirect, Response},
+    body::Bytes,
+    extract::{FromRequestParts, Path, Query, State},
+    http::{request::Parts, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Html, IntoResponse, Redirect, Response,
+    },
     routing::{get, post},
     Form, Json, Router,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
 use std::sync::Arc;
+use tokio_stream::StreamExt;
 use tower_sessions::Session;
 use askama::Template;
 
 use crate::{
-    auth::{self, SESSION_USER_KEY},
-    config::Config,
-    docker::DockerManager,
-    git::GitManager,
+    auth::{self, Actor, SESSION_USER_KEY},
+    deploy::{Deploy, DeployOptions, DeployStatus, RollbackHold},
+    docker::{ContainerInfo, ContainerStats, ContainerStatus, HealthStatus, LogLine, LogOptions, UpdateResult},
+    error::{MonitorError, PullFailure},
+    git::{CommitInfo, CommitSignature, DiffSummary, GitRef, GitStatus, PullOptions, RemoteRefs, SubmoduleStatus, WorkingTreeStatus},
+    history::{HistoryEntry, HistoryQuery, HistoryStore, NewHistoryEntry},
+    operations::{OperationEvent, OperationRegistry},
+    project::{Project, ProjectRegistry},
+    webhook::{self, PushEvent},
 };
 
 // Application state shared across handlers
 #[derive(Clone)]
 pub struct AppState {
-    pub config: Arc<Config>,
-    pub git: Arc<GitManager>,
-    pub docker: Arc<DockerManager>,
+    pub projects: Arc<ProjectRegistry>,
+    pub operations: Arc<OperationRegistry>,
+    pub history: Arc<HistoryStore>,
     pub password_hash: Arc<String>,
 }
 
@@ -35,20 +48,105 @@ struct LoginTemplate {
 #[derive(Template)]
 #[template(path = "dashboard.html")]
 struct DashboardTemplate {
+    projects: Vec<ProjectSummary>,
+}
+
+#[derive(Template)]
+#[template(path = "project.html")]
+struct ProjectTemplate {
+    project: String,
     repo_path: String,
     current_branch: String,
+    target: GitRef,
     local_commit: String,
     remote_commit: String,
     updates_available: bool,
+    ahead: usize,
+    behind: usize,
+    working_tree: WorkingTreeStatus,
+    stashes: usize,
+    drifted_submodules: Vec<SubmoduleStatus>,
+    signature: Option<CommitSignature>,
+    pull_strategy: String,
     containers: Vec<ContainerDisplay>,
+    deploys: Vec<DeployDisplay>,
+    previous_deploy: Option<String>,
+    rollback_hold: Option<RollbackHold>,
+    poll: Option<PollDisplay>,
+}
+
+struct DeployDisplay {
+    id: String,
+    status: String,
+    started_at: String,
+    commit: String,
+    rollback_of: String,
+    can_roll_back: bool,
+}
+
+/// Number of deploys listed on the dashboard
+const DASHBOARD_DEPLOYS: usize = 10;
+
+#[derive(Template)]
+#[template(path = "history.html")]
+struct HistoryTemplate {
+    entries: Vec<HistoryDisplay>,
+    projects: Vec<String>,
+    project: String,
+    action: String,
+    target: String,
+    success: String,
+    total: usize,
+    first: usize,
+    last: usize,
+    prev_offset: Option<usize>,
+    next_offset: Option<usize>,
+}
+
+struct HistoryDisplay {
+    timestamp: String,
+    actor: String,
+    project: String,
+    action: String,
+    target: String,
+    commit: String,
+    success: bool,
+    message: String,
+    output: String,
+}
+
+impl From<HistoryEntry> for HistoryDisplay {
+    fn from(entry: HistoryEntry) -> Self {
+        Self {
+            timestamp: entry.timestamp.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            actor: entry.actor,
+            project: entry.project.unwrap_or_default(),
+            action: entry.action,
+            target: entry.target.unwrap_or_default(),
+            commit: entry.commit.map(|c| c.chars().take(8).collect()).unwrap_or_default(),
+            success: entry.success,
+            message: entry.message.unwrap_or_default(),
+            output: entry.output.unwrap_or_default(),
+        }
+    }
 }
 
 #[derive(Serialize)]
 struct ContainerDisplay {
     name: String,
+    service: String,
     image: String,
     status: String,
     status_class: String,
+    health: Option<HealthDisplay>,
+}
+
+#[derive(Serialize)]
+struct HealthDisplay {
+    status: String,
+    status_class: String,
+    failing_streak: i64,
+    last_output: Option<String>,
 }
 
 // Form structs
@@ -69,10 +167,98 @@ struct ApiResponse {
     output: Option<String>,
 }
 
+/// Result of a pull; `failure` classifies why a failed pull did not apply
+#[derive(Serialize)]
+struct PullResponse {
+    #[serde(flatten)]
+    response: ApiResponse,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    failure: Option<PullFailure>,
+}
+
+#[derive(Serialize)]
+struct DeployResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    deploy: Option<Deploy>,
+}
+
+#[derive(Serialize)]
+struct OperationResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operation_id: Option<String>,
+}
+
+#[derive(Serialize)]
+struct LogsResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lines: Option<Vec<LogLine>>,
+}
+
+#[derive(Serialize)]
+struct StatsResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    containers: Vec<ContainerStatsDisplay>,
+}
+
+#[derive(Serialize)]
+struct ContainerStatsDisplay {
+    current: ContainerStats,
+    /// Samples taken in the background, oldest first
+    history: Vec<ContainerStats>,
+}
+
+#[derive(Serialize)]
+struct IncomingResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    commits: Option<Vec<CommitInfo>>,
+}
+
+#[derive(Serialize)]
+struct RefsResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    refs: Option<RemoteRefs>,
+}
+
+#[derive(Serialize)]
+struct DiffResponse {
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<DiffSummary>,
+}
+
 #[derive(Serialize)]
 struct StatusResponse {
     git: GitStatusDisplay,
     containers: Vec<ContainerDisplay>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    poll: Option<PollDisplay>,
+}
+
+#[derive(Serialize)]
+struct PollDisplay {
+    checked_at: String,
+    last_error: Option<String>,
 }
 
 #[derive(Serialize)]
@@ -81,6 +267,29 @@ struct GitStatusDisplay {
     remote_commit: String,
     updates_available: bool,
     current_branch: String,
+    target: GitRef,
+    ahead: usize,
+    behind: usize,
+    working_tree: WorkingTreeStatus,
+    stashes: usize,
+    /// Submodules not checked out at their recorded commit
+    drifted_submodules: Vec<SubmoduleStatus>,
+    /// Signature on HEAD, when signature verification is enabled
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<CommitSignature>,
+}
+
+/// One project as listed on the dashboard and by `GET /api/projects`
+#[derive(Serialize)]
+struct ProjectSummary {
+    name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<GitStatusDisplay>,
+    containers_running: usize,
+    containers_unhealthy: usize,
+    containers_total: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
 }
 
 // Create the router with all routes
@@ -92,21 +301,214 @@ pub fn create_router(state: AppState) -> Router {
         .route("/logout", get(handle_logout))
         // Protected routes
         .route("/dashboard", get(show_dashboard))
+        .route("/projects/:project", get(show_project))
+        .route("/history", get(show_history))
         // API routes
-        .route("/api/status", get(api_status))
-        .route("/api/git/fetch", post(api_git_fetch))
-        .route("/api/git/pull", post(api_git_pull))
-        .route("/api/docker/start/:name", post(api_docker_start))
-        .route("/api/docker/stop/:name", post(api_docker_stop))
-        .route("/api/docker/restart/:name", post(api_docker_restart))
-        .route("/api/docker/update/:name", post(api_docker_update))
-        .route("/api/docker/start-all", post(api_docker_start_all))
-        .route("/api/docker/stop-all", post(api_docker_stop_all))
-        .route("/api/docker/restart-all", post(api_docker_restart_all))
-        .route("/api/docker/update-all", post(api_docker_update_all))
+        .route("/api/projects", get(api_projects))
+        .route("/api/projects/:project/status", get(api_status))
+        .route("/api/projects/:project/git/fetch", post(api_git_fetch))
+        .route("/api/projects/:project/git/pull", post(api_git_pull))
+        .route("/api/projects/:project/git/stash", post(api_git_stash))
+        .route("/api/projects/:project/git/stash-pop", post(api_git_stash_pop))
+        .route("/api/projects/:project/git/discard", post(api_git_discard))
+        .route("/api/projects/:project/git/refs", get(api_git_refs))
+        .route("/api/projects/:project/git/checkout", post(api_git_checkout))
+        .route("/api/projects/:project/git/incoming", get(api_git_incoming))
+        .route("/api/projects/:project/git/diff", get(api_git_diff))
+        .route("/api/projects/:project/docker/start/:name", post(api_docker_start))
+        .route("/api/projects/:project/docker/stop/:name", post(api_docker_stop))
+        .route("/api/projects/:project/docker/restart/:name", post(api_docker_restart))
+        .route("/api/projects/:project/docker/update/:name", post(api_docker_update))
+        .route("/api/projects/:project/docker/logs/:name", get(api_docker_logs))
+        .route("/api/projects/:project/docker/stats", get(api_docker_stats))
+        .route("/api/projects/:project/docker/start-all", post(api_docker_start_all))
+        .route("/api/projects/:project/docker/stop-all", post(api_docker_stop_all))
+        .route("/api/projects/:project/docker/restart-all", post(api_docker_restart_all))
+        .route("/api/projects/:project/docker/update-all", post(api_docker_update_all))
+        .route("/api/projects/:project/operations/docker/update/:name", post(api_operation_docker_update))
+        .route("/api/projects/:project/operations/docker/update-all", post(api_operation_docker_update_all))
+        .route("/api/projects/:project/deploy", post(api_deploy))
+        .route("/api/projects/:project/deploy/:id", get(api_deploy_get))
+        .route("/api/projects/:project/deploys", get(api_deploy_list))
+        .route("/api/projects/:project/rollback/:deploy_id", post(api_rollback))
+        .route("/api/projects/:project/rollback-hold", get(api_rollback_hold).delete(api_release_rollback_hold))
+        .route("/api/operations/:id/stream", get(api_operation_stream))
+        .route("/api/history", get(api_history))
+        // Webhooks (authenticated by signature instead of session)
+        .route("/api/projects/:project/webhooks/github", post(api_webhook_github))
         .with_state(state)
 }
 
+/// HTTP status for a failed container operation; other failures keep the 200 with `success: false`
+fn docker_error_status(error: &MonitorError) -> StatusCode {
+    match error {
+        MonitorError::UnknownContainer(_) => StatusCode::NOT_FOUND,
+        MonitorError::ProtectedContainer(_) => StatusCode::FORBIDDEN,
+        MonitorError::Busy(_) => StatusCode::CONFLICT,
+        _ => StatusCode::OK,
+    }
+}
+
+/// Error message for a project name that is not configured
+fn unknown_project(name: &str) -> String {
+    format!("Project '{}' not found", name)
+}
+
+/// Rejection of an API request, in the shape of a failed [`ApiResponse`]
+fn api_error(status: StatusCode, error: String) -> (StatusCode, Json<ApiResponse>) {
+    (
+        status,
+        Json(ApiResponse {
+            success: false,
+            error: Some(error),
+            message: None,
+            output: None,
+        }),
+    )
+}
+
+/// The project named by the `:project` path parameter of an authenticated API request.
+/// Rejects with 401 without a session and with 404 for a project that is not configured.
+struct ProjectExtractor(Arc<Project>);
+
+#[axum::async_trait]
+impl FromRequestParts<AppState> for ProjectExtractor {
+    type Rejection = (StatusCode, Json<ApiResponse>);
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, Self::Rejection> {
+        let session = Session::from_request_parts(parts, state)
+            .await
+            .map_err(|(status, error)| api_error(status, error.to_string()))?;
+        if !auth::is_authenticated(&session).await {
+            return Err(api_error(StatusCode::UNAUTHORIZED, "Unauthorized".to_string()));
+        }
+
+        let Path(params) = Path::<HashMap<String, String>>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| api_error(e.status(), e.body_text()))?;
+        let name = params.get("project").map(String::as_str).unwrap_or_default();
+        state
+            .projects
+            .get(name)
+            .map(ProjectExtractor)
+            .ok_or_else(|| api_error(StatusCode::NOT_FOUND, unknown_project(name)))
+    }
+}
+
+/// Records the outcome of an API call in the operation history
+async fn record_history(
+    state: &AppState,
+    project: &Project,
+    actor: &Actor,
+    action: &str,
+    target: Option<&str>,
+    response: &ApiResponse,
+) {
+    state
+        .history
+        .record(NewHistoryEntry {
+            actor: actor.0.clone(),
+            project: Some(project.name().to_string()),
+            action: action.to_string(),
+            target: target.map(str::to_string),
+            commit: project.git.get_local_commit().await.ok(),
+            success: response.success,
+            message: response.message.clone().or_else(|| response.error.clone()),
+            output: response.output.clone(),
+        })
+        .await;
+}
+
+fn git_status_display(status: GitStatus) -> GitStatusDisplay {
+    let drifted_submodules = status.drifted_submodules().cloned().collect();
+    GitStatusDisplay {
+        local_commit: status.local_commit[..8].to_string(),
+        remote_commit: status.remote_commit[..8].to_string(),
+        updates_available: status.updates_available,
+        current_branch: status.current_branch,
+        target: status.target,
+        ahead: status.ahead,
+        behind: status.behind,
+        working_tree: status.working_tree,
+        stashes: status.stashes,
+        drifted_submodules,
+        signature: status.signature,
+    }
+}
+
+/// Summarises the latest background poll for display
+async fn poll_display(project: &Project) -> Option<PollDisplay> {
+    project.git_status.read().await.as_ref().map(|poll| PollDisplay {
+        checked_at: poll.checked_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+        last_error: poll.last_error.clone(),
+    })
+}
+
+fn container_display(info: ContainerInfo) -> ContainerDisplay {
+    let status_str = info.status.to_string();
+    let status_class = match status_str.as_str() {
+        "running" => "running",
+        "stopped" | "exited" => "stopped",
+        "paused" => "paused",
+        _ => "error",
+    };
+    let health = info.health.map(|health| HealthDisplay {
+        status: health.status.to_string(),
+        status_class: match health.status {
+            HealthStatus::Healthy => "running",
+            HealthStatus::Starting => "paused",
+            HealthStatus::Unhealthy => "error",
+        }
+        .to_string(),
+        failing_streak: health.failing_streak,
+        last_output: health.last_output,
+    });
+    ContainerDisplay {
+        name: info.name,
+        service: info.service,
+        image: info.image,
+        status: status_str,
+        status_class: status_class.to_string(),
+        health,
+    }
+}
+
+/// Collects the git and container status of a project for the project list
+async fn project_summary(project: &Project) -> ProjectSummary {
+    let mut summary = ProjectSummary {
+        name: project.name().to_string(),
+        git: None,
+        containers_running: 0,
+        containers_unhealthy: 0,
+        containers_total: 0,
+        error: None,
+    };
+
+    match project.current_git_status().await {
+        Ok(status) => {
+            summary.git = Some(git_status_display(status))
+        }
+        Err(e) => summary.error = Some(format!("Git error: {}", e)),
+    }
+
+    match project.docker.get_all_container_status().await {
+        Ok(infos) => {
+            summary.containers_total = project.docker.container_names().await.map_or(infos.len(), |names| names.len());
+            summary.containers_running = infos
+                .iter()
+                .filter(|info| info.status == ContainerStatus::Running)
+                .count();
+            summary.containers_unhealthy = infos
+                .iter()
+                .filter(|info| matches!(&info.health, Some(health) if health.status == HealthStatus::Unhealthy))
+                .count();
+        }
+        Err(e) => summary.error = Some(format!("Docker error: {}", e)),
+    }
+
+    summary
+}
+
 // Route handlers
 
 async fn index() -> Redirect {
@@ -165,8 +567,36 @@ async fn show_dashboard(State(state): State<AppState>, session: Session) -> Resp
         return Redirect::to("/login").into_response();
     }
 
+    // With a single project there is nothing to choose from
+    if let [project] = state.projects.all() {
+        return Redirect::to(&format!("/projects/{}", project.name())).into_response();
+    }
+
+    let mut projects = Vec::new();
+    for project in state.projects.all() {
+        projects.push(project_summary(project).await);
+    }
+
+    let template = DashboardTemplate { projects };
+    Html(template.render().unwrap()).into_response()
+}
+
+async fn show_project(
+    State(state): State<AppState>,
+    session: Session,
+    Path(project_name): Path<String>,
+) -> Response {
+    // Check authentication
+    if !auth::is_authenticated(&session).await {
+        return Redirect::to("/login").into_response();
+    }
+
+    let Some(project) = state.projects.get(&project_name) else {
+        return (StatusCode::NOT_FOUND, Html(unknown_project(&project_name))).into_response();
+    };
+
     // Get git status
-    let git_status = match state.git.get_status() {
+    let git_status = match project.current_git_status().await {
         Ok(status) => status,
         Err(e) => {
             tracing::error!("Failed to get git status: {}", e);
@@ -175,7 +605,7 @@ async fn show_dashboard(State(state): State<AppState>, session: Session) -> Resp
     };
 
     // Get container statuses
-    let container_infos = match state.docker.get_all_container_status().await {
+    let container_infos = match project.docker.get_all_container_status().await {
         Ok(infos) => infos,
         Err(e) => {
             tracing::error!("Failed to get container status: {}", e);
@@ -183,32 +613,82 @@ async fn show_dashboard(State(state): State<AppState>, session: Session) -> Resp
         }
     };
 
-    let containers: Vec<ContainerDisplay> = container_infos
+    let containers: Vec<ContainerDisplay> = container_infos.into_iter().map(container_display).collect();
+
+    let deploys = project.deploys.list().await;
+    // The deploy before the current one, i.e. the target of "roll back to previous deploy"
+    let previous_deploy = deploys
+        .iter()
+        .filter(|deploy| deploy.status == DeployStatus::Succeeded)
+        .nth(1)
+        .map(|deploy| deploy.id.clone());
+    let deploys = deploys
         .into_iter()
-        .map(|info| {
-            let status_str = info.status.to_string();
-            let status_class = match status_str.as_str() {
-                "running" => "running",
-                "stopped" | "exited" => "stopped",
-                "paused" => "paused",
-                _ => "error",
-            };
-            ContainerDisplay {
-                name: info.name,
-                image: info.image,
-                status: status_str,
-                status_class: status_class.to_string(),
-            }
+        .take(DASHBOARD_DEPLOYS)
+        .enumerate()
+        .map(|(index, deploy)| DeployDisplay {
+            can_roll_back: index > 0
+                && deploy.status == DeployStatus::Succeeded
+                && deploy.commit.is_some()
+                && !deploy.images.is_empty(),
+            status: format!("{:?}", deploy.status).to_lowercase(),
+            started_at: deploy.started_at.format("%Y-%m-%d %H:%M:%S UTC").to_string(),
+            commit: deploy.commit.map(|c| c.chars().take(8).collect()).unwrap_or_default(),
+            rollback_of: deploy.rollback_of.unwrap_or_default(),
+            id: deploy.id,
         })
         .collect();
 
-    let template = DashboardTemplate {
-        repo_path: state.config.git.repo_path.clone(),
+    let drifted_submodules = git_status.drifted_submodules().cloned().collect();
+    let template = ProjectTemplate {
+        project: project.name().to_string(),
+        repo_path: project.config.git.repo_path.clone(),
         current_branch: git_status.current_branch,
+        target: git_status.target,
         local_commit: git_status.local_commit[..8].to_string(),
         remote_commit: git_status.remote_commit[..8].to_string(),
         updates_available: git_status.updates_available,
+        ahead: git_status.ahead,
+        behind: git_status.behind,
+        working_tree: git_status.working_tree,
+        stashes: git_status.stashes,
+        drifted_submodules,
+        signature: git_status.signature,
+        pull_strategy: project.git.pull_strategy().to_string(),
         containers,
+        deploys,
+        previous_deploy,
+        rollback_hold: project.deploys.rollback_hold().await,
+        poll: poll_display(&project).await,
+    };
+
+    Html(template.render().unwrap()).into_response()
+}
+
+async fn show_history(
+    State(state): State<AppState>,
+    session: Session,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
+    if !auth::is_authenticated(&session).await {
+        return Redirect::to("/login").into_response();
+    }
+
+    let page = state.history.query(&query).await;
+    let shown = page.entries.len();
+
+    let template = HistoryTemplate {
+        projects: state.projects.all().iter().map(|project| project.name().to_string()).collect(),
+        project: query.project.clone().unwrap_or_default(),
+        action: query.action.clone().unwrap_or_default(),
+        target: query.target.clone().unwrap_or_default(),
+        success: query.success.map(|s| s.to_string()).unwrap_or_default(),
+        total: page.total,
+        first: if shown == 0 { 0 } else { page.offset + 1 },
+        last: page.offset + shown,
+        prev_offset: (page.offset > 0).then(|| page.offset.saturating_sub(page.limit)),
+        next_offset: (page.offset + shown < page.total).then_some(page.offset + shown),
+        entries: page.entries.into_iter().map(HistoryDisplay::from).collect(),
     };
 
     Html(template.render().unwrap()).into_response()
@@ -216,7 +696,7 @@ async fn show_dashboard(State(state): State<AppState>, session: Session) -> Resp
 
 // API handlers
 
-async fn api_status(State(state): State<AppState>, session: Session) -> Response {
+async fn api_projects(State(state): State<AppState>, session: Session) -> Response {
     if !auth::is_authenticated(&session).await {
         return Json(ApiResponse {
             success: false,
@@ -227,7 +707,18 @@ async fn api_status(State(state): State<AppState>, session: Session) -> Response
         .into_response();
     }
 
-    let git_status = match state.git.get_status() {
+    let mut projects = Vec::new();
+    for project in state.projects.all() {
+        projects.push(project_summary(project).await);
+    }
+
+    Json(projects).into_response()
+}
+
+async fn api_status(
+    ProjectExtractor(project): ProjectExtractor,
+) -> Response {
+    let git_status = match project.current_git_status().await {
         Ok(status) => status,
         Err(e) => {
             return Json(ApiResponse {
@@ -240,7 +731,7 @@ async fn api_status(State(state): State<AppState>, session: Session) -> Response
         }
     };
 
-    let container_infos = match state.docker.get_all_container_status().await {
+    let container_infos = match project.docker.get_all_container_status().await {
         Ok(infos) => infos,
         Err(e) => {
             return Json(ApiResponse {
@@ -253,48 +744,22 @@ async fn api_status(State(state): State<AppState>, session: Session) -> Response
         }
     };
 
-    let containers: Vec<ContainerDisplay> = container_infos
-        .into_iter()
-        .map(|info| {
-            let status_str = info.status.to_string();
-            let status_class = match status_str.as_str() {
-                "Running" => "running",
-                "Stopped" | "Exited" => "stopped",
-                "Paused" => "paused",
-                _ => "error",
-            };
-            ContainerDisplay {
-                name: info.name,
-                image: info.image,
-                status: status_str,
-                status_class: status_class.to_string(),
-            }
-        })
-        .collect();
+    let containers: Vec<ContainerDisplay> = container_infos.into_iter().map(container_display).collect();
 
     Json(StatusResponse {
-        git: GitStatusDisplay {
-            local_commit: git_status.local_commit[..8].to_string(),
-            remote_commit: git_status.remote_commit[..8].to_string(),
-            updates_available: git_status.updates_available,
-            current_branch: git_status.current_branch,
-        },
+        git: git_status_display(git_status),
         containers,
+        poll: poll_display(&project).await,
     })
     .into_response()
 }
 
-async fn api_git_fetch(State(state): State<AppState>, session: Session) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
-
-    match state.git.fetch() {
+async fn api_git_fetch(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+) -> Json<ApiResponse> {
+    let response = match project.git.fetch().await {
         Ok(_) => Json(ApiResponse {
             success: true,
             message: Some("Successfully fetched updates from remote".to_string()),
@@ -307,148 +772,299 @@ async fn api_git_fetch(State(state): State<AppState>, session: Session) -> Json<
             message: None,
             output: None,
         }),
-    }
+    };
+
+    record_history(&state, &project, &actor, "git.fetch", None, &response).await;
+    response
 }
 
-async fn api_git_pull(State(state): State<AppState>, session: Session) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
+async fn api_git_pull(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    options: Option<Json<PullOptions>>,
+) -> Json<PullResponse> {
+    let options = options.map(|Json(options)| options).unwrap_or_default();
+    let strategy = options.strategy.unwrap_or_else(|| project.git.pull_strategy());
 
-    match state.git.pull() {
+    let response = match project.change_working_tree(project.git.pull_with(&options)).await {
         Ok(result) => {
-            let message = if result.already_up_to_date {
+            let mut message = if result.already_up_to_date {
                 "Already up to date".to_string()
             } else {
-                format!("Successfully pulled {} file(s)", result.files_changed)
+                format!("Successfully pulled {} file(s) ({})", result.files_changed, strategy)
             };
-            Json(ApiResponse {
-                success: true,
-                message: Some(message),
-                error: None,
-                output: Some(result.output),
-            })
+            if let Some(err) = &result.extras_error {
+                message.push_str(&format!(", but updating submodules or LFS objects failed: {}", err));
+            }
+            PullResponse {
+                response: ApiResponse {
+                    success: true,
+                    message: Some(message),
+                    error: None,
+                    output: Some(result.output),
+                },
+                failure: None,
+            }
         }
-        Err(e) => Json(ApiResponse {
-            success: false,
-            error: Some(format!("Pull failed: {}", e)),
-            message: None,
-            output: None,
-        }),
-    }
+        Err(e) => PullResponse {
+            failure: match &e {
+                MonitorError::Pull { kind, .. } => Some(*kind),
+                _ => None,
+            },
+            response: ApiResponse {
+                success: false,
+                error: Some(format!("Pull failed: {}", e)),
+                message: None,
+                output: None,
+            },
+        },
+    };
+
+    record_history(&state, &project, &actor, "git.pull", None, &response.response).await;
+    Json(response)
 }
 
-async fn api_docker_start(
+async fn api_git_stash(
     State(state): State<AppState>,
-    session: Session,
-    axum::extract::Path(name): axum::extract::Path<String>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
 ) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
-
-    match state.docker.start_container(&name).await {
-        Ok(_) => Json(ApiResponse {
+    let response = match project.change_working_tree(project.git.stash()).await {
+        Ok(output) => Json(ApiResponse {
             success: true,
-            message: Some(format!("Successfully started container '{}'", name)),
+            message: Some("Stashed local changes".to_string()),
             error: None,
-            output: None,
+            output: Some(output),
         }),
         Err(e) => Json(ApiResponse {
             success: false,
-            error: Some(format!("Failed to start container: {}", e)),
+            error: Some(format!("Stash failed: {}", e)),
             message: None,
             output: None,
         }),
-    }
+    };
+
+    record_history(&state, &project, &actor, "git.stash", None, &response).await;
+    response
 }
 
-async fn api_docker_stop(
+async fn api_git_stash_pop(
     State(state): State<AppState>,
-    session: Session,
-    axum::extract::Path(name): axum::extract::Path<String>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
 ) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
-
-    match state.docker.stop_container(&name).await {
-        Ok(_) => Json(ApiResponse {
+    let response = match project.change_working_tree(project.git.stash_pop()).await {
+        Ok(output) => Json(ApiResponse {
             success: true,
-            message: Some(format!("Successfully stopped container '{}'", name)),
+            message: Some("Restored stashed changes".to_string()),
             error: None,
-            output: None,
+            output: Some(output),
         }),
         Err(e) => Json(ApiResponse {
             success: false,
-            error: Some(format!("Failed to stop container: {}", e)),
+            error: Some(format!("Stash pop failed: {}", e)),
             message: None,
             output: None,
         }),
-    }
+    };
+
+    record_history(&state, &project, &actor, "git.stash-pop", None, &response).await;
+    response
 }
 
-async fn api_docker_restart(
+async fn api_git_discard(
     State(state): State<AppState>,
-    session: Session,
-    axum::extract::Path(name): axum::extract::Path<String>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
 ) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
+    let response = match project.change_working_tree(project.git.discard_changes()).await {
+        Ok(output) => Json(ApiResponse {
+            success: true,
+            message: Some("Discarded local changes".to_string()),
+            error: None,
+            output: Some(output),
+        }),
+        Err(e) => Json(ApiResponse {
             success: false,
-            error: Some("Unauthorized".to_string()),
+            error: Some(format!("Discard failed: {}", e)),
             message: None,
             output: None,
-        });
-    }
+        }),
+    };
 
-    match state.docker.restart_container(&name).await {
-        Ok(_) => Json(ApiResponse {
+    record_history(&state, &project, &actor, "git.discard", None, &response).await;
+    response
+}
+
+async fn api_git_refs(
+    ProjectExtractor(project): ProjectExtractor,
+) -> Json<RefsResponse> {
+    match project.git.remote_refs().await {
+        Ok(refs) => Json(RefsResponse {
             success: true,
-            message: Some(format!("Successfully restarted container '{}'", name)),
             error: None,
-            output: None,
+            refs: Some(refs),
         }),
+        Err(e) => Json(RefsResponse {
+            success: false,
+            error: Some(format!("Failed to list remote refs: {}", e)),
+            refs: None,
+        }),
+    }
+}
+
+async fn api_git_checkout(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Json(target): Json<GitRef>,
+) -> Json<ApiResponse> {
+    let label = target.to_string();
+    let response = match project.change_working_tree(project.checkout(target)).await {
+        Ok(result) => {
+            let mut message = format!("Checked out {}", project.git.target());
+            if let Some(err) = &result.extras_error {
+                message.push_str(&format!(", but updating submodules or LFS objects failed: {}", err));
+            }
+            Json(ApiResponse {
+                success: true,
+                message: Some(message),
+                error: None,
+                output: Some(result.output),
+            })
+        }
         Err(e) => Json(ApiResponse {
             success: false,
-            error: Some(format!("Failed to restart container: {}", e)),
+            error: Some(format!("Checkout failed: {}", e)),
             message: None,
             output: None,
         }),
+    };
+
+    record_history(&state, &project, &actor, "git.checkout", Some(&label), &response).await;
+    response
+}
+
+async fn api_git_incoming(
+    ProjectExtractor(project): ProjectExtractor,
+) -> Json<IncomingResponse> {
+    match project.git.incoming_commits().await {
+        Ok(commits) => Json(IncomingResponse {
+            success: true,
+            error: None,
+            commits: Some(commits),
+        }),
+        Err(e) => Json(IncomingResponse {
+            success: false,
+            error: Some(format!("Failed to list incoming commits: {}", e)),
+            commits: None,
+        }),
     }
 }
 
-async fn api_docker_update(
+async fn api_git_diff(
+    ProjectExtractor(project): ProjectExtractor,
+) -> Json<DiffResponse> {
+    match project.git.incoming_diff().await {
+        Ok(diff) => Json(DiffResponse {
+            success: true,
+            error: None,
+            diff: Some(diff),
+        }),
+        Err(e) => Json(DiffResponse {
+            success: false,
+            error: Some(format!("Failed to diff incoming changes: {}", e)),
+            diff: None,
+        }),
+    }
+}
+
+async fn api_docker_start(
     State(state): State<AppState>,
-    session: Session,
-    axum::extract::Path(name): axum::extract::Path<String>,
-) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Path((_, name)): Path<(String, String)>,
+) -> (StatusCode, Json<ApiResponse>) {
+    let (status, response) = match project.docker.start_container(&name).await {
+        Ok(_) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            message: Some(format!("Successfully started container '{}'", name)),
+            error: None,
+            output: None,
+        })),
+        Err(e) => (docker_error_status(&e), Json(ApiResponse {
             success: false,
-            error: Some("Unauthorized".to_string()),
+            error: Some(format!("Failed to start container: {}", e)),
             message: None,
             output: None,
-        });
-    }
+        })),
+    };
+
+    record_history(&state, &project, &actor, "docker.start", Some(&name), &response).await;
+    (status, response)
+}
+
+async fn api_docker_stop(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Path((_, name)): Path<(String, String)>,
+) -> (StatusCode, Json<ApiResponse>) {
+    let (status, response) = match project.docker.stop_container(&name).await {
+        Ok(_) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            message: Some(format!("Successfully stopped container '{}'", name)),
+            error: None,
+            output: None,
+        })),
+        Err(e) => (docker_error_status(&e), Json(ApiResponse {
+            success: false,
+            error: Some(format!("Failed to stop container: {}", e)),
+            message: None,
+            output: None,
+        })),
+    };
+
+    record_history(&state, &project, &actor, "docker.stop", Some(&name), &response).await;
+    (status, response)
+}
 
-    match state.docker.update_container(&name).await {
-        Ok(result) => Json(ApiResponse {
+async fn api_docker_restart(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Path((_, name)): Path<(String, String)>,
+) -> (StatusCode, Json<ApiResponse>) {
+    let (status, response) = match project.docker.restart_container(&name).await {
+        Ok(_) => (StatusCode::OK, Json(ApiResponse {
+            success: true,
+            message: Some(format!("Successfully restarted container '{}'", name)),
+            error: None,
+            output: None,
+        })),
+        Err(e) => (docker_error_status(&e), Json(ApiResponse {
+            success: false,
+            error: Some(format!("Failed to restart container: {}", e)),
+            message: None,
+            output: None,
+        })),
+    };
+
+    record_history(&state, &project, &actor, "docker.restart", Some(&name), &response).await;
+    (status, response)
+}
+
+async fn api_docker_update(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Path((_, name)): Path<(String, String)>,
+) -> (StatusCode, Json<ApiResponse>) {
+    // Compose replaces the containers a deploy or rollback may be working on
+    let update = project.deploys.exclusive(project.docker.update_container(&name));
+    let (status, response) = match update.await {
+        Ok(result) => (StatusCode::OK, Json(ApiResponse {
             success: result.success,
             message: if result.success {
                 Some(format!("Successfully updated container '{}'", name))
@@ -457,30 +1073,94 @@ async fn api_docker_update(
             },
             error: result.error,
             output: Some(result.output),
-        }),
-        Err(e) => Json(ApiResponse {
+        })),
+        Err(e) => (docker_error_status(&e), Json(ApiResponse {
             success: false,
             error: Some(format!("Failed to update container: {}", e)),
             message: None,
             output: None,
-        }),
+        })),
+    };
+
+    record_history(&state, &project, &actor, "docker.update", Some(&name), &response).await;
+    (status, response)
+}
+
+async fn api_docker_logs(
+    ProjectExtractor(project): ProjectExtractor,
+    Path((_, name)): Path<(String, String)>,
+    Query(options): Query<LogOptions>,
+) -> Response {
+    if options.follow {
+        let stream = match project.docker.follow_container_logs(&name, &options).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                return (
+                    docker_error_status(&e),
+                    Json(LogsResponse {
+                        success: false,
+                        error: Some(format!("Failed to read logs: {}", e)),
+                        lines: None,
+                    }),
+                )
+                    .into_response()
+            }
+        };
+        let stream = stream.map(|line| {
+            let event = match line {
+                Ok(line) => Event::default()
+                    .event("log")
+                    .json_data(&line)
+                    .unwrap_or_else(|_| Event::default().event("log").data("{}")),
+                Err(e) => Event::default().event("error").data(e.to_string()),
+            };
+            Ok::<_, Infallible>(event)
+        });
+
+        return Sse::new(stream).keep_alive(KeepAlive::default()).into_response();
+    }
+
+    match project.docker.container_logs(&name, &options).await {
+        Ok(lines) => Json(LogsResponse {
+            success: true,
+            error: None,
+            lines: Some(lines),
+        })
+        .into_response(),
+        Err(e) => (
+            docker_error_status(&e),
+            Json(LogsResponse {
+                success: false,
+                error: Some(format!("Failed to read logs: {}", e)),
+                lines: None,
+            }),
+        )
+            .into_response(),
     }
 }
 
+async fn api_docker_stats(
+    ProjectExtractor(project): ProjectExtractor,
+) -> Json<StatsResponse> {
+    let mut containers = Vec::new();
+    for current in project.docker.all_container_stats().await {
+        let history = project.stats.samples(&current.name).await;
+        containers.push(ContainerStatsDisplay { current, history });
+    }
+
+    Json(StatsResponse {
+        success: true,
+        error: None,
+        containers,
+    })
+}
+
 async fn api_docker_start_all(
     State(state): State<AppState>,
-    session: Session,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
 ) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
-
-    match state.docker.start_all_containers().await {
+    let response = match project.docker.start_all_containers().await {
         Ok(_) => Json(ApiResponse {
             success: true,
             message: Some("Successfully started all containers".to_string()),
@@ -493,23 +1173,18 @@ async fn api_docker_start_all(
             message: None,
             output: None,
         }),
-    }
+    };
+
+    record_history(&state, &project, &actor, "docker.start-all", None, &response).await;
+    response
 }
 
 async fn api_docker_stop_all(
     State(state): State<AppState>,
-    session: Session,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
 ) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
-
-    match state.docker.stop_all_containers().await {
+    let response = match project.docker.stop_all_containers().await {
         Ok(_) => Json(ApiResponse {
             success: true,
             message: Some("Successfully stopped all containers".to_string()),
@@ -522,23 +1197,18 @@ async fn api_docker_stop_all(
             message: None,
             output: None,
         }),
-    }
+    };
+
+    record_history(&state, &project, &actor, "docker.stop-all", None, &response).await;
+    response
 }
 
 async fn api_docker_restart_all(
     State(state): State<AppState>,
-    session: Session,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
 ) -> Json<ApiResponse> {
-    if !auth::is_authenticated(&session).await {
-        return Json(ApiResponse {
-            success: false,
-            error: Some("Unauthorized".to_string()),
-            message: None,
-            output: None,
-        });
-    }
-
-    match state.docker.restart_all_containers().await {
+    let response = match project.docker.restart_all_containers().await {
         Ok(_) => Json(ApiResponse {
             success: true,
             message: Some("Successfully restarted all containers".to_string()),
@@ -551,38 +1221,519 @@ async fn api_docker_restart_all(
             message: None,
             output: None,
         }),
-    }
+    };
+
+    record_history(&state, &project, &actor, "docker.restart-all", None, &response).await;
+    response
 }
 
 async fn api_docker_update_all(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+) -> (StatusCode, Json<ApiResponse>) {
+    let update = project.deploys.exclusive(project.docker.update_all_containers());
+    let (status, response) = match update.await {
+        Ok(result) => (StatusCode::OK, Json(ApiResponse {
+            success: result.success,
+            message: if result.success {
+                Some("Successfully updated all containers".to_string())
+            } else {
+                None
+            },
+            error: result.error,
+            output: Some(result.output),
+        })),
+        Err(e) => (docker_error_status(&e), Json(ApiResponse {
+            success: false,
+            error: Some(format!("Failed to update all containers: {}", e)),
+            message: None,
+            output: None,
+        })),
+    };
+
+    record_history(&state, &project, &actor, "docker.update-all", None, &response).await;
+    (status, response)
+}
+
+async fn api_deploy(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    options: Option<Json<DeployOptions>>,
+) -> Json<DeployResponse> {
+    let options = options.map(|Json(options)| options).unwrap_or_default();
+
+    match project.deploys.deploy(options).await {
+        Ok(deploy) => {
+            let success = deploy.status == DeployStatus::Succeeded;
+            state
+                .history
+                .record(NewHistoryEntry {
+                    actor: actor.0,
+                    project: Some(project.name().to_string()),
+                    action: "deploy".to_string(),
+                    target: Some(deploy.id.clone()),
+                    commit: deploy.commit.clone(),
+                    success,
+                    message: Some(format!(
+                        "Deploy {} {}",
+                        deploy.id,
+                        if success { "succeeded" } else { "failed" }
+                    )),
+                    output: Some(deploy.output()),
+                })
+                .await;
+            Json(DeployResponse {
+                success,
+                error: if success {
+                    None
+                } else {
+                    Some(format!("Deploy {} failed", deploy.id))
+                },
+                output: Some(deploy.output()),
+                deploy: Some(deploy),
+            })
+        }
+        Err(e) => {
+            state
+                .history
+                .record(NewHistoryEntry {
+                    actor: actor.0,
+                    project: Some(project.name().to_string()),
+                    action: "deploy".to_string(),
+                    commit: project.git.get_local_commit().await.ok(),
+                    success: false,
+                    message: Some(format!("Deploy failed: {}", e)),
+                    ..Default::default()
+                })
+                .await;
+            Json(DeployResponse {
+                success: false,
+                error: Some(format!("Deploy failed: {}", e)),
+                output: None,
+                deploy: None,
+            })
+        }
+    }
+}
+
+async fn api_deploy_get(
+    ProjectExtractor(project): ProjectExtractor,
+    Path((_, id)): Path<(String, String)>,
+) -> Json<DeployResponse> {
+    match project.deploys.get(&id).await {
+        Some(deploy) => Json(DeployResponse {
+            success: true,
+            error: None,
+            output: Some(deploy.output()),
+            deploy: Some(deploy),
+        }),
+        None => Json(DeployResponse {
+            success: false,
+            error: Some(format!("Deploy '{}' not found", id)),
+            output: None,
+            deploy: None,
+        }),
+    }
+}
+
+async fn api_deploy_list(ProjectExtractor(project): ProjectExtractor) -> Json<Vec<Deploy>> {
+    Json(project.deploys.list().await)
+}
+
+async fn api_operation_docker_update(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Path((_, name)): Path<(String, String)>,
+) -> Response {
+    // Refuse unknown and protected names, and updates during a deploy, before anything is queued
+    let guard = match project.docker.resolve_updatable_service(&name).await {
+        Ok(_) => project.deploys.try_lock(),
+        Err(e) => Err(e),
+    };
+    let guard = match guard {
+        Ok(guard) => guard,
+        Err(e) => {
+            return (
+                docker_error_status(&e),
+                Json(OperationResponse {
+                    success: false,
+                    error: Some(format!("Failed to update container: {}", e)),
+                    operation_id: None,
+                }),
+            )
+                .into_response();
+        }
+    };
+
+    let operation = state.operations.create(format!("Update container '{}'", name));
+    let operation_id = operation.id.clone();
+    let docker = project.docker.clone();
+    let history = state.history.clone();
+    let git = project.git.clone();
+
+    tokio::spawn(async move {
+        let _guard = guard;
+        let result = docker
+            .update_container_streaming(&name, |line| operation.push_line(line))
+            .await
+            .unwrap_or_else(|e| UpdateResult {
+                success: false,
+                output: String::new(),
+                error: Some(format!("Failed to update container: {}", e)),
+                rollbacks: Vec::new(),
+            });
+        history
+            .record(NewHistoryEntry {
+                actor: actor.0,
+                project: Some(project.name().to_string()),
+                action: "docker.update".to_string(),
+                target: Some(name),
+                commit: git.get_local_commit().await.ok(),
+                success: result.success,
+                message: result.error.clone(),
+                output: Some(result.output.clone()),
+            })
+            .await;
+        operation.finish(result);
+    });
+
+    Json(OperationResponse {
+        success: true,
+        error: None,
+        operation_id: Some(operation_id),
+    })
+    .into_response()
+}
+
+async fn api_operation_docker_update_all(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+) -> Response {
+    let guard = match project.deploys.try_lock() {
+        Ok(guard) => guard,
+        Err(e) => {
+            return (
+                docker_error_status(&e),
+                Json(OperationResponse {
+                    success: false,
+                    error: Some(format!("Failed to update all containers: {}", e)),
+                    operation_id: None,
+                }),
+            )
+                .into_response();
+        }
+    };
+    let operation = state.operations.create("Update all containers");
+    let operation_id = operation.id.clone();
+    let docker = project.docker.clone();
+    let history = state.history.clone();
+    let git = project.git.clone();
+
+    tokio::spawn(async move {
+        let _guard = guard;
+        let result = docker
+            .update_all_containers_streaming(|line| operation.push_line(line))
+            .await
+            .unwrap_or_else(|e| UpdateResult {
+                success: false,
+                output: String::new(),
+                error: Some(format!("Failed to update all containers: {}", e)),
+                rollbacks: Vec::new(),
+            });
+        history
+            .record(NewHistoryEntry {
+                actor: actor.0,
+                project: Some(project.name().to_string()),
+                action: "docker.update-all".to_string(),
+                target: None,
+                commit: git.get_local_commit().await.ok(),
+                success: result.success,
+                message: result.error.clone(),
+                output: Some(result.output.clone()),
+            })
+            .await;
+        operation.finish(result);
+    });
+
+    Json(OperationResponse {
+        success: true,
+        error: None,
+        operation_id: Some(operation_id),
+    })
+    .into_response()
+}
+
+async fn api_operation_stream(
     State(state): State<AppState>,
     session: Session,
-) -> Json<ApiResponse> {
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Response {
+    if !auth::is_authenticated(&session).await {
+        return Json(OperationResponse {
+            success: false,
+            error: Some("Unauthorized".to_string()),
+            operation_id: None,
+        })
+        .into_response();
+    }
+
+    let Some(operation) = state.operations.get(&id) else {
+        return Json(OperationResponse {
+            success: false,
+            error: Some(format!("Operation '{}' not found", id)),
+            operation_id: None,
+        })
+        .into_response();
+    };
+
+    let start = Event::default()
+        .event("start")
+        .json_data(serde_json::json!({
+            "id": operation.id,
+            "description": operation.description,
+            "started_at": operation.started_at,
+        }))
+        .unwrap_or_else(|_| Event::default().event("start").data("{}"));
+
+    let stream = tokio_stream::once(start)
+        .chain(operation.follow().map(operation_event_to_sse))
+        .map(Ok::<_, Infallible>);
+
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
+
+fn operation_event_to_sse(event: OperationEvent) -> Event {
+    match event {
+        OperationEvent::Line { line } => Event::default().event("line").data(line),
+        OperationEvent::Done { result } => Event::default()
+            .event("done")
+            .json_data(&result)
+            .unwrap_or_else(|_| Event::default().event("done").data("{}")),
+    }
+}
+
+async fn api_history(
+    State(state): State<AppState>,
+    session: Session,
+    Query(query): Query<HistoryQuery>,
+) -> Response {
     if !auth::is_authenticated(&session).await {
         return Json(ApiResponse {
             success: false,
             error: Some("Unauthorized".to_string()),
             message: None,
             output: None,
-        });
+        })
+        .into_response();
     }
 
-    match state.docker.update_all_containers().await {
-        Ok(result) => Json(ApiResponse {
-            success: result.success,
-            message: if result.success {
-                Some("Successfully updated all containers".to_string())
-            } else {
-                None
-            },
-            error: result.error,
-            output: Some(result.output),
+    Json(state.history.query(&query).await).into_response()
+}
+
+async fn api_rollback(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+    Path((_, deploy_id)): Path<(String, String)>,
+) -> Json<DeployResponse> {
+    let response = match project.deploys.rollback(&deploy_id).await {
+        Ok(deploy) => {
+            let success = deploy.status == DeployStatus::Succeeded;
+            Json(DeployResponse {
+                success,
+                error: if success {
+                    None
+                } else {
+                    Some(format!("Rollback {} failed", deploy.id))
+                },
+                output: Some(deploy.output()),
+                deploy: Some(deploy),
+            })
+        }
+        Err(e) => Json(DeployResponse {
+            success: false,
+            error: Some(format!("Rollback failed: {}", e)),
+            output: None,
+            deploy: None,
         }),
-        Err(e) => Json(ApiResponse {
+    };
+
+    state
+        .history
+        .record(NewHistoryEntry {
+            actor: actor.0,
+            project: Some(project.name().to_string()),
+            action: "rollback".to_string(),
+            target: Some(deploy_id),
+            commit: project.git.get_local_commit().await.ok(),
+            success: response.success,
+            message: response.error.clone(),
+            output: response.output.clone(),
+        })
+        .await;
+
+    response
+}
+
+async fn api_rollback_hold(ProjectExtractor(project): ProjectExtractor) -> Json<Option<RollbackHold>> {
+    Json(project.deploys.rollback_hold().await)
+}
+
+async fn api_release_rollback_hold(
+    State(state): State<AppState>,
+    ProjectExtractor(project): ProjectExtractor,
+    actor: Actor,
+) -> Json<ApiResponse> {
+    let response = match project.deploys.release_hold().await {
+        Some(hold) => Json(ApiResponse {
+            success: true,
+            message: Some(format!("Released the hold on {}; auto-deploy may deploy it again", hold.commit)),
+            error: None,
+            output: None,
+        }),
+        None => Json(ApiResponse {
             success: false,
-            error: Some(format!("Failed to update all containers: {}", e)),
+            error: Some("Auto-deploy is not held".to_string()),
             message: None,
             output: None,
         }),
+    };
+
+    record_history(&state, &project, &actor, "rollback.release", None, &response).await;
+    response
+}
+
+async fn api_webhook_github(
+    State(state): State<AppState>,
+    Path(project_name): Path<String>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> (StatusCode, Json<ApiResponse>) {
+    let reply = |status: StatusCode, success: bool, text: String| {
+        let (message, error) = if success { (Some(text), None) } else { (None, Some(text)) };
+        (
+            status,
+            Json(ApiResponse {
+                success,
+                message,
+                error,
+                output: None,
+            }),
+        )
+    };
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default();
+
+    let Some(project) = state.projects.get(&project_name) else {
+        return reply(StatusCode::NOT_FOUND, false, unknown_project(&project_name));
+    };
+
+    let Some(secret) = &project.config.git.webhook_secret else {
+        return reply(StatusCode::NOT_FOUND, false, "Webhook is not configured".to_string());
+    };
+
+    if !webhook::verify_signature(secret, &body, header("x-hub-signature-256")) {
+        tracing::warn!("[{}] Rejected GitHub webhook with invalid signature", project.name());
+        return reply(StatusCode::UNAUTHORIZED, false, "Invalid signature".to_string());
+    }
+
+    let event = header("x-github-event");
+    let delivery_id = header("x-github-delivery").to_string();
+
+    if event == "ping" {
+        return reply(StatusCode::OK, true, "pong".to_string());
+    }
+    if event != "push" {
+        return reply(StatusCode::ACCEPTED, true, format!("Ignoring '{}' event", event));
+    }
+    if delivery_id.is_empty() {
+        return reply(StatusCode::BAD_REQUEST, false, "Missing X-GitHub-Delivery header".to_string());
+    }
+
+    let push: PushEvent = match serde_json::from_slice(&body) {
+        Ok(push) => push,
+        Err(e) => return reply(StatusCode::BAD_REQUEST, false, format!("Invalid push payload: {}", e)),
+    };
+
+    // Pushes only matter while a branch is deployed; a tag or pinned commit does not move
+    let tracked = project.git.tracked_branch();
+    if push.deleted || !tracked.as_deref().is_some_and(|branch| push.is_branch(branch)) {
+        return reply(
+            StatusCode::ACCEPTED,
+            true,
+            format!("Ignoring push to {} (deployed from {})", push.git_ref, project.git.target()),
+        );
+    }
+
+    if !project.webhook_deliveries.insert(&delivery_id) {
+        tracing::info!("[{}] Ignoring duplicate webhook delivery {}", project.name(), delivery_id);
+        return reply(StatusCode::OK, true, format!("Delivery {} already processed", delivery_id));
     }
+
+    tracing::info!(
+        "[{}] Webhook delivery {} for {} ({}), starting deploy",
+        project.name(),
+        delivery_id,
+        push.git_ref,
+        push.after.as_deref().unwrap_or("unknown commit")
+    );
+
+    // GitHub expects a quick response, so fetch and deploy in the background
+    let message = format!("Delivery {} accepted, deploying {}", delivery_id, push.git_ref);
+    tokio::spawn(async move {
+        let actor = match &push.repository {
+            Some(repository) => format!("github webhook ({})", repository.full_name),
+            None => "github webhook".to_string(),
+        };
+
+        let held = match project.git.fetch().await {
+            Err(e) => Err(e),
+            Ok(()) => match project.git.get_remote_commit().await {
+                Ok(commit) if project.deploys.is_held(&commit).await => Ok(Some(commit)),
+                _ => Ok(None),
+            },
+        };
+        let (success, message, output) = match held {
+            Err(e) => {
+                // Nothing was deployed, so let GitHub's redelivery try again
+                project.webhook_deliveries.remove(&delivery_id);
+                (false, format!("Fetch failed: {}", e), None)
+            }
+            Ok(Some(commit)) => (true, format!("Not deploying {}, held since a rollback", commit), None),
+            Ok(None) => match project.deploys.deploy(DeployOptions::default()).await {
+                Ok(deploy) => {
+                    let success = deploy.status == DeployStatus::Succeeded;
+                    let message = format!(
+                        "Deploy {} {}",
+                        deploy.id,
+                        if success { "succeeded" } else { "failed" }
+                    );
+                    (success, message, Some(deploy.output()))
+                }
+                Err(e) => {
+                    project.webhook_deliveries.remove(&delivery_id);
+                    (false, format!("Deploy failed: {}", e), None)
+                }
+            },
+        };
+
+        state
+            .history
+            .record(NewHistoryEntry {
+                actor,
+                project: Some(project.name().to_string()),
+                action: "webhook".to_string(),
+                target: Some(delivery_id),
+                commit: project.git.get_local_commit().await.ok(),
+                success,
+                message: Some(message),
+                output,
+            })
+            .await;
+    });
+
+    reply(StatusCode::ACCEPTED, true, message)
 }
@@ -0,0 +1,132 @@
+use crate::docker::{ContainerStats, DockerManager};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Samples kept per container, ten minutes at the default interval
+pub const STATS_HISTORY_LEN: usize = 60;
+
+/// Recent resource usage samples of each container, oldest first
+pub struct StatsHistory {
+    capacity: usize,
+    samples: RwLock<HashMap<String, VecDeque<ContainerStats>>>,
+}
+
+impl Default for StatsHistory {
+    fn default() -> Self {
+        Self::new(STATS_HISTORY_LEN)
+    }
+}
+
+impl StatsHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            samples: RwLock::new(HashMap::new()),
+        }
+    }
+
+    /// Adds a sample, dropping the oldest one of that container when the buffer is full
+    pub async fn push(&self, stats: ContainerStats) {
+        let mut samples = self.samples.write().await;
+        let buffer = samples.entry(stats.name.clone()).or_default();
+        if buffer.len() == self.capacity {
+            buffer.pop_front();
+        }
+        buffer.push_back(stats);
+    }
+
+    /// Forgets the containers not in `names`, e.g. ones removed or recreated under another name
+    pub async fn retain(&self, names: &[String]) {
+        self.samples.write().await.retain(|name, _| names.contains(name));
+    }
+
+    /// Samples of a container, oldest first
+    pub async fn samples(&self, name: &str) -> Vec<ContainerStats> {
+        self.samples
+            .read()
+            .await
+            .get(name)
+            .map(|buffer| buffer.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+/// Periodically samples the resource usage of a project's containers
+pub struct StatsSampler {
+    /// Name of the sampled project, for logging
+    pub project: String,
+    pub docker: Arc<DockerManager>,
+    pub history: Arc<StatsHistory>,
+    pub interval: Duration,
+}
+
+impl StatsSampler {
+    /// Starts sampling on a background task
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    async fn run(self) {
+        info!("[{}] Sampling container stats every {:?}", self.project, self.interval);
+
+        let mut interval = tokio::time::interval(self.interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            interval.tick().await;
+            match self.docker.container_names().await {
+                Ok(names) => self.history.retain(&names).await,
+                Err(e) => {
+                    warn!("[{}] Failed to list managed containers: {}", self.project, e);
+                    continue;
+                }
+            }
+            for stats in self.docker.all_container_stats().await {
+                self.history.push(stats).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    fn sample(name: &str, cpu_percent: f64) -> ContainerStats {
+        ContainerStats {
+            name: name.to_string(),
+            timestamp: Utc::now(),
+            cpu_percent,
+            memory_usage: 0,
+            memory_limit: 0,
+            memory_percent: 0.0,
+            network_rx: 0,
+            network_tx: 0,
+            block_read: 0,
+            block_write: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stats_ring_buffer() {
+        let history = StatsHistory::new(3);
+        for cpu in 1..=5 {
+            history.push(sample("web", cpu as f64)).await;
+        }
+        history.push(sample("db", 9.0)).await;
+
+        let cpu: Vec<f64> = history.samples("web").await.iter().map(|s| s.cpu_percent).collect();
+        assert_eq!(cpu, vec![3.0, 4.0, 5.0]);
+        assert_eq!(history.samples("db").await.len(), 1);
+        assert!(history.samples("cache").await.is_empty());
+
+        // Containers that are gone are dropped
+        history.retain(&["db".to_string()]).await;
+        assert!(history.samples("web").await.is_empty());
+        assert_eq!(history.samples("db").await.len(), 1);
+    }
+}
@@ -0,0 +1,253 @@
+use crate::deploy::DeployManager;
+use crate::docker::{DockerManager, HealthStatus};
+use crate::history::{HistoryStore, NewHistoryEntry};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+
+/// Periodically checks container health and restarts containers that stay unhealthy
+pub struct Supervisor {
+    /// Name of the supervised project, recorded with restarts
+    pub project: String,
+    pub docker: Arc<DockerManager>,
+    pub deploys: Arc<DeployManager>,
+    pub history: Arc<HistoryStore>,
+    pub interval: Duration,
+    /// How long a container must stay unhealthy before it is restarted
+    pub threshold: Duration,
+    /// Restarts attempted before giving up on a container until it is healthy again
+    pub max_restarts: u32,
+}
+
+impl Supervisor {
+    /// Starts supervising on a background task
+    pub fn spawn(self) -> JoinHandle<()> {
+        tokio::spawn(self.run())
+    }
+
+    async fn run(self) {
+        info!(
+            "[{}] Restarting containers unhealthy for {:?} (at most {} time(s), checked every {:?})",
+            self.project, self.threshold, self.max_restarts, self.interval
+        );
+
+        let mut tracker = Tracker::new(self.threshold, self.max_restarts);
+        loop {
+            tokio::time::sleep(self.interval).await;
+
+            // Deploys and rollbacks replace containers and watch their health themselves
+            if self.deploys.is_running() {
+                debug!("[{}] Deploy in progress, skipping health check", self.project);
+                tracker.reset_timers();
+                continue;
+            }
+
+            let infos = match self.docker.get_all_container_status().await {
+                Ok(infos) => infos,
+                Err(e) => {
+                    warn!("[{}] Health check failed: {}", self.project, e);
+                    continue;
+                }
+            };
+
+            for info in infos {
+                let health = info.health.as_ref().map(|health| health.status);
+                match tracker.observe(&info.name, health, Instant::now()) {
+                    Verdict::Wait => {}
+                    Verdict::Restart { attempt, unhealthy_for } => {
+                        let last_output = info.health.and_then(|health| health.last_output);
+                        self.restart(&info.name, attempt, unhealthy_for, last_output).await;
+                    }
+                    Verdict::GiveUp => {
+                        let message = format!(
+                            "'{}' is still unhealthy after {} restart(s), giving up until it is healthy again",
+                            info.name, self.max_restarts
+                        );
+                        error!("[{}] {}", self.project, message);
+                        self.record(&info.name, false, message, None).await;
+                    }
+                }
+            }
+        }
+    }
+
+    async fn restart(&self, name: &str, attempt: u32, unhealthy_for: Duration, last_output: Option<String>) {
+        warn!(
+            "[{}] '{}' unhealthy for {}s, restarting ({}/{})",
+            self.project,
+            name,
+            unhealthy_for.as_secs(),
+            attempt,
+            self.max_restarts
+        );
+
+        let (success, message) = match self.docker.restart_container(name).await {
+            Ok(()) => (
+                true,
+                format!(
+                    "Restarted '{}' after it was unhealthy for {}s ({}/{})",
+                    name,
+                    unhealthy_for.as_secs(),
+                    attempt,
+                    self.max_restarts
+                ),
+            ),
+            Err(e) => (false, format!("Failed to restart unhealthy '{}': {}", name, e)),
+        };
+        self.record(name, success, message, last_output).await;
+    }
+
+    async fn record(&self, name: &str, success: bool, message: String, output: Option<String>) {
+        self.history
+            .record(NewHistoryEntry {
+                actor: "supervisor".to_string(),
+                project: Some(self.project.clone()),
+                action: "docker.auto-restart".to_string(),
+                target: Some(name.to_string()),
+                commit: None,
+                success,
+                message: Some(message),
+                output,
+            })
+            .await;
+    }
+}
+
+/// What the supervisor should do about a container after a health check
+#[derive(Debug, PartialEq)]
+enum Verdict {
+    Wait,
+    Restart { attempt: u32, unhealthy_for: Duration },
+    /// The restart cap was reached; reported once
+    GiveUp,
+}
+
+#[derive(Debug, Default)]
+struct Watch {
+    unhealthy_since: Option<Instant>,
+    restarts: u32,
+    gave_up: bool,
+}
+
+/// Tracks how long each container has been unhealthy and how often it was restarted
+struct Tracker {
+    threshold: Duration,
+    max_restarts: u32,
+    watches: HashMap<String, Watch>,
+}
+
+impl Tracker {
+    fn new(threshold: Duration, max_restarts: u32) -> Self {
+        Self {
+            threshold,
+            max_restarts,
+            watches: HashMap::new(),
+        }
+    }
+
+    fn observe(&mut self, name: &str, health: Option<HealthStatus>, now: Instant) -> Verdict {
+        match health {
+            // Only a healthy container earns back its restarts
+            Some(HealthStatus::Healthy) => {
+                self.watches.remove(name);
+                Verdict::Wait
+            }
+            Some(HealthStatus::Unhealthy) => {
+                let watch = self.watches.entry(name.to_string()).or_default();
+                let unhealthy_for = now.duration_since(*watch.unhealthy_since.get_or_insert(now));
+                if unhealthy_for < self.threshold {
+                    Verdict::Wait
+                } else if watch.restarts < self.max_restarts {
+                    watch.restarts += 1;
+                    watch.unhealthy_since = None;
+                    Verdict::Restart {
+                        attempt: watch.restarts,
+                        unhealthy_for,
+                    }
+                } else if !watch.gave_up {
+                    watch.gave_up = true;
+                    Verdict::GiveUp
+                } else {
+                    Verdict::Wait
+                }
+            }
+            Some(HealthStatus::Starting) | None => {
+                if let Some(watch) = self.watches.get_mut(name) {
+                    watch.unhealthy_since = None;
+                }
+                Verdict::Wait
+            }
+        }
+    }
+
+    /// Forgets when containers became unhealthy, keeping their restart counts
+    fn reset_timers(&mut self) {
+        for watch in self.watches.values_mut() {
+            watch.unhealthy_since = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_tracker() {
+        let threshold = Duration::from_secs(60);
+        let mut tracker = Tracker::new(threshold, 2);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+        let unhealthy = Some(HealthStatus::Unhealthy);
+
+        assert_eq!(tracker.observe("web", unhealthy, at(0)), Verdict::Wait);
+        assert_eq!(tracker.observe("web", unhealthy, at(30)), Verdict::Wait);
+        assert_eq!(
+            tracker.observe("web", unhealthy, at(60)),
+            Verdict::Restart {
+                attempt: 1,
+                unhealthy_for: threshold
+            }
+        );
+
+        // The threshold starts over after a restart and while the healthcheck is starting
+        assert_eq!(tracker.observe("web", Some(HealthStatus::Starting), at(70)), Verdict::Wait);
+        assert_eq!(tracker.observe("web", unhealthy, at(80)), Verdict::Wait);
+        assert_eq!(tracker.observe("web", unhealthy, at(120)), Verdict::Wait);
+        assert!(matches!(
+            tracker.observe("web", unhealthy, at(140)),
+            Verdict::Restart { attempt: 2, .. }
+        ));
+
+        // Capped: give up once, then leave the container alone
+        assert_eq!(tracker.observe("web", unhealthy, at(150)), Verdict::Wait);
+        assert_eq!(tracker.observe("web", unhealthy, at(210)), Verdict::GiveUp);
+        assert_eq!(tracker.observe("web", unhealthy, at(400)), Verdict::Wait);
+
+        // Other containers are tracked separately
+        assert_eq!(tracker.observe("db", unhealthy, at(400)), Verdict::Wait);
+        assert!(matches!(
+            tracker.observe("db", unhealthy, at(460)),
+            Verdict::Restart { attempt: 1, .. }
+        ));
+
+        // Becoming healthy resets the cap
+        assert_eq!(tracker.observe("web", Some(HealthStatus::Healthy), at(500)), Verdict::Wait);
+        assert_eq!(tracker.observe("web", unhealthy, at(510)), Verdict::Wait);
+        assert!(matches!(
+            tracker.observe("web", unhealthy, at(570)),
+            Verdict::Restart { attempt: 1, .. }
+        ));
+
+        // A deploy restarts the clock but not the count
+        assert_eq!(tracker.observe("db", unhealthy, at(600)), Verdict::Wait);
+        tracker.reset_timers();
+        assert_eq!(tracker.observe("db", unhealthy, at(650)), Verdict::Wait);
+        assert!(matches!(
+            tracker.observe("db", unhealthy, at(710)),
+            Verdict::Restart { attempt: 2, .. }
+        ));
+    }
+}
@@ -0,0 +1,138 @@
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+/// Number of delivery IDs remembered for duplicate detection
+const MAX_REMEMBERED_DELIVERIES: usize = 1000;
+
+/// The parts of a GitHub push event payload we care about
+#[derive(Debug, Clone, Deserialize)]
+pub struct PushEvent {
+    /// Full ref that was pushed, e.g. "refs/heads/main"
+    #[serde(rename = "ref")]
+    pub git_ref: String,
+    /// Commit the ref points to after the push
+    #[serde(default)]
+    pub after: Option<String>,
+    /// Whether the push deleted the ref
+    #[serde(default)]
+    pub deleted: bool,
+    #[serde(default)]
+    pub repository: Option<PushRepository>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PushRepository {
+    pub full_name: String,
+}
+
+impl PushEvent {
+    /// Whether the push updated `branch` (as opposed to another branch or a tag)
+    pub fn is_branch(&self, branch: &str) -> bool {
+        self.git_ref.strip_prefix("refs/heads/") == Some(branch)
+    }
+}
+
+/// Verifies an `X-Hub-Signature-256` header ("sha256=<hex>") against the request body
+pub fn verify_signature(secret: &str, body: &[u8], signature_header: &str) -> bool {
+    let Some(signature) = signature_header.trim().strip_prefix("sha256=") else {
+        return false;
+    };
+    let Ok(signature) = hex::decode(signature) else {
+        return false;
+    };
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    // Constant-time comparison
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// Remembers recent delivery IDs so that redelivered webhooks are ignored
+#[derive(Default)]
+pub struct DeliveryLog {
+    deliveries: Mutex<(VecDeque<String>, HashSet<String>)>,
+}
+
+impl DeliveryLog {
+    /// Records a delivery ID, returning `false` if it was already seen
+    pub fn insert(&self, delivery_id: &str) -> bool {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let (order, seen) = &mut *deliveries;
+
+        if !seen.insert(delivery_id.to_string()) {
+            return false;
+        }
+        order.push_back(delivery_id.to_string());
+
+        while order.len() > MAX_REMEMBERED_DELIVERIES {
+            if let Some(oldest) = order.pop_front() {
+                seen.remove(&oldest);
+            }
+        }
+
+        true
+    }
+
+    /// Forgets a delivery ID, so that a redelivery is processed again
+    pub fn remove(&self, delivery_id: &str) {
+        let mut deliveries = self.deliveries.lock().unwrap();
+        let (order, seen) = &mut *deliveries;
+
+        if seen.remove(delivery_id) {
+            order.retain(|id| id != delivery_id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    #[test]
+    fn test_verify_signature() {
+        let body = br#"{"ref":"refs/heads/main","after":"abc123"}"#;
+        let signature = sign("s3cret", body);
+
+        assert!(verify_signature("s3cret", body, &signature));
+        assert!(!verify_signature("wrong", body, &signature));
+        assert!(!verify_signature("s3cret", b"tampered", &signature));
+        assert!(!verify_signature("s3cret", body, "sha1=deadbeef"));
+        assert!(!verify_signature("s3cret", body, "sha256=not-hex"));
+
+        // Known vector from GitHub's webhook documentation
+        assert!(verify_signature(
+            "It's a Secret to Everybody",
+            b"Hello, World!",
+            "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17"
+        ));
+    }
+
+    #[test]
+    fn test_push_event_branch_and_deliveries() {
+        let event: PushEvent =
+            serde_json::from_str(r#"{"ref":"refs/heads/main","after":"abc123","repository":{"full_name":"o/r"}}"#)
+                .unwrap();
+        assert!(event.is_branch("main"));
+        assert!(!event.is_branch("develop"));
+
+        let tag: PushEvent = serde_json::from_str(r#"{"ref":"refs/tags/main"}"#).unwrap();
+        assert!(!tag.is_branch("main"));
+
+        let log = DeliveryLog::default();
+        assert!(log.insert("delivery-1"));
+        assert!(!log.insert("delivery-1"));
+        assert!(log.insert("delivery-2"));
+
+        log.remove("delivery-1");
+        assert!(log.insert("delivery-1"));
+    }
+}